@@ -0,0 +1,108 @@
+// Reproduces the per-worker scratch buffers' gain: the same depth-first
+// search over the steno's plies, once allocating a fresh move list and path
+// at every node as the search used to, once reusing one path and a move list
+// per depth as it does now, and then the solver itself on one thread.
+//
+//     cargo bench --bench make_unmake
+//
+// Prints a tab-separated table like `steno_solver bench`.
+
+use chess::{Board, ChessMove, MoveGen};
+use std::time::{Duration, Instant};
+use steno_solver::{parse_steno_string, solve_with_callback, Constraint, MoveContext, SolveOptions, BENCH_SUITE};
+
+// How many times each search is run, the fastest taken.
+const RUNS: usize = 5;
+
+#[derive(Default)]
+struct Counts {
+    nodes: u64,
+    solutions: u64,
+}
+
+fn satisfies(steno_constraints: &[Constraint], depth: usize, before: &Board, mov: ChessMove, after: &Board) -> bool {
+    steno_constraints[depth].matches(&MoveContext::new(before, mov, after))
+}
+
+// Copy-make with a move list and a path allocated per node.
+fn allocating(steno_constraints: &[Constraint], board: Board, path: Vec<ChessMove>, counts: &mut Counts) {
+    counts.nodes += 1;
+    if path.len() == steno_constraints.len() {
+        counts.solutions += 1;
+        return;
+    }
+    let moves: Vec<ChessMove> = MoveGen::new_legal(&board).collect();
+    for &mov in &moves {
+        let after = board.make_move_new(mov);
+        if satisfies(steno_constraints, path.len(), &board, mov, &after) {
+            let mut new_path = path.to_vec();
+            new_path.push(mov);
+            allocating(steno_constraints, after, new_path, counts);
+        }
+    }
+}
+
+// Copy-make into one path, pushed and popped, and a move list per depth,
+// this one's first.
+fn reusing(steno_constraints: &[Constraint], board: Board, path: &mut Vec<ChessMove>, moves: &mut [Vec<ChessMove>], counts: &mut Counts) {
+    counts.nodes += 1;
+    let depth = path.len();
+    if depth == steno_constraints.len() {
+        counts.solutions += 1;
+        return;
+    }
+    let (here, deeper) = moves.split_first_mut().unwrap();
+    here.clear();
+    here.extend(MoveGen::new_legal(&board));
+    for &mov in here.iter() {
+        let after = board.make_move_new(mov);
+        if satisfies(steno_constraints, depth, &board, mov, &after) {
+            path.push(mov);
+            reusing(steno_constraints, after, path, deeper, counts);
+            path.pop();
+        }
+    }
+}
+
+fn fastest(mut run: impl FnMut() -> Counts) -> (Counts, Duration) {
+    let mut best = None;
+    for _ in 0..RUNS {
+        let started = Instant::now();
+        let counts = run();
+        let elapsed = started.elapsed();
+        if best.as_ref().is_none_or(|(_, fastest)| elapsed < *fastest) {
+            best = Some((counts, elapsed));
+        }
+    }
+    best.unwrap()
+}
+
+fn main() {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    let options = SolveOptions { print_solutions: false, ..SolveOptions::default() };
+
+    println!("steno\tnodes\tsolutions\tallocating_ms\treusing_ms\tsolver_ms");
+    for &steno in BENCH_SUITE {
+        let steno_constraints = parse_steno_string(steno).unwrap();
+        let (allocated, allocating_time) = fastest(|| {
+            let mut counts = Counts::default();
+            allocating(&steno_constraints, Board::default(), Vec::new(), &mut counts);
+            counts
+        });
+        let (reused, reusing_time) = fastest(|| {
+            let mut counts = Counts::default();
+            let mut moves = vec![Vec::new(); steno_constraints.len() + 1];
+            reusing(&steno_constraints, Board::default(), &mut Vec::with_capacity(steno_constraints.len()), &mut moves, &mut counts);
+            counts
+        });
+        assert_eq!((allocated.nodes, allocated.solutions), (reused.nodes, reused.solutions), "{}", steno);
+        let (solved, solver_time) = fastest(|| {
+            let stats = pool.install(|| solve_with_callback(Board::default(), &steno_constraints, &options, &|_| {}));
+            Counts { nodes: stats.nodes_visited, solutions: stats.solutions }
+        });
+        assert_eq!(solved.solutions, reused.solutions, "{}", steno);
+
+        let ms = |elapsed: Duration| elapsed.as_secs_f64() * 1000.0;
+        println!("{}\t{}\t{}\t{:.3}\t{:.3}\t{:.3}", steno, reused.nodes, reused.solutions, ms(allocating_time), ms(reusing_time), ms(solver_time));
+    }
+}
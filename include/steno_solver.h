@@ -0,0 +1,46 @@
+#ifndef STENO_SOLVER_H
+#define STENO_SOLVER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define STENO_FORMAT_URL 0
+#define STENO_FORMAT_SAN 1
+#define STENO_FORMAT_UCI 2
+#define STENO_FORMAT_PGN 3
+
+#define STENO_ERR_INVALID_ARGUMENT -1
+#define STENO_ERR_INVALID_STENO -2
+#define STENO_ERR_INVALID_FEN -3
+
+typedef struct steno_options {
+    /* Starting position, or NULL for the initial position. */
+    const char* fen;
+    /* One of the STENO_FORMAT_* values. */
+    int format;
+    /* Stop after this many solutions, or 0 for no limit. */
+    uint64_t limit;
+} steno_options;
+
+/*
+ * Called once per solution. `solution` is only valid for the duration of the
+ * call. Return 0 to stop the search, anything else to continue. Calls may
+ * come from the solver's worker threads, but never concurrently.
+ */
+typedef int (*steno_solution_callback)(const char* solution, void* userdata);
+
+/*
+ * Solves `steno` from the position in `options` (which may be NULL for the
+ * defaults). Returns the number of solutions reported to `callback`, or a
+ * negative STENO_ERR_* code.
+ */
+int64_t steno_solve(const char* steno, const steno_options* options, steno_solution_callback callback, void* userdata);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
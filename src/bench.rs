@@ -0,0 +1,36 @@
+use chess::Board;
+
+use crate::search::{solve, SolveOptions};
+use crate::stats::SearchStats;
+use crate::steno::parse_steno_string;
+
+// A spread of shapes: unconstrained perft-like searches, sparse stenos that
+// prune hard early, and stenos whose pruning only kicks in at the last plies.
+pub const BENCH_SUITE: &[&str] = &[
+    "~~N+",
+    "NNNNx",
+    "~~~~",
+    "e~d~Lx",
+    "PPN~Qx#",
+    "~~~~x+",
+    "PPPPQx+",
+    "~~~~~",
+];
+
+pub struct BenchResult {
+    pub steno: String,
+    pub stats: SearchStats,
+}
+
+pub fn run_bench() -> Vec<BenchResult> {
+    let options = SolveOptions {
+        print_solutions: false,
+        ..SolveOptions::default()
+    };
+
+    BENCH_SUITE.iter().map(|&steno| {
+        let steno_constraints = parse_steno_string(steno).unwrap();
+        let stats = solve(Board::default(), None, &steno_constraints, &options);
+        BenchResult { steno: steno.to_string(), stats }
+    }).collect()
+}
@@ -0,0 +1,120 @@
+use chess::ChessMove;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+const HEADER: &str = "steno-solver cache 1";
+
+// Past this many solutions an entry keeps only the count, which still
+// answers a run that prints nothing but its exit code.
+pub const MAX_CACHED_SOLUTIONS: u64 = 100_000;
+
+// What an earlier solve found, given the games the search reported before any
+// deduplication, limit or filter, so those can be applied again on replay.
+#[derive(Clone, Debug, Default)]
+pub struct CachedResult {
+    pub solutions: u64,
+    pub nodes_visited: u64,
+    pub elapsed: Duration,
+    // None when there were more than MAX_CACHED_SOLUTIONS.
+    pub games: Option<Vec<Vec<ChessMove>>>,
+}
+
+// $STENO_SOLVER_CACHE_DIR, else $XDG_CACHE_HOME/steno-solver, else
+// ~/.cache/steno-solver.
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("STENO_SOLVER_CACHE_DIR") {
+        return Some(PathBuf::from(path));
+    }
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_home.join("steno-solver"))
+}
+
+// One file per query, named by a hash of it. The query is a line describing
+// everything that decides which games solve it, and is kept in the file so a
+// hash collision reads as a miss.
+#[derive(Clone, Debug)]
+pub struct ResultCache {
+    dir: PathBuf,
+}
+
+impl ResultCache {
+    pub fn new(dir: &Path) -> ResultCache {
+        ResultCache { dir: dir.to_path_buf() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry_path(&self, query: &str) -> PathBuf {
+        let hash = query.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+        self.dir.join(format!("{:016x}.txt", hash))
+    }
+
+    // A missing, unreadable or outdated entry is a miss.
+    pub fn get(&self, query: &str) -> Option<CachedResult> {
+        let contents = fs::read_to_string(self.entry_path(query)).ok()?;
+        let mut lines = contents.lines();
+        if lines.next()? != HEADER || lines.next()?.strip_prefix("query ")? != query {
+            return None;
+        }
+        let mut field = |name: &str| lines.next()?.strip_prefix(name)?.strip_prefix(' ')?.parse::<u64>().ok();
+        let (solutions, nodes_visited, elapsed) = (field("solutions")?, field("nodes")?, field("elapsed_us")?);
+        let games = match lines.next()? {
+            "games" => Some(lines.map(|line| line.split_whitespace().map(ChessMove::from_str).collect::<Result<Vec<_>, _>>().ok()).collect::<Option<Vec<_>>>()?),
+            _ => None,
+        };
+        Some(CachedResult { solutions, nodes_visited, elapsed: Duration::from_micros(elapsed), games })
+    }
+
+    // Written to a temporary file first, so a reader never sees half an entry.
+    pub fn put(&self, query: &str, result: &CachedResult) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut contents = format!(
+            "{}\nquery {}\nsolutions {}\nnodes {}\nelapsed_us {}\n",
+            HEADER,
+            query,
+            result.solutions,
+            result.nodes_visited,
+            result.elapsed.as_micros()
+        );
+        match &result.games {
+            Some(games) => {
+                contents.push_str("games\n");
+                for game in games {
+                    contents.push_str(&game.iter().map(|mov| mov.to_string()).collect::<Vec<_>>().join(" "));
+                    contents.push('\n');
+                }
+            }
+            None => contents.push_str("count only\n"),
+        }
+        let path = self.entry_path(query);
+        let partial = path.with_extension("partial");
+        fs::write(&partial, contents)?;
+        fs::rename(&partial, &path)
+    }
+
+    // The number of entries removed; an empty or missing cache is fine.
+    pub fn clear(&self) -> io::Result<usize> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "txt" || extension == "partial") {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
@@ -0,0 +1,124 @@
+use chess::Board;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::render::{render_solution, OutputFormat};
+use crate::search::{solve_with_callback, SolveOptions};
+use crate::steno::{halfmove_clock, parse_steno_string};
+
+// Keep in sync with include/steno_solver.h. A build without the san feature
+// writes every format as UCI.
+pub const STENO_FORMAT_URL: c_int = 0;
+pub const STENO_FORMAT_SAN: c_int = 1;
+pub const STENO_FORMAT_UCI: c_int = 2;
+pub const STENO_FORMAT_PGN: c_int = 3;
+
+pub const STENO_ERR_INVALID_ARGUMENT: i64 = -1;
+pub const STENO_ERR_INVALID_STENO: i64 = -2;
+pub const STENO_ERR_INVALID_FEN: i64 = -3;
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+pub struct steno_options {
+    pub fen: *const c_char,
+    pub format: c_int,
+    pub limit: u64,
+}
+
+#[allow(non_camel_case_types)]
+pub type steno_solution_callback = Option<unsafe extern "C" fn(solution: *const c_char, userdata: *mut c_void) -> c_int>;
+
+struct Callback {
+    callback: unsafe extern "C" fn(*const c_char, *mut c_void) -> c_int,
+    userdata: *mut c_void,
+}
+
+// SAFETY: the caller of `steno_solve` promises `userdata` may be used from
+// other threads, and calls are serialized through a mutex so the callback
+// never runs concurrently with itself.
+unsafe impl Send for Callback {}
+
+/// Solves `steno` and calls `callback(solution, userdata)` for every solution
+/// found, where `solution` is a NUL-terminated string only valid for the
+/// duration of the call. Returning 0 from the callback stops the search.
+///
+/// Returns the number of solutions reported, or a negative `STENO_ERR_*` code.
+///
+/// # Safety
+///
+/// `steno` must be a valid NUL-terminated string. `options` may be NULL;
+/// otherwise it must point to a valid `steno_options` whose `fen` is NULL or
+/// a valid NUL-terminated string. The callback may be invoked from worker
+/// threads (one at a time), so `userdata` must be safe to use from them.
+#[no_mangle]
+pub unsafe extern "C" fn steno_solve(steno: *const c_char, options: *const steno_options, callback: steno_solution_callback, userdata: *mut c_void) -> i64 {
+    let Some(callback) = callback else {
+        return STENO_ERR_INVALID_ARGUMENT;
+    };
+    if steno.is_null() {
+        return STENO_ERR_INVALID_ARGUMENT;
+    }
+    let Ok(steno) = CStr::from_ptr(steno).to_str() else {
+        return STENO_ERR_INVALID_STENO;
+    };
+    let Ok(steno_constraints) = parse_steno_string(steno) else {
+        return STENO_ERR_INVALID_STENO;
+    };
+
+    let (fen, format, limit) = match options.as_ref() {
+        Some(options) => {
+            let fen = if options.fen.is_null() {
+                None
+            } else {
+                match CStr::from_ptr(options.fen).to_str() {
+                    Ok(fen) => Some(fen.to_string()),
+                    Err(_) => return STENO_ERR_INVALID_FEN,
+                }
+            };
+            let format = match options.format {
+                STENO_FORMAT_URL => OutputFormat::Url,
+                STENO_FORMAT_SAN => OutputFormat::San,
+                STENO_FORMAT_UCI => OutputFormat::Uci,
+                STENO_FORMAT_PGN => OutputFormat::Pgn,
+                _ => return STENO_ERR_INVALID_ARGUMENT,
+            };
+            (fen, format, (options.limit > 0).then_some(options.limit))
+        }
+        None => (None, OutputFormat::default(), None),
+    };
+
+    let board = match &fen {
+        Some(fen) => match Board::from_str(fen) {
+            Ok(board) => board,
+            Err(_) => return STENO_ERR_INVALID_FEN,
+        },
+        None => Board::default(),
+    };
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let reported = AtomicU64::new(0);
+    let callback = Mutex::new(Callback { callback, userdata });
+    let solve_options = SolveOptions {
+        print_solutions: false,
+        cancel: Some(cancel.clone()),
+        halfmove_clock: halfmove_clock(&fen),
+        ..SolveOptions::default()
+    };
+
+    solve_with_callback(board, &steno_constraints, &solve_options, &|path| {
+        let solution = CString::new(render_solution(&fen, path, format)).unwrap();
+        let callback = callback.lock().unwrap();
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let keep_going = (callback.callback)(solution.as_ptr(), callback.userdata) != 0;
+        let reported = reported.fetch_add(1, Ordering::Relaxed) + 1;
+        if !keep_going || limit.is_some_and(|limit| reported >= limit) {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    });
+
+    reported.load(Ordering::Relaxed) as i64
+}
@@ -0,0 +1,32 @@
+use serde_json::Value;
+
+use crate::pgn::{parse_pgn, PgnGame};
+
+fn get_json(url: &str) -> Result<Value, String> {
+    let response = ureq::get(url).call().map_err(|err| format!("{}: {}", url, err))?;
+    response.into_string().map_err(|err| err.to_string()).and_then(|body| serde_json::from_str(&body).map_err(|err| err.to_string()))
+}
+
+// Chess.com's public API has no lookup by game id, only monthly archives per
+// player, so the game's own page data is used to find which archive holds it.
+pub fn fetch_chesscom_game(game_url: &str) -> Result<PgnGame, String> {
+    let id = game_url.trim_end_matches('/').rsplit('/').next().filter(|id| !id.is_empty() && id.chars().all(|ch| ch.is_ascii_digit()))
+        .ok_or_else(|| format!("Not a Chess.com game URL: {}", game_url))?;
+    let kind = if game_url.contains("daily") { "daily" } else { "live" };
+
+    let callback = get_json(&format!("https://www.chess.com/callback/{}/game/{}", kind, id))?;
+    let headers = &callback["game"]["pgnHeaders"];
+    let white = headers["White"].as_str().ok_or("Chess.com game has no White player")?;
+    let date = headers["Date"].as_str().ok_or("Chess.com game has no date")?;
+    let mut date_parts = date.split('.');
+    let (Some(year), Some(month)) = (date_parts.next(), date_parts.next()) else {
+        return Err(format!("Unexpected Chess.com game date: {}", date));
+    };
+
+    let archive = get_json(&format!("https://api.chess.com/pub/player/{}/games/{}/{}", white.to_lowercase(), year, month))?;
+    let pgn = archive["games"].as_array()
+        .and_then(|games| games.iter().find(|game| game["url"].as_str().is_some_and(|url| url.ends_with(&format!("/{}", id)))))
+        .and_then(|game| game["pgn"].as_str())
+        .ok_or_else(|| format!("Game {} not found in {}'s {}/{} archive", id, white, year, month))?;
+    parse_pgn(pgn)
+}
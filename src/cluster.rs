@@ -0,0 +1,374 @@
+use chess::{Board, ChessMove};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::{Method, Request, Server};
+use tracing::{info, warn};
+
+use crate::metrics::{respond_metrics, Metrics};
+use crate::search::{solve_after_prefix, solve_with_callback, SolveOptions, SHARD_PLY};
+use crate::server::respond_json;
+use crate::steno::{halfmove_clock, parse_steno_string, Constraint};
+
+// How long an idle worker waits before asking for work again.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct CoordinatorConfig {
+    pub addr: String,
+    pub steno: String,
+    pub fen: Option<String>,
+    // Tasks are the games' first `task_ply` moves; workers search the rest.
+    pub task_ply: usize,
+    // A task not reported back in this long is handed to another worker.
+    pub lease: Duration,
+}
+
+impl Default for CoordinatorConfig {
+    fn default() -> Self {
+        CoordinatorConfig {
+            addr: "127.0.0.1:8080".to_string(),
+            steno: String::new(),
+            fen: None,
+            task_ply: SHARD_PLY,
+            lease: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum TaskState {
+    Pending,
+    Leased { worker: String, until: Instant },
+    Done,
+}
+
+struct ClusterTask {
+    prefix: Vec<ChessMove>,
+    state: TaskState,
+}
+
+// What a cluster search found, once every task has been reported.
+pub struct ClusterResult {
+    pub solutions: Vec<Vec<ChessMove>>,
+    pub tasks: usize,
+    pub nodes_visited: u64,
+    pub reassigned: u64,
+    pub elapsed: Duration,
+}
+
+struct Coordinator {
+    steno: String,
+    fen: Option<String>,
+    task_ply: usize,
+    lease: Duration,
+    tasks: Mutex<Vec<ClusterTask>>,
+    solutions: Mutex<Vec<Vec<ChessMove>>>,
+    metrics: Metrics,
+    reassigned: AtomicU64,
+}
+
+#[derive(Deserialize)]
+struct ClaimRequest {
+    worker: String,
+}
+
+#[derive(Deserialize)]
+struct CompleteRequest {
+    worker: String,
+    // Whole games, as space-separated UCI moves.
+    solutions: Vec<String>,
+    nodes_visited: u64,
+}
+
+impl Coordinator {
+    fn is_finished(&self) -> bool {
+        self.tasks.lock().unwrap().iter().all(|task| task.state == TaskState::Done)
+    }
+
+    fn claim(&self, worker: &str) -> Value {
+        let mut tasks = self.tasks.lock().unwrap();
+        let now = Instant::now();
+        for (id, task) in tasks.iter_mut().enumerate() {
+            if let TaskState::Leased { worker, until } = &task.state {
+                if *until < now {
+                    warn!(task = id, worker = %worker, "lease expired, reassigning");
+                    task.state = TaskState::Pending;
+                    self.reassigned.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        let Some((id, task)) = tasks.iter_mut().enumerate().find(|(_, task)| task.state == TaskState::Pending) else {
+            return if tasks.iter().all(|task| task.state == TaskState::Done) { json!({ "done": true }) } else { json!({ "wait": true }) };
+        };
+        task.state = TaskState::Leased { worker: worker.to_string(), until: now + self.lease };
+        json!({
+            "task": {
+                "id": id,
+                "steno": self.steno,
+                "fen": self.fen,
+                "task_ply": self.task_ply,
+                "prefix": task.prefix.iter().map(|mov| mov.to_string()).collect::<Vec<_>>(),
+            }
+        })
+    }
+
+    // Results for a task that is already done, e.g. from a worker whose lease
+    // ran out, are dropped so no solution is counted twice.
+    fn complete(&self, id: usize, report: CompleteRequest) -> Result<Value, (u16, String)> {
+        let mut solutions = Vec::new();
+        for solution in &report.solutions {
+            let path = solution.split_whitespace().map(ChessMove::from_str).collect::<Result<Vec<ChessMove>, _>>()
+                .map_err(|err| (400, format!("Invalid solution {}: {}", solution, err)))?;
+            solutions.push(path);
+        }
+
+        let mut tasks = self.tasks.lock().unwrap();
+        let (done, total) = (tasks.iter().filter(|task| task.state == TaskState::Done).count(), tasks.len());
+        let task = tasks.get_mut(id).ok_or_else(|| (404, format!("No such task: {}", id)))?;
+        if task.state == TaskState::Done {
+            return Ok(json!({ "accepted": false }));
+        }
+        if let Some(path) = solutions.iter().find(|path| !path.starts_with(&task.prefix)) {
+            return Err((400, format!("Solution {:?} isn't in task {}", path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>(), id)));
+        }
+        task.state = TaskState::Done;
+        drop(tasks);
+
+        info!(task = id, worker = %report.worker, solutions = solutions.len(), done = done + 1, total, "task done");
+        self.metrics.nodes_visited.fetch_add(report.nodes_visited, Ordering::Relaxed);
+        self.metrics.solutions.fetch_add(solutions.len() as u64, Ordering::Relaxed);
+        self.solutions.lock().unwrap().extend(solutions);
+        Ok(json!({ "accepted": true }))
+    }
+
+    fn fail(&self, id: usize, worker: &str) -> Result<Value, (u16, String)> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let task = tasks.get_mut(id).ok_or_else(|| (404, format!("No such task: {}", id)))?;
+        if matches!(&task.state, TaskState::Leased { worker: leased_to, .. } if leased_to == worker) {
+            warn!(task = id, worker = %worker, "task failed, reassigning");
+            task.state = TaskState::Pending;
+            self.reassigned.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(json!({ "accepted": true }))
+    }
+
+    fn metrics(&self) -> String {
+        let tasks = self.tasks.lock().unwrap();
+        let leased = tasks.iter().filter(|task| matches!(task.state, TaskState::Leased { .. })).count();
+        let pending = tasks.iter().filter(|task| task.state == TaskState::Pending).count();
+        drop(tasks);
+        self.metrics.render(leased, pending)
+    }
+
+    fn status(&self) -> Value {
+        let tasks = self.tasks.lock().unwrap();
+        let count = |test: fn(&TaskState) -> bool| tasks.iter().filter(|task| test(&task.state)).count();
+        json!({
+            "steno": self.steno,
+            "tasks": tasks.len(),
+            "pending": count(|state| *state == TaskState::Pending),
+            "leased": count(|state| matches!(state, TaskState::Leased { .. })),
+            "done": count(|state| *state == TaskState::Done),
+            "solutions_found": self.solutions.lock().unwrap().len(),
+            "nodes_visited": self.metrics.nodes_visited.load(Ordering::Relaxed),
+            "reassigned": self.reassigned.load(Ordering::Relaxed),
+        })
+    }
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(request: &mut Request) -> Result<T, (u16, String)> {
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body).map_err(|err| (400, err.to_string()))?;
+    serde_json::from_str(&body).map_err(|err| (400, format!("Invalid request body: {}", err)))
+}
+
+fn handle_cluster_request(coordinator: &Coordinator, mut request: Request) {
+    let method = request.method().clone();
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let task_id = |id: &str| id.parse::<usize>().map_err(|_| (404, format!("No such task: {}", id)));
+
+    let result = match (&method, segments.as_slice()) {
+        (Method::Post, ["tasks", "claim"]) => read_json::<ClaimRequest>(&mut request).map(|claim| (200, coordinator.claim(&claim.worker))),
+        (Method::Post, ["tasks", id, "complete"]) => task_id(id)
+            .and_then(|id| Ok((id, read_json::<CompleteRequest>(&mut request)?)))
+            .and_then(|(id, report)| coordinator.complete(id, report))
+            .map(|body| (200, body)),
+        (Method::Post, ["tasks", id, "fail"]) => task_id(id)
+            .and_then(|id| Ok((id, read_json::<ClaimRequest>(&mut request)?)))
+            .and_then(|(id, claim)| coordinator.fail(id, &claim.worker))
+            .map(|body| (200, body)),
+        (Method::Get, ["status"]) => Ok((200, coordinator.status())),
+        (Method::Get, ["metrics"]) => {
+            respond_metrics(request, coordinator.metrics());
+            return;
+        }
+        _ => Err((404, format!("No route for {} {}", method, path))),
+    };
+    respond_json(request, result);
+}
+
+// Splits the steno's games into tasks by their first moves, hands them out
+// to workers over HTTP, and returns once every task is reported. Workers
+// that fail or go quiet past their lease have their tasks passed on.
+pub fn coordinate(config: &CoordinatorConfig) -> Result<ClusterResult, String> {
+    let started = Instant::now();
+    let steno_constraints = parse_steno_string(&config.steno)?;
+    let board = match &config.fen {
+        Some(fen) => Board::from_str(fen).map_err(|err| format!("Invalid FEN: {}", err))?,
+        None => Board::default(),
+    };
+    let task_ply = config.task_ply.min(steno_constraints.len());
+
+    let prefixes = Mutex::new(Vec::new());
+    let options = SolveOptions {
+        print_solutions: false,
+        halfmove_clock: halfmove_clock(&config.fen),
+        ..SolveOptions::default()
+    };
+    solve_with_callback(board, &steno_constraints[..task_ply], &options, &|path| prefixes.lock().unwrap().push(path.to_vec()));
+    let mut prefixes = prefixes.into_inner().unwrap();
+    prefixes.sort_by_key(|path| path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>());
+
+    let coordinator = Coordinator {
+        steno: config.steno.clone(),
+        fen: config.fen.clone(),
+        task_ply,
+        lease: config.lease,
+        tasks: Mutex::new(prefixes.into_iter().map(|prefix| ClusterTask { prefix, state: TaskState::Pending }).collect()),
+        solutions: Mutex::new(Vec::new()),
+        metrics: Metrics::new(),
+        reassigned: AtomicU64::new(0),
+    };
+    let tasks = coordinator.tasks.lock().unwrap().len();
+
+    let server = Server::http(&config.addr).map_err(|err| err.to_string())?;
+    eprintln!("Coordinating {} tasks on http://{}", tasks, config.addr);
+
+    // Workers asking after the last report are still told the search is
+    // done, for a couple of their polls.
+    let mut finished_at: Option<Instant> = None;
+    thread::scope(|scope| loop {
+        if coordinator.is_finished() {
+            let finished_at = *finished_at.get_or_insert_with(Instant::now);
+            if finished_at.elapsed() > 2 * POLL_INTERVAL {
+                return Ok::<(), String>(());
+            }
+        }
+        match server.recv_timeout(Duration::from_millis(100)) {
+            Ok(Some(request)) => {
+                let coordinator = &coordinator;
+                scope.spawn(move || handle_cluster_request(coordinator, request));
+            }
+            Ok(None) => {}
+            Err(err) => return Err(err.to_string()),
+        }
+    })?;
+
+    Ok(ClusterResult {
+        solutions: coordinator.solutions.into_inner().unwrap(),
+        tasks,
+        nodes_visited: coordinator.metrics.nodes_visited.load(Ordering::Relaxed),
+        reassigned: coordinator.reassigned.into_inner(),
+        elapsed: started.elapsed(),
+    })
+}
+
+// A bare HTTP/1.1 POST, enough to talk to the coordinator.
+fn post_json(addr: &str, path: &str, body: &Value) -> Result<Value, String> {
+    let exchange = || -> io::Result<String> {
+        let mut stream = TcpStream::connect(addr)?;
+        let body = body.to_string();
+        write!(stream, "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", path, addr, body.len(), body)?;
+        stream.flush()?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    };
+    let response = exchange().map_err(|err| format!("Could not reach the coordinator at {}: {}", addr, err))?;
+
+    let (head, body) = response.split_once("\r\n\r\n").ok_or("Malformed response from the coordinator")?;
+    let status = head.split_whitespace().nth(1).and_then(|status| status.parse::<u16>().ok()).ok_or("Malformed response from the coordinator")?;
+    let body: Value = serde_json::from_str(body).map_err(|err| format!("Malformed response from the coordinator: {}", err))?;
+    if status != 200 {
+        return Err(format!("The coordinator refused: {}", body["error"].as_str().unwrap_or("unknown error")));
+    }
+    Ok(body)
+}
+
+#[derive(Deserialize)]
+struct ClaimedTask {
+    id: usize,
+    steno: String,
+    fen: Option<String>,
+    task_ply: usize,
+    prefix: Vec<String>,
+}
+
+fn search_task(task: &ClaimedTask) -> Result<(Vec<String>, u64), String> {
+    let steno_constraints: Vec<Constraint> = parse_steno_string(&task.steno)?;
+    let prefix = task.prefix.iter().map(|mov| ChessMove::from_str(mov).map_err(|err| format!("Invalid move {}: {}", mov, err))).collect::<Result<Vec<_>, _>>()?;
+    let start = match &task.fen {
+        Some(fen) => Board::from_str(fen).map_err(|err| format!("Invalid FEN: {}", err))?,
+        None => Board::default(),
+    };
+    if prefix.len() != task.task_ply.min(steno_constraints.len()) {
+        return Err(format!("Task {} has {} moves, not {}", task.id, prefix.len(), task.task_ply));
+    }
+
+    // The whole steno, searched below the prefix, so constraints on the line
+    // before a ply see the prefix's moves too.
+    let solutions = Mutex::new(Vec::new());
+    let options = SolveOptions {
+        print_solutions: false,
+        halfmove_clock: halfmove_clock(&task.fen),
+        ..SolveOptions::default()
+    };
+    let stats = solve_after_prefix(start, &prefix, &steno_constraints, &options, &|path| {
+        let path: Vec<String> = path.iter().map(|mov| mov.to_string()).collect();
+        solutions.lock().unwrap().push(path.join(" "));
+    });
+    Ok((solutions.into_inner().unwrap(), stats.nodes_visited))
+}
+
+pub struct WorkerStats {
+    pub tasks: u64,
+    pub solutions: u64,
+}
+
+// Claims tasks from the coordinator at `addr` and searches them until it
+// says the search is done.
+pub fn work(addr: &str, worker: &str) -> Result<WorkerStats, String> {
+    let mut stats = WorkerStats { tasks: 0, solutions: 0 };
+    loop {
+        let claim = post_json(addr, "/tasks/claim", &json!({ "worker": worker }))?;
+        if claim["done"].as_bool() == Some(true) {
+            return Ok(stats);
+        }
+        if claim["wait"].as_bool() == Some(true) {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        let task: ClaimedTask = serde_json::from_value(claim["task"].clone()).map_err(|err| format!("Malformed task from the coordinator: {}", err))?;
+
+        match search_task(&task) {
+            Ok((solutions, nodes_visited)) => {
+                info!(task = task.id, solutions = solutions.len(), "task searched");
+                stats.tasks += 1;
+                stats.solutions += solutions.len() as u64;
+                post_json(addr, &format!("/tasks/{}/complete", task.id), &json!({ "worker": worker, "solutions": solutions, "nodes_visited": nodes_visited }))?;
+            }
+            Err(err) => {
+                warn!(task = task.id, "{}", err);
+                post_json(addr, &format!("/tasks/{}/fail", task.id), &json!({ "worker": worker }))?;
+            }
+        }
+    }
+}
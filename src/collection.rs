@@ -0,0 +1,137 @@
+use chess::Board;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::pgn::{moves_from_san, parse_pgn};
+use crate::search::count_solutions;
+use crate::steno::{halfmove_clock, parse_steno_string, verify_game};
+
+// One puzzle of a collection file: a steno, the solution it is meant to
+// have as SAN movetext, and optionally the position it starts from.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Puzzle {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub source: Option<String>,
+    pub steno: String,
+    pub solution: String,
+    pub fen: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CollectionFile {
+    #[serde(rename = "puzzle")]
+    puzzles: Vec<Puzzle>,
+}
+
+// The puzzles `play` serves without a collection file: stenos of famous
+// short games, loose enough that most have other solutions besides, as
+// (name, steno, solution).
+const BUILTIN_PUZZLES: &[(&str, &str, &str)] = &[
+    ("Fool's mate", "PPP#", "1. f3 e5 2. g4 Qh4#"),
+    ("Scholar's mate", "PPQNLN[Qx#]", "1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7#"),
+    ("Bongcloud", "PPKKKK", "1. e4 e5 2. Ke2 Ke7 3. Ke3 Ke6"),
+    ("Scandinavian", "PPxxNQ", "1. e4 d5 2. exd5 Qxd5 3. Nc3 Qa5"),
+    ("Trompowsky", "PNLPPL", "1. d4 Nf6 2. Bg5 e6 3. e4 Be7"),
+    ("Both sides castle", "PPNNLLoo", "1. e4 e5 2. Nf3 Nf6 3. Bc4 Bc5 4. O-O O-O"),
+    ("Knight raid", "NNNN[Nx][Nx][Nx]", "1. Nc3 Nc6 2. Nd5 Nd4 3. Nxe7 Nxe2 4. Nxc8"),
+    ("Queen trade", "PPQ~Q[Qx+]?", "1. c4 c5 2. Qc2 Qa5 3. Qd1 Qxd2+ 4. Nxd2"),
+    ("Pawn to knight", "PPxP%NxRn", "1. e4 f5 2. exf5 g5 3. fxg6 Nh6 4. gxh7 Rg8 5. hxg8=N"),
+    ("Legal's mate", "PPNPLLN[g6][Nx][Lx][Lx+]K[N#]", "1. e4 e5 2. Nf3 d6 3. Bc4 Bg4 4. Nc3 g6 5. Nxe5 Bxd1 6. Bxf7+ Ke7 7. Nd5#"),
+];
+
+pub fn builtin_puzzles() -> Vec<Puzzle> {
+    BUILTIN_PUZZLES.iter().map(|&(name, steno, solution)| Puzzle {
+        name: Some(name.to_string()),
+        author: None,
+        source: None,
+        steno: steno.to_string(),
+        solution: solution.to_string(),
+        fen: None,
+    }).collect()
+}
+
+// Reads a collection of `[[puzzle]]` tables from TOML, or from a JSON file
+// (ending in .json) of the form {"puzzle": [...]}.
+pub fn load_collection(path: &Path) -> Result<Vec<Puzzle>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let file: CollectionFile = if path.extension().is_some_and(|extension| extension == "json") {
+        serde_json::from_str(&contents).map_err(|err| format!("{}: {}", path.display(), err))?
+    } else {
+        toml::from_str(&contents).map_err(|err| format!("{}: {}", path.display(), err))?
+    };
+    Ok(file.puzzles)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PuzzleStatus {
+    // Solvable, unique, and the intended solution is the one.
+    Ok,
+    NoSolution,
+    // The intended solution matches, but so do other games.
+    NotUnique,
+    // The steno has solutions, but the intended one isn't among them.
+    WrongSolution(String),
+    // The steno, FEN or solution couldn't be read.
+    Invalid(String),
+}
+
+impl fmt::Display for PuzzleStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PuzzleStatus::Ok => write!(f, "ok"),
+            PuzzleStatus::NoSolution => write!(f, "no solution"),
+            PuzzleStatus::NotUnique => write!(f, "not unique"),
+            PuzzleStatus::WrongSolution(err) => write!(f, "wrong solution: {}", err),
+            PuzzleStatus::Invalid(err) => write!(f, "invalid: {}", err),
+        }
+    }
+}
+
+pub fn verify_puzzle(puzzle: &Puzzle) -> PuzzleStatus {
+    let parsed = (|| {
+        let board = match &puzzle.fen {
+            Some(fen) => Board::from_str(fen).map_err(|err| format!("Invalid FEN: {}", err))?,
+            None => Board::default(),
+        };
+        let steno_constraints = parse_steno_string(&puzzle.steno)?;
+        let game = parse_pgn(&puzzle.solution)?;
+        let moves = moves_from_san(&puzzle.fen, &game.san_moves)?;
+        Ok::<_, String>((board, steno_constraints, moves))
+    })();
+    let (board, steno_constraints, moves) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => return PuzzleStatus::Invalid(err),
+    };
+
+    let halfmove_clock = halfmove_clock(&puzzle.fen);
+    let solutions = count_solutions(board, halfmove_clock, &steno_constraints, Some(2));
+    if solutions == 0 {
+        return PuzzleStatus::NoSolution;
+    }
+    if let Err(err) = verify_game(board, halfmove_clock, &steno_constraints, &moves) {
+        return PuzzleStatus::WrongSolution(err);
+    }
+    if solutions > 1 {
+        return PuzzleStatus::NotUnique;
+    }
+    PuzzleStatus::Ok
+}
+
+// Puzzles are checked side by side, each search also spreading over the
+// workers.
+pub fn verify_collection(puzzles: &[Puzzle]) -> Vec<PuzzleStatus> {
+    #[cfg(feature = "parallel")]
+    let puzzles = puzzles.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let puzzles = puzzles.iter();
+
+    puzzles.map(verify_puzzle).collect()
+}
@@ -0,0 +1,345 @@
+use clap::{Arg, ArgAction, Command};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+use crate::steno::CONSTRAINT_LANGUAGE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!("Unknown shell: {} (expected bash, zsh or fish)", s)),
+        }
+    }
+}
+
+// What the shells can offer for an option's value.
+enum ValueHint {
+    Choices(Vec<String>),
+    File,
+    Directory,
+    Other,
+}
+
+fn help(arg: &Arg) -> String {
+    arg.get_help().map_or_else(String::new, |help| help.to_string())
+}
+
+fn about(command: &Command) -> String {
+    command.get_about().map_or_else(String::new, |about| about.to_string())
+}
+
+// Choices come from clap, else from a value name like url|san|uci, which is
+// how the options taking one of a few words are documented.
+fn value_hint(arg: &Arg) -> ValueHint {
+    let choices: Vec<String> = arg.get_possible_values().iter().filter(|value| !value.is_hide_set()).map(|value| value.get_name().to_string()).collect();
+    if !choices.is_empty() {
+        return ValueHint::Choices(choices);
+    }
+    let value_name = arg.get_value_names().and_then(|names| names.first()).map_or_else(String::new, |name| name.to_string());
+    match value_name.as_str() {
+        name if name.contains('|') => ValueHint::Choices(name.split('|').map(str::to_string).collect()),
+        "FILE" | "PATH" => ValueHint::File,
+        "DIR" => ValueHint::Directory,
+        _ => ValueHint::Other,
+    }
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+fn repeatable(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Append | ArgAction::Count)
+}
+
+fn options(command: &Command) -> impl Iterator<Item = &Arg> {
+    command.get_arguments().filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+}
+
+// Without clap's help subcommand, which --help covers.
+fn subcommands(command: &Command) -> impl Iterator<Item = &Command> {
+    command.get_subcommands().filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+}
+
+// The words the positional arguments can be, when they're one of a few.
+fn positional_choices(command: &Command) -> Vec<String> {
+    command.get_arguments().filter(|arg| arg.is_positional() && !arg.is_hide_set()).flat_map(|arg| match value_hint(arg) {
+        ValueHint::Choices(choices) => choices,
+        _ => Vec::new(),
+    }).collect()
+}
+
+// Every spelling of an option: --long, then -s.
+fn flags(arg: &Arg) -> Vec<String> {
+    arg.get_long().map(|long| format!("--{}", long)).into_iter().chain(arg.get_short().map(|short| format!("-{}", short))).collect()
+}
+
+fn function_name(names: &[&str]) -> String {
+    names.iter().map(|name| name.replace('-', "_")).collect::<Vec<_>>().join("_")
+}
+
+// A completion script for `command`, built with its global options, which
+// clap only copies into the subcommands when it parses.
+pub fn completion_script(command: &Command, shell: Shell) -> String {
+    let mut command = command.clone();
+    command.build();
+    match shell {
+        Shell::Bash => bash_script(&command),
+        Shell::Zsh => zsh_script(&command),
+        Shell::Fish => fish_script(&command),
+    }
+}
+
+fn bash_case(command: &Command, path: &str, script: &mut String) {
+    let words: Vec<String> = options(command).flat_map(flags).chain(subcommands(command).map(|subcommand| subcommand.get_name().to_string())).chain(positional_choices(command)).collect();
+    script.push_str(&format!("        \"{}\")\n            case \"$prev\" in\n", path));
+    for arg in options(command).filter(|arg| takes_value(arg)) {
+        let reply = match value_hint(arg) {
+            ValueHint::Choices(choices) => format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", choices.join(" ")),
+            ValueHint::File => "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string(),
+            ValueHint::Directory => "COMPREPLY=($(compgen -d -- \"$cur\"))".to_string(),
+            ValueHint::Other => "COMPREPLY=()".to_string(),
+        };
+        script.push_str(&format!("                {})\n                    {}\n                    return\n                    ;;\n", flags(arg).join("|"), reply));
+    }
+    script.push_str(&format!("            esac\n            opts=\"{}\"\n            ;;\n", words.join(" ")));
+    for subcommand in subcommands(command) {
+        let path = if path.is_empty() { subcommand.get_name().to_string() } else { format!("{} {}", path, subcommand.get_name()) };
+        bash_case(subcommand, &path, script);
+    }
+}
+
+// Every subcommand, as the words that lead to it, like `cache clear`.
+fn subcommand_paths(command: &Command, path: &str, paths: &mut Vec<String>) {
+    for subcommand in subcommands(command) {
+        let path = if path.is_empty() { subcommand.get_name().to_string() } else { format!("{} {}", path, subcommand.get_name()) };
+        paths.push(path.clone());
+        subcommand_paths(subcommand, &path, paths);
+    }
+}
+
+fn bash_script(command: &Command) -> String {
+    let name = command.get_name();
+    let mut script = format!(
+        "_{function}() {{
+    local cur prev path word i opts
+    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"
+    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"
+    path=\"\"
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        word=\"${{COMP_WORDS[i]}}\"
+        case \"$path $word\" in
+",
+        function = function_name(&[name])
+    );
+    let mut paths = Vec::new();
+    subcommand_paths(command, "", &mut paths);
+    for path in &paths {
+        let parent = path.rsplit_once(' ').map_or("", |(parent, _)| parent);
+        let word = path.rsplit(' ').next().unwrap();
+        script.push_str(&format!("            \"{} {}\") path=\"{}\" ;;\n", parent, word, path));
+    }
+    script.push_str("        esac\n    done\n    case \"$path\" in\n");
+    bash_case(command, "", &mut script);
+    script.push_str(&format!(
+        "    esac
+    COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))
+}}
+
+complete -F _{} -o default {}
+",
+        function_name(&[name]),
+        name
+    ));
+    script
+}
+
+fn zsh_quote(text: &str) -> String {
+    text.replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]").replace(':', "\\:")
+}
+
+fn zsh_action(hint: ValueHint) -> String {
+    match hint {
+        ValueHint::Choices(choices) => format!("({})", choices.join(" ")),
+        ValueHint::File => "_files".to_string(),
+        ValueHint::Directory => "_files -/".to_string(),
+        ValueHint::Other => " ".to_string(),
+    }
+}
+
+fn zsh_function(command: &Command, names: &[&str], script: &mut String) {
+    let mut specs = Vec::new();
+    for arg in options(command) {
+        let prefix = if repeatable(arg) { "*" } else { "" };
+        let value = if takes_value(arg) {
+            let action = zsh_action(value_hint(arg));
+            let value_name = arg.get_value_names().and_then(|names| names.first()).map_or_else(|| arg.get_id().to_string(), |name| name.to_string());
+            format!(":{}:{}", zsh_quote(&value_name), action)
+        } else {
+            String::new()
+        };
+        for flag in flags(arg) {
+            let equals = if takes_value(arg) && flag.starts_with("--") { "=" } else { "" };
+            specs.push(format!("'{}{}{}[{}]{}'", prefix, flag, equals, zsh_quote(&help(arg)), value));
+        }
+    }
+    let children: Vec<&Command> = subcommands(command).collect();
+    if !children.is_empty() {
+        specs.push("'1: :->command'".to_string());
+        specs.push("'*:: :->argument'".to_string());
+    } else {
+        for arg in command.get_arguments().filter(|arg| arg.is_positional() && !arg.is_hide_set()) {
+            let prefix = if repeatable(arg) || arg.get_num_args().is_some_and(|range| range.max_values() > 1) { "*" } else { "" };
+            specs.push(format!("'{}: :{}'", prefix, zsh_action(value_hint(arg))));
+        }
+    }
+    script.push_str(&format!("_{}() {{\n    local context state line\n    _arguments -s -C \\\n", function_name(names)));
+    for spec in &specs {
+        script.push_str(&format!("        {} \\\n", spec));
+    }
+    script.push_str("        && return\n");
+    if !children.is_empty() {
+        script.push_str("    case $state in\n        command)\n            local -a commands\n            commands=(\n");
+        for child in &children {
+            script.push_str(&format!("                '{}:{}'\n", child.get_name(), zsh_quote(&about(child))));
+        }
+        script.push_str("            )\n            _describe command commands\n            ;;\n        argument)\n            case $line[1] in\n");
+        for child in &children {
+            let child_names: Vec<&str> = names.iter().copied().chain([child.get_name()]).collect();
+            script.push_str(&format!("                {}) _{} ;;\n", child.get_name(), function_name(&child_names)));
+        }
+        script.push_str("            esac\n            ;;\n    esac\n");
+    }
+    script.push_str("}\n\n");
+    for child in children {
+        let child_names: Vec<&str> = names.iter().copied().chain([child.get_name()]).collect();
+        zsh_function(child, &child_names, script);
+    }
+}
+
+fn zsh_script(command: &Command) -> String {
+    let name = command.get_name();
+    let mut script = format!("#compdef {}\n\n", name);
+    zsh_function(command, &[name], &mut script);
+    script.push_str(&format!("_{} \"$@\"\n", function_name(&[name])));
+    script
+}
+
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish_options(command: &Command, name: &str, condition: &str, script: &mut String) {
+    for arg in options(command) {
+        let mut line = format!("complete -c {}", name);
+        if !condition.is_empty() && !arg.is_global_set() {
+            line.push_str(&format!(" -n {}", fish_quote(condition)));
+        }
+        if let Some(long) = arg.get_long() {
+            line.push_str(&format!(" -l {}", long));
+        }
+        if let Some(short) = arg.get_short() {
+            line.push_str(&format!(" -s {}", short));
+        }
+        if takes_value(arg) {
+            line.push_str(&match value_hint(arg) {
+                ValueHint::Choices(choices) => format!(" -r -f -a {}", fish_quote(&choices.join(" "))),
+                ValueHint::File => " -r -F".to_string(),
+                ValueHint::Directory => " -r -f -a '(__fish_complete_directories)'".to_string(),
+                ValueHint::Other => " -r".to_string(),
+            });
+        }
+        line.push_str(&format!(" -d {}\n", fish_quote(&help(arg))));
+        script.push_str(&line);
+    }
+}
+
+fn fish_script(command: &Command) -> String {
+    let name = command.get_name();
+    let children: Vec<&Command> = subcommands(command).collect();
+    let names = children.iter().map(|child| child.get_name()).collect::<Vec<_>>().join(" ");
+    let top_level = format!("not __fish_seen_subcommand_from {}", names);
+    let mut script = String::new();
+    fish_options(command, name, &top_level, &mut script);
+    for child in &children {
+        script.push_str(&format!("complete -c {} -n {} -f -a {} -d {}\n", name, fish_quote("__fish_use_subcommand"), child.get_name(), fish_quote(&about(child))));
+    }
+    for child in &children {
+        let condition = format!("__fish_seen_subcommand_from {}", child.get_name());
+        fish_options(child, name, &condition, &mut script);
+        for choice in positional_choices(child) {
+            script.push_str(&format!("complete -c {} -n {} -f -a {}\n", name, fish_quote(&condition), choice));
+        }
+        for grandchild in subcommands(child) {
+            script.push_str(&format!("complete -c {} -n {} -f -a {} -d {}\n", name, fish_quote(&condition), grandchild.get_name(), fish_quote(&about(grandchild))));
+        }
+    }
+    script
+}
+
+fn arg_schema(arg: &Arg) -> Value {
+    let choices = match value_hint(arg) {
+        ValueHint::Choices(choices) => Some(choices),
+        _ => None,
+    };
+    json!({
+        "name": arg.get_id().as_str(),
+        "long": arg.get_long(),
+        "short": arg.get_short().map(String::from),
+        "help": help(arg),
+        "takes_value": takes_value(arg),
+        "value_name": arg.get_value_names().and_then(|names| names.first()).map(|name| name.to_string()),
+        "choices": choices,
+        "default": arg.get_default_values().iter().map(|value| value.to_string_lossy()).collect::<Vec<_>>(),
+        "required": arg.is_required_set(),
+        "repeatable": repeatable(arg),
+        "global": arg.is_global_set(),
+    })
+}
+
+fn command_schema(command: &Command) -> Value {
+    let visible = |arg: &&Arg| !arg.is_hide_set();
+    json!({
+        "name": command.get_name(),
+        "about": about(command),
+        "options": command.get_arguments().filter(visible).filter(|arg| !arg.is_positional()).map(arg_schema).collect::<Vec<_>>(),
+        "positionals": command.get_arguments().filter(visible).filter(|arg| arg.is_positional()).map(arg_schema).collect::<Vec<_>>(),
+        "subcommands": subcommands(command).filter(|subcommand| subcommand.get_name() != "help").map(command_schema).collect::<Vec<_>>(),
+    })
+}
+
+// The constraint characters and syntax from CONSTRAINT_LANGUAGE, each with
+// what it asks of a move.
+fn grammar_schema() -> Value {
+    let entries: Vec<Value> = CONSTRAINT_LANGUAGE.lines()
+        .filter_map(|line| line.strip_prefix("  "))
+        .filter_map(|line| {
+            let (syntax, meaning) = line.split_once("  ")?;
+            Some(json!({ "syntax": syntax.trim(), "meaning": meaning.trim() }))
+        })
+        .collect();
+    json!({ "summary": CONSTRAINT_LANGUAGE.lines().next(), "constraints": entries })
+}
+
+// The options of `command` and its subcommands, and the constraint language,
+// for front ends that build their forms from it.
+pub fn cli_schema(command: &Command) -> Value {
+    let mut command = command.clone();
+    command.build();
+    let mut schema = command_schema(&command);
+    schema["version"] = json!(command.get_version());
+    schema["constraint_language"] = grammar_schema();
+    schema
+}
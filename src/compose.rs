@@ -0,0 +1,174 @@
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, Piece};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::mem;
+use std::str::FromStr;
+
+use crate::search::{count_solutions, solve_with_callback, SolveOptions};
+use crate::stats::SearchStats;
+use crate::steno::{parse_steno_string, requires_line, steno_string, CastleSide, Constraint, MoveContext};
+
+// Most to least specific: each ply of a game is encoded by the first of these
+// constraints its move satisfies. Every move matches its piece letter.
+const ENCODING_PRIORITY: &[Constraint] = &[
+    Constraint::Checkmate,
+    Constraint::Stalemate,
+    Constraint::Castle { side: Some(CastleSide::Kingside), color: None },
+    Constraint::Castle { side: Some(CastleSide::Queenside), color: None },
+    Constraint::Promotion(Piece::Queen),
+    Constraint::Promotion(Piece::Rook),
+    Constraint::Promotion(Piece::Knight),
+    Constraint::Promotion(Piece::Bishop),
+    Constraint::EnPassant,
+    Constraint::Check,
+    Constraint::Capture,
+    Constraint::Mover(Piece::King),
+    Constraint::Mover(Piece::Queen),
+    Constraint::Mover(Piece::Rook),
+    Constraint::Mover(Piece::Bishop),
+    Constraint::Mover(Piece::Knight),
+    Constraint::Mover(Piece::Pawn),
+];
+
+pub fn steno_for_game(board: Board, moves: &[ChessMove]) -> Result<String, String> {
+    let mut board = board;
+    let mut steno = String::new();
+
+    for (ply, &mov) in moves.iter().enumerate() {
+        if !board.legal(mov) {
+            return Err(format!("Illegal move at ply {}: {}", ply + 1, mov));
+        }
+        let new_board = board.make_move_new(mov);
+        let context = MoveContext::new(&board, mov, &new_board);
+        let constraint = ENCODING_PRIORITY.iter()
+            .find(|constraint| constraint.matches(&context))
+            .unwrap();
+        steno.push_str(&constraint.to_string());
+        board = new_board;
+    }
+
+    Ok(steno)
+}
+
+// Greedily relaxes plies to `~`, front to back, keeping each relaxation only
+// if the steno still has exactly one solution. Returns None when the steno
+// isn't unique to begin with. `halfmove_clock` is the one on `board`.
+pub fn weaken_to_unique(board: Board, halfmove_clock: u32, steno: &str) -> Option<String> {
+    let mut steno_constraints = parse_steno_string(steno).ok()?;
+    if count_solutions(board, halfmove_clock, &steno_constraints, Some(2)) != 1 {
+        return None;
+    }
+
+    for ply in 0..steno_constraints.len() {
+        if steno_constraints[ply] == Constraint::Any {
+            continue;
+        }
+        let original = mem::replace(&mut steno_constraints[ply], Constraint::Any);
+        if count_solutions(board, halfmove_clock, &steno_constraints, Some(2)) != 1 {
+            steno_constraints[ply] = original;
+        }
+    }
+
+    Some(steno_string(&steno_constraints))
+}
+
+// Plays uniformly random legal moves. Games that end in mate or stalemate
+// before `plies` are retried, up to a fixed number of attempts.
+pub fn random_game<R: Rng>(board: Board, plies: usize, rng: &mut R) -> Option<Vec<ChessMove>> {
+    for _ in 0..100 {
+        let mut position = board;
+        let mut moves = Vec::new();
+        while moves.len() < plies && position.status() == BoardStatus::Ongoing {
+            let legal: Vec<ChessMove> = MoveGen::new_legal(&position).collect();
+            let &mov = legal.choose(rng).unwrap();
+            position = position.make_move_new(mov);
+            moves.push(mov);
+        }
+        if moves.len() == plies {
+            return Some(moves);
+        }
+    }
+    None
+}
+
+const PREFIX_SUMMARY: &str = "Distinct positions after ply ";
+
+// What solve --prefix-depth prints: each position with the number of games
+// reaching it, without the move counters, then a summary line.
+pub fn render_prefix_positions(positions: &[(Board, u64)], ply: usize) -> String {
+    let mut rendered = String::new();
+    for (position, count) in positions {
+        let fen = position.to_string();
+        rendered.push_str(&format!("{} {}\n", count, fen.split(' ').take(4).collect::<Vec<_>>().join(" ")));
+    }
+    let games: u64 = positions.iter().map(|(_, count)| count).sum();
+    rendered.push_str(&format!("{}{}: {} (from {} games)\n", PREFIX_SUMMARY, ply, positions.len(), games));
+    rendered
+}
+
+// Reads render_prefix_positions' output back, checked against itself and
+// against the prefix it is to stand in for: its ply count, the side to move
+// after it, and the game total in the summary. Lines after the summary, such
+// as --stats, are ignored.
+pub fn read_prefix_positions(contents: &str, board: &Board, prefix_plies: usize) -> Result<Vec<(Board, u64)>, String> {
+    let mut positions = Vec::new();
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    for line in lines.by_ref() {
+        if let Some(summary) = line.strip_prefix(PREFIX_SUMMARY) {
+            let (ply, counts) = summary.split_once(": ").ok_or_else(|| format!("Malformed summary line: {}", line))?;
+            if ply.parse::<usize>().ok() != Some(prefix_plies) {
+                return Err(format!("The positions are after ply {}, but the prefix has {} plies", ply, prefix_plies));
+            }
+            let games: u64 = positions.iter().map(|(_, count)| count).sum();
+            if counts != format!("{} (from {} games)", positions.len(), games) {
+                return Err(format!("The summary says {} positions, but {} positions reached by {} games are listed", counts, positions.len(), games));
+            }
+            return Ok(positions);
+        }
+        let (count, fen) = line.split_once(' ').ok_or_else(|| format!("Expected a game count and a FEN: {}", line))?;
+        let count = count.parse::<u64>().map_err(|_| format!("Expected a game count and a FEN: {}", line))?;
+        let position = Board::from_str(fen).map_err(|err| format!("Invalid FEN {}: {}", fen, err))?;
+        let side_to_move = if prefix_plies.is_multiple_of(2) { board.side_to_move() } else { !board.side_to_move() };
+        if position.side_to_move() != side_to_move {
+            let side = |color: Color| if color == Color::White { "white" } else { "black" };
+            return Err(format!("{} has {} to move, but {} would be after the prefix", fen, side(position.side_to_move()), side(side_to_move)));
+        }
+        positions.push((position, count));
+    }
+    Err(format!("No \"{}\" summary line; is this the output of solve --prefix-depth?", PREFIX_SUMMARY.trim_end()))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ContinuedPosition {
+    pub position: Board,
+    pub prefix_games: u64,
+    pub suffix_solutions: u64,
+}
+
+impl ContinuedPosition {
+    // The joined steno's games through this position.
+    pub fn games(&self) -> u64 {
+        self.prefix_games * self.suffix_solutions
+    }
+}
+
+// Searches the suffix from each position a prefix ends in, so a change to the
+// end of a long steno doesn't search its opening again.
+pub fn continue_positions(positions: &[(Board, u64)], suffix_constraints: &[Constraint], options: &SolveOptions) -> Result<(Vec<ContinuedPosition>, SearchStats), String> {
+    if requires_line(suffix_constraints) || options.prune_repetitions {
+        return Err("Repetitions, the fifty-move rule and constraints on earlier plies depend on the prefix's games, which the positions don't keep".to_string());
+    }
+    let mut stats = SearchStats::default();
+    let mut continued = Vec::new();
+    for &(position, count) in positions {
+        let position_stats = solve_with_callback(position, suffix_constraints, options, &|_| {});
+        stats.nodes_visited += position_stats.nodes_visited;
+        stats.nodes_pruned += position_stats.nodes_pruned;
+        stats.tasks += position_stats.tasks;
+        stats.elapsed += position_stats.elapsed;
+        stats.peak_memory = stats.peak_memory.max(position_stats.peak_memory);
+        continued.push(ContinuedPosition { position, prefix_games: count, suffix_solutions: position_stats.solutions });
+    }
+    stats.solutions = continued.iter().map(ContinuedPosition::games).sum();
+    Ok((continued, stats))
+}
@@ -0,0 +1,98 @@
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::render::OutputFormat;
+
+// Defaults for flags people don't want to retype. Each field is filled from
+// config.toml, then overridden by the matching STENO_SOLVER_* variable;
+// command-line flags override both.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub threads: Option<usize>,
+    pub format: Option<OutputFormat>,
+    // Path to a UCI engine binary.
+    pub engine: Option<PathBuf>,
+    pub limit: Option<u64>,
+    pub max_plies: Option<usize>,
+    // Whether solve keeps and reuses results in the result cache.
+    pub cache: Option<bool>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    threads: Option<usize>,
+    format: Option<String>,
+    engine: Option<PathBuf>,
+    limit: Option<u64>,
+    max_plies: Option<usize>,
+    cache: Option<bool>,
+}
+
+// $STENO_SOLVER_CONFIG, else $XDG_CONFIG_HOME/steno-solver/config.toml,
+// else ~/.config/steno-solver/config.toml.
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("STENO_SOLVER_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("steno-solver").join("config.toml"))
+}
+
+fn env_value<T: std::str::FromStr>(name: &str) -> Result<Option<T>, String> {
+    match env::var(name) {
+        Ok(value) => value.parse().map(Some).map_err(|_| format!("Invalid value for {}: {}", name, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Config, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let file: ConfigFile = toml::from_str(&contents).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let format = match file.format {
+            Some(format) => Some(format.parse().map_err(|err| format!("{}: {}", path.display(), err))?),
+            None => None,
+        };
+        Ok(Config {
+            threads: file.threads,
+            format,
+            engine: file.engine,
+            limit: file.limit,
+            max_plies: file.max_plies,
+            cache: file.cache,
+        })
+    }
+
+    // A missing config file is fine; a malformed one or a bad variable is not.
+    pub fn load() -> Result<Config, String> {
+        let mut config = match config_path() {
+            Some(path) if path.exists() => Config::from_file(&path)?,
+            _ => Config::default(),
+        };
+
+        if let Some(threads) = env_value("STENO_SOLVER_THREADS")? {
+            config.threads = Some(threads);
+        }
+        if let Some(format) = env_value("STENO_SOLVER_FORMAT")? {
+            config.format = Some(format);
+        }
+        if let Some(engine) = env_value("STENO_SOLVER_ENGINE")? {
+            config.engine = Some(engine);
+        }
+        if let Some(limit) = env_value("STENO_SOLVER_LIMIT")? {
+            config.limit = Some(limit);
+        }
+        if let Some(max_plies) = env_value("STENO_SOLVER_MAX_PLIES")? {
+            config.max_plies = Some(max_plies);
+        }
+        if let Some(cache) = env_value("STENO_SOLVER_CACHE")? {
+            config.cache = Some(cache);
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,245 @@
+use chess::{Board, ChessMove, Color, Rank, Square, ALL_SQUARES};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::dedup::SpillSet;
+use crate::render::solution_id;
+use crate::search::{solve_with_callback, SolveOptions};
+use crate::stats::SearchStats;
+use crate::steno::Constraint;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CountBy {
+    #[default]
+    Games,
+    // Solutions reaching the same final position (pieces, side to move,
+    // castling and en passant rights) count once.
+    Positions,
+    // Solutions where each side plays the same moves in a different order
+    // count once.
+    Classes,
+}
+
+impl FromStr for CountBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "games" => Ok(CountBy::Games),
+            "positions" => Ok(CountBy::Positions),
+            "classes" => Ok(CountBy::Classes),
+            _ => Err(format!("Unknown count: {} (expected games, positions or classes)", s)),
+        }
+    }
+}
+
+// Fed every solution from the search callback. Only 64-bit keys are kept
+// per distinct solution, so collisions are possible but vanishingly rare.
+pub struct DistinctCounter {
+    count_by: CountBy,
+    games: AtomicU64,
+    seen: Mutex<SpillSet>,
+}
+
+fn class_key(path: &[ChessMove]) -> u64 {
+    let mut white: Vec<ChessMove> = path.iter().step_by(2).copied().collect();
+    let mut black: Vec<ChessMove> = path.iter().skip(1).step_by(2).copied().collect();
+    white.sort();
+    black.sort();
+
+    let mut hasher = DefaultHasher::new();
+    (white, black).hash(&mut hasher);
+    hasher.finish()
+}
+
+// The same for solutions that only differ in what a pawn promoted to, at
+// plies whose constraint doesn't ask for a promotion piece. Predicates from
+// --define count as asking, since they may.
+pub fn promotion_class_key(steno_constraints: &[Constraint], path: &[ChessMove]) -> u64 {
+    let asks_piece = |constraint: &Constraint| constraint.requires(&|part| match part {
+        Constraint::Promotion(_) => true,
+        #[cfg(feature = "script")]
+        Constraint::Custom(..) => true,
+        _ => false,
+    });
+    let moves: Vec<ChessMove> = path.iter().zip(steno_constraints).map(|(&mov, constraint)| match mov.get_promotion() {
+        Some(_) if !asks_piece(constraint) => ChessMove::new(mov.get_source(), mov.get_dest(), None),
+        _ => mov,
+    }).collect();
+
+    let mut hasher = DefaultHasher::new();
+    moves.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl DistinctCounter {
+    pub fn new(count_by: CountBy) -> DistinctCounter {
+        DistinctCounter {
+            count_by,
+            games: AtomicU64::new(0),
+            seen: Mutex::new(SpillSet::new()),
+        }
+    }
+
+    // Keeps about `bytes` of distinct keys in memory and spills the rest to disk.
+    pub fn with_memory_limit(count_by: CountBy, bytes: u64) -> DistinctCounter {
+        DistinctCounter {
+            seen: Mutex::new(SpillSet::with_memory_limit(bytes)),
+            ..DistinctCounter::new(count_by)
+        }
+    }
+
+    // Keeps its keys in `seen`, however that set was built.
+    pub fn with_set(count_by: CountBy, seen: SpillSet) -> DistinctCounter {
+        DistinctCounter {
+            seen: Mutex::new(seen),
+            ..DistinctCounter::new(count_by)
+        }
+    }
+
+    pub fn record(&self, board: Board, path: &[ChessMove]) -> io::Result<()> {
+        let key = match self.count_by {
+            CountBy::Games => {
+                self.games.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+            CountBy::Positions => path.iter().fold(board, |board, &mov| board.make_move_new(mov)).get_hash(),
+            CountBy::Classes => class_key(path),
+        };
+        self.seen.lock().unwrap().insert(key).map(|_| ())
+    }
+
+    pub fn count(&self) -> u64 {
+        match self.count_by {
+            CountBy::Games => self.games.load(Ordering::Relaxed),
+            _ => self.seen.lock().unwrap().len(),
+        }
+    }
+
+    pub fn spilled(&self) -> u64 {
+        self.seen.lock().unwrap().spilled()
+    }
+}
+
+fn mirror_square(square: Square) -> Square {
+    Square::make_square(Rank::from_index(7 - square.get_rank().to_index()), square.get_file())
+}
+
+// Whether the start position is its own color mirror image (the board turned
+// over and the colors swapped, side to move aside) and each move pair's
+// constraints are too, the second the first's mirror. Only then does
+// MirrorCounter pair solutions up.
+pub fn color_symmetric(board: &Board, steno_constraints: &[Constraint]) -> bool {
+    let position_symmetric = ALL_SQUARES.iter().all(|&square| {
+        let mirror = mirror_square(square);
+        board.piece_on(square) == board.piece_on(mirror) && board.color_on(square).map(|color| !color) == board.color_on(mirror)
+    });
+    position_symmetric
+        && board.castle_rights(Color::White) == board.castle_rights(Color::Black)
+        && board.en_passant().is_none()
+        && steno_constraints.len().is_multiple_of(2)
+        && steno_constraints.chunks(2).all(|pair| pair[0].mirrored().as_ref() == Some(&pair[1]))
+}
+
+// The game where each side plays, mirrored, what the other played in the same
+// move pair, if it's legal.
+fn mirror_game(board: Board, path: &[ChessMove]) -> Option<Vec<ChessMove>> {
+    let mut mirrored = Vec::with_capacity(path.len());
+    let mut position = board;
+    for pair in path.chunks(2) {
+        for mov in pair.iter().rev() {
+            let mov = ChessMove::new(mirror_square(mov.get_source()), mirror_square(mov.get_dest()), mov.get_promotion());
+            if !position.legal(mov) {
+                return None;
+            }
+            position = position.make_move_new(mov);
+            mirrored.push(mov);
+        }
+    }
+    Some(mirrored)
+}
+
+// Fed every solution of a color-symmetric steno, counts a solution and its
+// mirror game once. A solution whose mirror game isn't one counts alone.
+pub struct MirrorCounter {
+    seen: Mutex<SpillSet>,
+}
+
+impl MirrorCounter {
+    pub fn new(seen: SpillSet) -> MirrorCounter {
+        MirrorCounter { seen: Mutex::new(seen) }
+    }
+
+    pub fn record(&self, board: Board, path: &[ChessMove]) -> io::Result<()> {
+        let id = solution_id(path);
+        let key = mirror_game(board, path).map_or(id, |mirrored| id.min(solution_id(&mirrored)));
+        self.seen.lock().unwrap().insert(key).map(|_| ())
+    }
+
+    pub fn count(&self) -> u64 {
+        self.seen.lock().unwrap().len()
+    }
+}
+
+// The wrapping sum of the solutions' IDs, so the same games give the same
+// digest however the workers shared them out and whichever came first.
+#[derive(Default)]
+pub struct SolutionDigest {
+    solutions: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl SolutionDigest {
+    pub fn new() -> SolutionDigest {
+        SolutionDigest::default()
+    }
+
+    pub fn record(&self, path: &[ChessMove]) {
+        self.solutions.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(solution_id(path), Ordering::Relaxed);
+    }
+
+    pub fn solutions(&self) -> u64 {
+        self.solutions.load(Ordering::Relaxed)
+    }
+
+    pub fn digest(&self) -> u64 {
+        self.sum.load(Ordering::Relaxed)
+    }
+}
+
+// Every position the steno's games end in, with how many games reach it,
+// most common first.
+pub fn prefix_positions(board: Board, steno_constraints: &[Constraint], options: &SolveOptions) -> (Vec<(Board, u64)>, SearchStats) {
+    let positions: Mutex<HashMap<Board, u64>> = Mutex::new(HashMap::new());
+    let stats = solve_with_callback(board, steno_constraints, options, &|path| {
+        let position = path.iter().fold(board, |board, &mov| board.make_move_new(mov));
+        *positions.lock().unwrap().entry(position).or_default() += 1;
+    });
+
+    let mut positions: Vec<(Board, u64)> = positions.into_inner().unwrap().into_iter().collect();
+    positions.sort_by_cached_key(|&(position, count)| (Reverse(count), position.to_string()));
+    (positions, stats)
+}
+
+// The steno solved from each start, a position with its halfmove clock, side
+// by side, each search also spreading over the workers.
+pub fn solve_from_starts(starts: &[(Board, u32)], steno_constraints: &[Constraint], options: &SolveOptions) -> Vec<SearchStats> {
+    #[cfg(feature = "parallel")]
+    let starts = starts.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let starts = starts.iter();
+
+    starts.map(|&(board, halfmove_clock)| {
+        let options = SolveOptions { halfmove_clock, ..options.clone() };
+        solve_with_callback(board, steno_constraints, &options, &|_| {})
+    }).collect()
+}
@@ -0,0 +1,268 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::memory::MemoryBudget;
+
+// What a key costs in the in-memory set, hash table overhead included.
+const BYTES_PER_KEY: u64 = 16;
+
+// Spilled keys are read back a block at a time; the first key of every block
+// stays in memory to find the right one.
+const BLOCK_KEYS: u64 = 512;
+
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+// Keys moved out of memory: one sorted file of little-endian u64s, removed
+// again when the set is dropped.
+struct Spill {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    block_starts: Vec<u64>,
+}
+
+impl Spill {
+    fn contains(&self, key: u64) -> io::Result<bool> {
+        let Some(block) = self.block_starts.partition_point(|&first| first <= key).checked_sub(1) else {
+            return Ok(false);
+        };
+        let start = block as u64 * BLOCK_KEYS;
+        let mut buf = vec![0; (BLOCK_KEYS.min(self.len - start) * 8) as usize];
+        (&self.file).seek(SeekFrom::Start(start * 8))?;
+        (&self.file).read_exact(&mut buf)?;
+        let keys: Vec<u64> = buf.chunks_exact(8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap())).collect();
+        Ok(keys.binary_search(&key).is_ok())
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Merges the keys already on disk with `keys` (sorted, none of them on disk
+// yet) into a new spill file.
+fn write_merged(old: Option<&Spill>, keys: &[u64]) -> io::Result<Spill> {
+    let path = env::temp_dir().join(format!("steno-solver-{}-{}.keys", process::id(), SPILL_FILES.fetch_add(1, Ordering::Relaxed)));
+    let write = || -> io::Result<(u64, Vec<u64>)> {
+        let mut output = BufWriter::new(File::create(&path)?);
+        let mut len = 0;
+        let mut block_starts = Vec::new();
+        let mut push = |key: u64| {
+            if len % BLOCK_KEYS == 0 {
+                block_starts.push(key);
+            }
+            len += 1;
+            output.write_all(&key.to_le_bytes())
+        };
+
+        let mut keys = keys.iter().copied().peekable();
+        if let Some(old) = old {
+            let mut input = BufReader::new(File::open(&old.path)?);
+            let mut buf = [0; 8];
+            for _ in 0..old.len {
+                input.read_exact(&mut buf)?;
+                let value = u64::from_le_bytes(buf);
+                while let Some(key) = keys.next_if(|&key| key < value) {
+                    push(key)?;
+                }
+                push(value)?;
+            }
+        }
+        for key in keys {
+            push(key)?;
+        }
+        output.flush()?;
+        Ok((len, block_starts))
+    };
+
+    match write().and_then(|(len, block_starts)| Ok((File::open(&path)?, len, block_starts))) {
+        Ok((file, len, block_starts)) => Ok(Spill { path, file, len, block_starts }),
+        Err(err) => {
+            let _ = fs::remove_file(&path);
+            Err(err)
+        }
+    }
+}
+
+// A set of 64-bit keys for deduplicating solutions. With a memory limit,
+// whenever the in-memory part fills up it is merged into a sorted file in the
+// temp directory, so huge searches dedup at the cost of disk reads instead
+// of running out of memory. With a budget, it also spills whenever the budget
+// refuses a key.
+pub struct SpillSet {
+    memory: HashSet<u64>,
+    max_in_memory: Option<usize>,
+    spill: Option<Spill>,
+    budget: Option<Arc<MemoryBudget>>,
+    warned: bool,
+}
+
+impl Drop for SpillSet {
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.release(self.memory.len() as u64 * BYTES_PER_KEY);
+        }
+    }
+}
+
+impl Default for SpillSet {
+    fn default() -> Self {
+        SpillSet::new()
+    }
+}
+
+impl SpillSet {
+    pub fn new() -> SpillSet {
+        SpillSet { memory: HashSet::new(), max_in_memory: None, spill: None, budget: None, warned: false }
+    }
+
+    pub fn with_memory_limit(bytes: u64) -> SpillSet {
+        let max_in_memory = (bytes / BYTES_PER_KEY).max(1) as usize;
+        let mut set = SpillSet::new();
+        set.max_in_memory = Some(max_in_memory);
+        set
+    }
+
+    pub fn with_budget(mut self, budget: Arc<MemoryBudget>) -> SpillSet {
+        self.budget = Some(budget);
+        self
+    }
+
+    // True when the key wasn't in the set yet.
+    pub fn insert(&mut self, key: u64) -> io::Result<bool> {
+        if self.memory.contains(&key) {
+            return Ok(false);
+        }
+        if let Some(spill) = &self.spill {
+            if spill.contains(key)? {
+                return Ok(false);
+            }
+        }
+        self.memory.insert(key);
+
+        let refused = self.budget.as_ref().is_some_and(|budget| !budget.reserve(BYTES_PER_KEY));
+        if refused && !self.warned {
+            warn!("--max-memory reached, spilling dedup keys to a temporary file");
+            self.warned = true;
+        }
+        if refused || self.max_in_memory.is_some_and(|max| self.memory.len() >= max) {
+            let mut keys: Vec<u64> = self.memory.drain().collect();
+            keys.sort_unstable();
+            match write_merged(self.spill.as_ref(), &keys) {
+                Ok(spill) => self.spill = Some(spill),
+                Err(err) => {
+                    self.memory.extend(keys);
+                    return Err(err);
+                }
+            }
+            // The key refused a reservation is on disk with the rest.
+            let held = keys.len() as u64 - refused as u64;
+            if let Some(budget) = &self.budget {
+                budget.release(held * BYTES_PER_KEY);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.memory.len() as u64 + self.spilled()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // How many of the keys live on disk.
+    pub fn spilled(&self) -> u64 {
+        self.spill.as_ref().map_or(0, |spill| spill.len)
+    }
+}
+
+const BLOOM_HASHES: u32 = 7;
+
+// Spreads a key's bits so the second Bloom hash is independent of the first.
+fn mix(mut key: u64) -> u64 {
+    key ^= key >> 30;
+    key = key.wrapping_mul(0xbf58476d1ce4e5b9);
+    key ^= key >> 27;
+    key = key.wrapping_mul(0x94d049bb133111eb);
+    key ^ (key >> 31)
+}
+
+// Approximate dedup in constant memory, shared between workers without a
+// lock. A key whose bits all happen to be set already is taken for a
+// duplicate and skipped; two workers inserting the same key at the same time
+// may both see it as new.
+pub struct BloomFilter {
+    words: Vec<AtomicU64>,
+    bits: u64,
+    set_bits: AtomicU64,
+    inserted: AtomicU64,
+}
+
+impl BloomFilter {
+    pub fn new(bits: u64) -> BloomFilter {
+        let words = bits.max(1).div_ceil(64);
+        BloomFilter {
+            words: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            bits: words * 64,
+            set_bits: AtomicU64::new(0),
+            inserted: AtomicU64::new(0),
+        }
+    }
+
+    // True when the key definitely wasn't in the filter yet.
+    pub fn insert(&self, key: u64) -> bool {
+        let step = mix(key) | 1;
+        let mut set = 0;
+        for i in 0..BLOOM_HASHES as u64 {
+            let bit = key.wrapping_add(i.wrapping_mul(step)) % self.bits;
+            let mask = 1 << (bit % 64);
+            if self.words[(bit / 64) as usize].fetch_or(mask, Ordering::Relaxed) & mask == 0 {
+                set += 1;
+            }
+        }
+        if set == 0 {
+            return false;
+        }
+        self.set_bits.fetch_add(set, Ordering::Relaxed);
+        self.inserted.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    pub fn len(&self) -> u64 {
+        self.inserted.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Expected number of new keys wrongly taken for duplicates so far: the
+    // false positive rate at each fill level, summed over the distinct keys
+    // offered. How many those were is estimated from the bits set, since the
+    // skipped ones were never counted.
+    pub fn expected_false_positives(&self) -> f64 {
+        let unset = 1.0 - self.set_bits.load(Ordering::Relaxed) as f64 / self.bits as f64;
+        let distinct = (-(self.bits as f64) / BLOOM_HASHES as f64 * unset.max(1.0 / self.bits as f64).ln()).max(self.len() as f64);
+        let steps = 1000;
+        let per_step = distinct / steps as f64;
+        (0..steps).map(|step| {
+            let filled = 1.0 - (-(BLOOM_HASHES as f64) * (step as f64 + 0.5) * per_step / self.bits as f64).exp();
+            filled.powi(BLOOM_HASHES as i32) * per_step
+        }).sum()
+    }
+}
@@ -0,0 +1,126 @@
+use chess::{Board, ChessMove, Color, File, Piece, Rank, Square};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+const SQUARE_SIZE: usize = 45;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DiagramFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+impl DiagramFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            DiagramFormat::Svg => "svg",
+            DiagramFormat::Png => "png",
+        }
+    }
+}
+
+impl FromStr for DiagramFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "svg" => Ok(DiagramFormat::Svg),
+            "png" => Ok(DiagramFormat::Png),
+            _ => Err(format!("Unknown diagram format: {} (expected svg or png)", s)),
+        }
+    }
+}
+
+fn piece_glyph(piece: Piece) -> char {
+    match piece {
+        Piece::King => '♚',
+        Piece::Queen => '♛',
+        Piece::Rook => '♜',
+        Piece::Bishop => '♝',
+        Piece::Knight => '♞',
+        Piece::Pawn => '♟',
+    }
+}
+
+// Pieces are drawn as the solid Unicode glyphs, outlined in black and filled
+// per side, so any font with the chess symbols block renders them.
+pub fn board_svg(board: &Board, last_move: Option<ChessMove>) -> String {
+    let size = SQUARE_SIZE * 8;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">\n",
+        size
+    );
+
+    for rank in 0..8 {
+        for file in 0..8 {
+            let square = Square::make_square(Rank::from_index(rank), File::from_index(file));
+            let x = file * SQUARE_SIZE;
+            let y = (7 - rank) * SQUARE_SIZE;
+            let highlighted = last_move.is_some_and(|mov| mov.get_source() == square || mov.get_dest() == square);
+            let fill = match ((rank + file) % 2 == 1, highlighted) {
+                (true, false) => "#f0d9b5",
+                (false, false) => "#b58863",
+                (true, true) => "#cdd26a",
+                (false, true) => "#aaa23a",
+            };
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{2}\" height=\"{2}\" fill=\"{3}\"/>\n",
+                x, y, SQUARE_SIZE, fill
+            ));
+
+            if let (Some(piece), Some(color)) = (board.piece_on(square), board.color_on(square)) {
+                let fill = if color == Color::White { "#ffffff" } else { "#000000" };
+                svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" font-family=\"DejaVu Sans, Segoe UI Symbol, sans-serif\" font-size=\"38\" text-anchor=\"middle\" fill=\"{}\" stroke=\"#000000\" stroke-width=\"1\">{}</text>\n",
+                    x + SQUARE_SIZE / 2, y + SQUARE_SIZE - 9, fill, piece_glyph(piece)
+                ));
+            }
+        }
+    }
+
+    for index in 0..8 {
+        let color = |light: bool| if light { "#b58863" } else { "#f0d9b5" };
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"DejaVu Sans, sans-serif\" font-size=\"10\" fill=\"{}\">{}</text>\n",
+            index * SQUARE_SIZE + SQUARE_SIZE - 8, size - 3, color(index % 2 == 1), (b'a' + index as u8) as char
+        ));
+        svg.push_str(&format!(
+            "<text x=\"2\" y=\"{}\" font-family=\"DejaVu Sans, sans-serif\" font-size=\"10\" fill=\"{}\">{}</text>\n",
+            (7 - index) * SQUARE_SIZE + 11, color(index % 2 == 1), index + 1
+        ));
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(feature = "png")]
+fn write_png(svg: &str, path: &Path) -> io::Result<()> {
+    use resvg::{tiny_skia, usvg};
+
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|err| io::Error::other(err.to_string()))?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height()).ok_or_else(|| io::Error::other("empty diagram"))?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap.save_png(path).map_err(|err| io::Error::other(err.to_string()))
+}
+
+#[cfg(not(feature = "png"))]
+fn write_png(_svg: &str, _path: &Path) -> io::Result<()> {
+    Err(io::Error::other("steno_solver was built without the png feature"))
+}
+
+// Writes the position reached after `path` from `board` to `output`.
+pub fn write_diagram(board: Board, path: &[ChessMove], format: DiagramFormat, output: &Path) -> io::Result<()> {
+    let final_board = path.iter().fold(board, |board, &mov| board.make_move_new(mov));
+    let svg = board_svg(&final_board, path.last().copied());
+    match format {
+        DiagramFormat::Svg => fs::write(output, svg),
+        DiagramFormat::Png => write_png(&svg, output),
+    }
+}
@@ -0,0 +1,75 @@
+use std::str::FromStr;
+
+// Notations stenos are published in, read by rewriting them into ours.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    #[default]
+    Default,
+    // B for a bishop move, =Q =R =B =N for promotions.
+    English,
+    // K D T L S B for king, queen (Dame), rook (Turm), bishop (Läufer),
+    // knight (Springer) and pawn (Bauer), =D =T =L =S for promotions.
+    German,
+    // English pieces, with promotions numbered as in ICCF notation:
+    // =1 queen, =2 rook, =3 bishop, =4 knight.
+    Numeric,
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Dialect::Default),
+            "english" => Ok(Dialect::English),
+            "german" => Ok(Dialect::German),
+            "numeric" => Ok(Dialect::Numeric),
+            _ => Err(format!("Unknown dialect: {} (expected default, english, german or numeric)", s)),
+        }
+    }
+}
+
+// Written the same in every dialect but ours.
+const CASTLING_AND_EN_PASSANT: &[(&str, char)] = &[("O-O-O", '0'), ("0-0-0", '0'), ("O-O", 'o'), ("0-0", 'o'), ("e.p.", '%'), ("ep", '%')];
+
+const ENGLISH: &[(&str, char)] = &[("=Q", 'q'), ("=R", 'r'), ("=B", 'l'), ("=N", 'n'), ("B", 'L')];
+
+const GERMAN: &[(&str, char)] = &[("=D", 'q'), ("=T", 'r'), ("=L", 'l'), ("=S", 'n'), ("D", 'Q'), ("T", 'R'), ("S", 'N'), ("B", 'P')];
+
+const NUMERIC: &[(&str, char)] = &[("=1", 'q'), ("=2", 'r'), ("=3", 'l'), ("=4", 'n'), ("B", 'L')];
+
+impl Dialect {
+    fn tokens(self) -> Vec<(&'static str, char)> {
+        let own = match self {
+            Dialect::Default => return Vec::new(),
+            Dialect::English => ENGLISH,
+            Dialect::German => GERMAN,
+            Dialect::Numeric => NUMERIC,
+        };
+        let mut tokens = [CASTLING_AND_EN_PASSANT, own].concat();
+        // Longest first, so O-O-O isn't read as O-O and a stray -O.
+        tokens.sort_by_key(|(token, _)| std::cmp::Reverse(token.len()));
+        tokens
+    }
+}
+
+// The steno in our notation, one character per ply. Characters a dialect
+// doesn't give a meaning of its own mean what they do in ours.
+pub fn translate_steno(steno: &str, dialect: Dialect) -> String {
+    let tokens = dialect.tokens();
+    let mut translated = String::new();
+    let mut rest = steno;
+    while let Some(ch) = rest.chars().next() {
+        match tokens.iter().find(|(token, _)| rest.starts_with(token)) {
+            Some((token, ours)) => {
+                translated.push(*ours);
+                rest = &rest[token.len()..];
+            }
+            None => {
+                translated.push(ch);
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+    translated
+}
@@ -0,0 +1,187 @@
+use chess::{Board, BoardStatus, ChessMove, Color};
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use crate::render::san_moves;
+
+pub const DEFAULT_ENGINE_DEPTH: u32 = 12;
+
+// Drops of at least this much for the side that moved are marked `??`.
+const BLUNDER_CENTIPAWNS: i32 = 300;
+
+// An evaluation from White's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    Centipawns(i32),
+    // Mate in this many moves, negative when Black mates.
+    Mate(i32),
+}
+
+impl Score {
+    // Mates as huge scores, sooner mates larger, so drops can be compared.
+    pub(crate) fn centipawns(self) -> i32 {
+        match self {
+            Score::Centipawns(cp) => cp,
+            Score::Mate(moves) if moves > 0 => 100_000 - moves,
+            Score::Mate(moves) => -100_000 - moves,
+        }
+    }
+
+    fn negate(self) -> Score {
+        match self {
+            Score::Centipawns(cp) => Score::Centipawns(-cp),
+            Score::Mate(moves) => Score::Mate(-moves),
+        }
+    }
+}
+
+// As in a PGN `[%eval]` comment.
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Score::Centipawns(cp) => write!(f, "{:.2}", cp as f64 / 100.0),
+            Score::Mate(moves) => write!(f, "#{}", moves),
+        }
+    }
+}
+
+// A UCI engine running as a child process.
+pub struct Engine {
+    child: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+}
+
+impl Engine {
+    pub fn start(path: &Path) -> Result<Engine, String> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("Could not start {}: {}", path.display(), err))?;
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        let mut engine = Engine { child, input, output };
+        engine.send("uci")?;
+        engine.wait_for("uciok")?;
+        engine.send("isready")?;
+        engine.wait_for("readyok")?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.input, "{}", command).and_then(|_| self.input.flush()).map_err(|err| format!("Engine went away: {}", err))
+    }
+
+    fn read_line(&mut self) -> Result<String, String> {
+        let mut line = String::new();
+        match self.output.read_line(&mut line) {
+            Ok(0) => Err("Engine exited unexpectedly".to_string()),
+            Ok(_) => Ok(line.trim_end().to_string()),
+            Err(err) => Err(format!("Could not read from the engine: {}", err)),
+        }
+    }
+
+    fn wait_for(&mut self, reply: &str) -> Result<(), String> {
+        while self.read_line()? != reply {}
+        Ok(())
+    }
+
+    // Evaluates the position after `moves`, searching to `depth` plies.
+    pub fn evaluate(&mut self, fen_string: &Option<String>, moves: &[ChessMove], depth: u32) -> Result<Score, String> {
+        let moves: Vec<String> = moves.iter().map(|mov| mov.to_string()).collect();
+        let position = match fen_string {
+            Some(fen) => format!("position fen {}", fen),
+            None => "position startpos".to_string(),
+        };
+        if moves.is_empty() {
+            self.send(&position)?;
+        } else {
+            self.send(&format!("{} moves {}", position, moves.join(" ")))?;
+        }
+        self.send(&format!("go depth {}", depth))?;
+
+        // The engine scores from the side to move's point of view; the last
+        // score before bestmove is the deepest.
+        let mut score = None;
+        loop {
+            let line = self.read_line()?;
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("bestmove") => break,
+                Some("info") => {
+                    let words: Vec<&str> = words.collect();
+                    if let Some(index) = words.iter().position(|&word| word == "score") {
+                        let value = words.get(index + 2).and_then(|value| value.parse().ok());
+                        score = match (words.get(index + 1), value) {
+                            (Some(&"cp"), Some(cp)) => Some(Score::Centipawns(cp)),
+                            (Some(&"mate"), Some(moves)) => Some(Score::Mate(moves)),
+                            _ => score,
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+        let score = score.ok_or("The engine gave no score")?;
+
+        let black_to_move = fen_string.as_deref().is_some_and(|fen| fen.split(' ').nth(1) == Some("b")) != (moves.len() % 2 == 1);
+        Ok(if black_to_move { score.negate() } else { score })
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        if self.send("quit").is_err() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+// Movetext with each move's evaluation in a `[%eval]` comment; moves that
+// throw away a lot for the side that played them get `??`. Mate and
+// stalemate positions aren't given to the engine and get no comment.
+pub fn annotated_movetext(engine: &mut Engine, board: Board, fen_string: &Option<String>, path: &[ChessMove], depth: u32) -> Result<String, String> {
+    let mut scores = Vec::new();
+    let mut position = board;
+    for ply in 0..=path.len() {
+        if ply > 0 {
+            position = position.make_move_new(path[ply - 1]);
+        }
+        scores.push(match position.status() {
+            BoardStatus::Ongoing => Some(engine.evaluate(fen_string, &path[..ply], depth)?),
+            BoardStatus::Stalemate | BoardStatus::Checkmate => None,
+        });
+    }
+    // From White's point of view, for the final position the engine didn't score.
+    let final_centipawns = match position.status() {
+        BoardStatus::Checkmate if position.side_to_move() == Color::Black => 100_000,
+        BoardStatus::Checkmate => -100_000,
+        _ => 0,
+    };
+    let centipawns = |ply: usize| scores[ply].map_or(final_centipawns, Score::centipawns);
+
+    let mut move_number: u32 = fen_string.as_deref().and_then(|fen| fen.split(' ').nth(5)?.parse().ok()).unwrap_or(1);
+    let mut white_to_move = board.side_to_move() == Color::White;
+    let mut movetext = Vec::new();
+    for (ply, san) in san_moves(fen_string, path).iter().enumerate() {
+        let (before, after) = (centipawns(ply), centipawns(ply + 1));
+        let drop = if white_to_move { before - after } else { after - before };
+        let marker = if drop >= BLUNDER_CENTIPAWNS { "??" } else { "" };
+        let number = if white_to_move { format!("{}.", move_number) } else { format!("{}...", move_number) };
+        match scores[ply + 1] {
+            Some(score) => movetext.push(format!("{} {}{} {{ [%eval {}] }}", number, san, marker, score)),
+            None => movetext.push(format!("{} {}{}", number, san, marker)),
+        }
+        if !white_to_move {
+            move_number += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+    movetext.push("*".to_string());
+    Ok(movetext.join(" "))
+}
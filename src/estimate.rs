@@ -0,0 +1,149 @@
+use chess::{Board, ChessMove, MoveGen};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fmt;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use crate::steno::{check_steno_constraints, Constraint};
+
+// Normal approximation: the estimate is a sum of many independent probes.
+const Z_95: f64 = 1.96;
+
+#[derive(Clone, Debug)]
+pub struct Estimate {
+    pub solutions: f64,
+    pub solutions_margin: f64,
+    pub nodes: f64,
+    pub nodes_margin: f64,
+    // What the full search would take on one thread, extrapolated from how
+    // fast the probes themselves examined moves.
+    pub single_thread_time: Duration,
+    pub samples: usize,
+}
+
+fn format_count(count: f64) -> String {
+    if count < 1e6 {
+        format!("{:.0}", count)
+    } else {
+        format!("{:.2e}", count)
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    match seconds {
+        s if s < 60.0 => format!("{:.1}s", s),
+        s if s < 3600.0 => format!("{:.1} min", s / 60.0),
+        s if s < 86400.0 => format!("{:.1} h", s / 3600.0),
+        s => format!("{:.1} days", s / 86400.0),
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Estimated from {} random probes (95% intervals):", self.samples)?;
+        writeln!(
+            f,
+            "  Solutions: {} ({} to {})",
+            format_count(self.solutions),
+            format_count((self.solutions - self.solutions_margin).max(0.0)),
+            format_count(self.solutions + self.solutions_margin)
+        )?;
+        writeln!(
+            f,
+            "  Nodes:     {} ({} to {})",
+            format_count(self.nodes),
+            format_count((self.nodes - self.nodes_margin).max(0.0)),
+            format_count(self.nodes + self.nodes_margin)
+        )?;
+        write!(f, "  Time on one thread: about {}", format_duration(self.single_thread_time))
+    }
+}
+
+// Children of `board` that satisfy the constraint for the ply they're played at.
+fn matching_children(board: &Board, ply: usize, steno_constraints: &[Constraint], examined: &mut u64) -> (usize, Vec<Board>) {
+    let legal: Vec<ChessMove> = MoveGen::new_legal(board).collect();
+    *examined += legal.len() as u64;
+    let children = legal.iter().filter_map(|&mov| {
+        let child = board.make_move_new(mov);
+        check_steno_constraints(&child, Some((board, mov)), ply, steno_constraints, None).then_some(child)
+    }).collect();
+    (legal.len(), children)
+}
+
+// One Knuth probe: a random walk down matching moves, where each step's
+// branching factor scales the weight of everything below it. Returns unbiased
+// estimates of the nodes the search visits and the solutions it finds below
+// `board`.
+fn probe<R: Rng>(board: Board, ply: usize, steno_constraints: &[Constraint], rng: &mut R, examined: &mut u64) -> (f64, f64) {
+    let mut board = board;
+    let mut ply = ply;
+    let mut weight = 1.0;
+    let mut nodes = 0.0;
+    while ply < steno_constraints.len() {
+        let (legal, children) = matching_children(&board, ply + 1, steno_constraints, examined);
+        nodes += weight * legal as f64;
+        let Some(&child) = children.choose(rng) else {
+            return (nodes, 0.0);
+        };
+        weight *= children.len() as f64;
+        board = child;
+        ply += 1;
+    }
+    (nodes, weight)
+}
+
+fn mean_and_variance(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 {
+        return (mean, 0.0);
+    }
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, variance)
+}
+
+// Stratified by the matching root moves: each gets an equal share of the
+// probes, and the per-move estimates are summed.
+pub fn estimate_search<R: Rng>(board: Board, steno_constraints: &[Constraint], samples: usize, rng: &mut R) -> Estimate {
+    let started = Instant::now();
+    let mut examined = 0;
+
+    if steno_constraints.is_empty() {
+        return Estimate { solutions: 1.0, solutions_margin: 0.0, nodes: 1.0, nodes_margin: 0.0, single_thread_time: Duration::ZERO, samples: 0 };
+    }
+
+    let (legal, strata) = matching_children(&board, 1, steno_constraints, &mut examined);
+    let per_stratum = samples.div_ceil(strata.len().max(1)).max(2);
+
+    let mut solutions = 0.0;
+    let mut solutions_variance = 0.0;
+    let mut nodes = 1.0 + legal as f64;
+    let mut nodes_variance = 0.0;
+    for &child in &strata {
+        let (stratum_nodes, stratum_solutions): (Vec<f64>, Vec<f64>) = (0..per_stratum)
+            .map(|_| probe(child, 1, steno_constraints, rng, &mut examined))
+            .unzip();
+
+        let (mean, variance) = mean_and_variance(&stratum_nodes);
+        nodes += mean;
+        nodes_variance += variance / per_stratum as f64;
+        let (mean, variance) = mean_and_variance(&stratum_solutions);
+        solutions += mean;
+        solutions_variance += variance / per_stratum as f64;
+    }
+
+    let seconds_per_move = started.elapsed().as_secs_f64() / examined.max(1) as f64;
+    Estimate {
+        solutions,
+        solutions_margin: Z_95 * solutions_variance.sqrt(),
+        nodes,
+        nodes_margin: Z_95 * nodes_variance.sqrt(),
+        single_thread_time: Duration::from_secs_f64(nodes * seconds_per_move),
+        samples: per_stratum * strata.len(),
+    }
+}
@@ -0,0 +1,105 @@
+use chess::{Board, ChessMove, MoveGen};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tracing::warn;
+
+use crate::goal::Goal;
+use crate::memory::MemoryBudget;
+use crate::search::SolveOptions;
+use crate::stats::{peak_memory_bytes, SearchStats};
+use crate::steno::{check_steno_constraints, Constraint};
+
+struct Forced<'a> {
+    steno_constraints: &'a [Constraint],
+    goal: Option<Goal>,
+    cancel: Option<&'a AtomicBool>,
+    budget: Option<&'a MemoryBudget>,
+    // Set once the budget refuses a proof, which is then proved again when
+    // met again instead of kept.
+    over_budget: AtomicBool,
+}
+
+// What a kept proof costs, hash table overhead included.
+const PROOF_BYTES: u64 = 32;
+
+// Whether the side that moved first can make the rest of the steno happen
+// from `board`, the node at `depth`, whatever the other side replies. Its own
+// plies need one move that keeps the steno forced; the other side's need
+// every legal reply to, and at least one to exist. Results are kept per
+// position and depth, so transpositions are proved once.
+fn forced(search: &Forced, board: &Board, depth: usize, proved: &mut HashMap<(u64, usize), bool>, nodes: &mut u64) -> bool {
+    if depth == search.steno_constraints.len() {
+        return search.goal.is_none_or(|goal| goal.reached(board));
+    }
+    if search.cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+        return false;
+    }
+    if let Some(&known) = proved.get(&(board.get_hash(), depth)) {
+        return known;
+    }
+    *nodes += 1;
+
+    let mut keeps_forced = |mov: ChessMove| {
+        let child = board.make_move_new(mov);
+        check_steno_constraints(&child, Some((board, mov)), depth + 1, search.steno_constraints, None) && forced(search, &child, depth + 1, proved, nodes)
+    };
+    let mut moves = MoveGen::new_legal(board).peekable();
+    let result = if depth.is_multiple_of(2) {
+        moves.any(&mut keeps_forced)
+    } else {
+        moves.peek().is_some() && moves.all(&mut keeps_forced)
+    };
+    if search.budget.is_none_or(|budget| budget.reserve(PROOF_BYTES)) {
+        proved.insert((board.get_hash(), depth), result);
+    } else if !search.over_budget.swap(true, Ordering::Relaxed) {
+        warn!("--max-memory reached, no longer keeping forced-line proofs");
+    }
+    result
+}
+
+// The first moves ("keys") after which the steno is forced: every reply the
+// other side has satisfies it, and the side to move can always go on
+// satisfying it, up to the goal if there is one. Each key is proved on a
+// worker of its own. Repetition, the fifty-move rule and constraints on the
+// plies before are never met.
+pub fn solve_forced(board: Board, steno_constraints: &[Constraint], options: &SolveOptions) -> (Vec<ChessMove>, SearchStats) {
+    let search = Forced {
+        steno_constraints,
+        goal: options.goal,
+        cancel: options.cancel.as_deref(),
+        budget: options.memory_budget.as_deref(),
+        over_budget: AtomicBool::new(false),
+    };
+    let started = Instant::now();
+    let moves: Vec<ChessMove> = MoveGen::new_legal(&board).collect();
+
+    #[cfg(feature = "parallel")]
+    let candidates = moves.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let candidates = moves.iter();
+
+    let results: Vec<(ChessMove, bool, u64)> = candidates.map(|&mov| {
+        let (mut proved, mut nodes) = (HashMap::new(), 0);
+        let child = board.make_move_new(mov);
+        let is_key = !steno_constraints.is_empty()
+            && check_steno_constraints(&child, Some((&board, mov)), 1, steno_constraints, None)
+            && forced(&search, &child, 1, &mut proved, &mut nodes);
+        if let Some(budget) = search.budget {
+            budget.release(proved.len() as u64 * PROOF_BYTES);
+        }
+        (mov, is_key, nodes)
+    }).collect();
+
+    let keys: Vec<ChessMove> = results.iter().filter(|(_, is_key, _)| *is_key).map(|(mov, _, _)| *mov).collect();
+    let stats = SearchStats {
+        nodes_visited: 1 + results.iter().map(|(_, _, nodes)| nodes).sum::<u64>(),
+        solutions: keys.len() as u64,
+        elapsed: started.elapsed(),
+        peak_memory: peak_memory_bytes(),
+        ..SearchStats::default()
+    };
+    (keys, stats)
+}
@@ -0,0 +1,67 @@
+use chess::{Board, BoardStatus, MoveGen};
+use serde::{Deserialize, Serialize};
+use std::iter;
+use std::str::FromStr;
+
+use crate::steno::Constraint;
+
+// What the last ply of a problem has to achieve, on top of its constraint.
+// The side to move at the start plays first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Goal {
+    // The other side mates with the last ply, both sides helping.
+    Helpmate,
+    // The side to move plays the last ply, after which every reply the
+    // other side has mates it. Only that reply is forced; the plies before
+    // it are found cooperatively.
+    Selfmate,
+    // The other side stalemates with the last ply, both sides helping.
+    Stalemate,
+}
+
+impl FromStr for Goal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "helpmate" => Ok(Goal::Helpmate),
+            "selfmate" => Ok(Goal::Selfmate),
+            "stalemate" => Ok(Goal::Stalemate),
+            _ => Err(format!("Unknown goal: {} (expected helpmate, selfmate or stalemate)", s)),
+        }
+    }
+}
+
+impl Goal {
+    // Plies in a problem of `moves` moves: each side's moves for a help
+    // problem, and for a selfmate all but the forced reply.
+    pub fn plies(self, moves: usize) -> usize {
+        match self {
+            Goal::Helpmate | Goal::Stalemate => 2 * moves,
+            Goal::Selfmate => (2 * moves).saturating_sub(1),
+        }
+    }
+
+    // Whether `board`, where the last ply left it, meets the goal.
+    pub fn reached(self, board: &Board) -> bool {
+        match self {
+            Goal::Helpmate => board.status() == BoardStatus::Checkmate,
+            Goal::Stalemate => board.status() == BoardStatus::Stalemate,
+            Goal::Selfmate => {
+                let mut replies = MoveGen::new_legal(board).peekable();
+                replies.peek().is_some() && replies.all(|reply| board.make_move_new(reply).status() == BoardStatus::Checkmate)
+            }
+        }
+    }
+}
+
+// The steno of a problem in `moves` moves: a shorter one constrains its last
+// plies, the ones before it left free.
+pub fn goal_steno(steno_constraints: Vec<Constraint>, goal: Goal, moves: usize) -> Result<Vec<Constraint>, String> {
+    let plies = goal.plies(moves);
+    if steno_constraints.len() > plies {
+        return Err(format!("A {:?} in {} is {} plies, but the steno has {}", goal, moves, plies, steno_constraints.len()));
+    }
+    Ok(iter::repeat_n(Constraint::Any, plies - steno_constraints.len()).chain(steno_constraints).collect())
+}
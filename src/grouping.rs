@@ -0,0 +1,79 @@
+use chess::ChessMove;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::render::{render_solution, OutputFormat};
+
+// The solutions that open with the same moves.
+#[derive(Clone, Debug)]
+pub struct OpeningGroup {
+    pub opening: Vec<ChessMove>,
+    pub solutions: u64,
+    // The first in UCI order, so it doesn't depend on which worker found what.
+    pub representative: Vec<ChessMove>,
+    // Every solution, when the groups keep them.
+    pub games: Vec<Vec<ChessMove>>,
+}
+
+// Fed every solution from the search callback, and grouped by its first
+// `plies` moves.
+pub struct OpeningGroups {
+    plies: usize,
+    keep_games: bool,
+    groups: Mutex<HashMap<Vec<ChessMove>, OpeningGroup>>,
+}
+
+fn uci_key(path: &[ChessMove]) -> Vec<String> {
+    path.iter().map(|mov| mov.to_string()).collect()
+}
+
+impl OpeningGroups {
+    // Without `keep_games` only the count and representative of each group
+    // are kept.
+    pub fn new(plies: usize, keep_games: bool) -> OpeningGroups {
+        OpeningGroups { plies, keep_games, groups: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn plies(&self) -> usize {
+        self.plies
+    }
+
+    pub fn record(&self, path: &[ChessMove]) {
+        let opening = &path[..self.plies.min(path.len())];
+        let mut groups = self.groups.lock().unwrap();
+        let group = groups.entry(opening.to_vec()).or_insert_with(|| OpeningGroup {
+            opening: opening.to_vec(),
+            solutions: 0,
+            representative: path.to_vec(),
+            games: Vec::new(),
+        });
+        group.solutions += 1;
+        if uci_key(path) < uci_key(&group.representative) {
+            group.representative = path.to_vec();
+        }
+        if self.keep_games {
+            group.games.push(path.to_vec());
+        }
+    }
+
+    // Biggest first, ties in UCI order of their openings; each group's games
+    // in UCI order.
+    pub fn into_groups(self) -> Vec<OpeningGroup> {
+        let mut groups: Vec<OpeningGroup> = self.groups.into_inner().unwrap().into_values().collect();
+        for group in &mut groups {
+            group.games.sort_by_key(|path| uci_key(path));
+        }
+        groups.sort_by(|a, b| b.solutions.cmp(&a.solutions).then_with(|| uci_key(&a.opening).cmp(&uci_key(&b.opening))));
+        groups
+    }
+}
+
+// One line per group: its size, its opening in SAN and its representative
+// in `format`.
+pub fn render_opening_groups(fen_string: &Option<String>, groups: &[OpeningGroup], format: OutputFormat) -> String {
+    let width = groups.first().map_or(1, |group| group.solutions.to_string().len());
+    groups.iter().map(|group| {
+        let opening = render_solution(fen_string, &group.opening, OutputFormat::San);
+        format!("{:>width$} {}, e.g. {}\n", group.solutions, opening, render_solution(fen_string, &group.representative, format), width = width)
+    }).collect()
+}
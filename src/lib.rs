@@ -0,0 +1,127 @@
+mod bench;
+mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "online")]
+mod chesscom;
+#[cfg(feature = "san")]
+mod collection;
+#[cfg(feature = "server")]
+mod cluster;
+mod completions;
+mod compose;
+mod config;
+mod count;
+mod dedup;
+mod diagram;
+mod dialect;
+#[cfg(feature = "engine")]
+mod engine;
+mod estimate;
+mod forced;
+mod goal;
+mod grouping;
+#[cfg(feature = "online")]
+mod lichess;
+mod memory;
+#[cfg(feature = "server")]
+mod metrics;
+mod opening;
+mod order;
+mod parity;
+#[cfg(feature = "san")]
+mod pgn;
+#[cfg(feature = "san")]
+mod pin;
+mod profile;
+mod render;
+#[cfg(feature = "san")]
+mod replay;
+mod report;
+#[cfg(feature = "san")]
+mod san;
+#[cfg(feature = "script")]
+mod script;
+mod search;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "san")]
+mod sort;
+mod spec;
+mod stats;
+mod steno;
+#[cfg(feature = "async")]
+mod stream;
+mod suggest;
+#[cfg(feature = "syzygy")]
+mod syzygy;
+mod target;
+#[cfg(feature = "tui")]
+mod tui;
+mod writer;
+// Build with `cargo build --lib --target wasm32-unknown-unknown --no-default-features --features wasm`,
+// adding `san` for formats other than uci.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm;
+
+#[cfg(all(feature = "wasm", feature = "parallel", target_arch = "wasm32"))]
+compile_error!("the wasm feature is single-threaded; build it with --no-default-features");
+
+pub use bench::{run_bench, BenchResult, BENCH_SUITE};
+pub use cache::{cache_dir, CachedResult, ResultCache, MAX_CACHED_SOLUTIONS};
+#[cfg(feature = "online")]
+pub use chesscom::fetch_chesscom_game;
+#[cfg(feature = "server")]
+pub use cluster::{coordinate, work, ClusterResult, CoordinatorConfig, WorkerStats};
+#[cfg(feature = "san")]
+pub use collection::{builtin_puzzles, load_collection, verify_collection, verify_puzzle, Puzzle, PuzzleStatus};
+pub use completions::{cli_schema, completion_script, Shell};
+pub use compose::{continue_positions, random_game, read_prefix_positions, render_prefix_positions, steno_for_game, weaken_to_unique, ContinuedPosition};
+pub use config::{config_path, Config};
+pub use count::{color_symmetric, prefix_positions, promotion_class_key, solve_from_starts, CountBy, DistinctCounter, MirrorCounter, SolutionDigest};
+pub use dedup::{BloomFilter, SpillSet};
+pub use diagram::{board_svg, write_diagram, DiagramFormat};
+pub use dialect::{translate_steno, Dialect};
+#[cfg(feature = "engine")]
+pub use engine::{annotated_movetext, Engine, Score, DEFAULT_ENGINE_DEPTH};
+pub use estimate::{estimate_search, Estimate};
+pub use forced::solve_forced;
+pub use goal::{goal_steno, Goal};
+pub use grouping::{render_opening_groups, OpeningGroup, OpeningGroups};
+#[cfg(feature = "online")]
+pub use lichess::{export_to_study, fetch_lichess_game, MAX_STUDY_CHAPTERS};
+pub use memory::{parse_memory_size, MemoryBudget};
+pub use opening::OpeningIndex;
+pub use order::MoveOrder;
+#[cfg(feature = "san")]
+pub use pgn::{moves_from_san, parse_pgn, PgnGame};
+#[cfg(feature = "san")]
+pub use pin::{matches_pins, parse_pins, pin_steno, Pin};
+pub use profile::chrome_trace;
+pub use render::{board_diagram, format_solution_id, render_series, render_solution, render_with_boards, solution_id, tag_solution_id, OutputFormat, ShowBoards};
+#[cfg(feature = "san")]
+pub use render::{pgn_movetext, pgn_with_movetext, render_broadcast, render_pgn, render_tree, san_moves};
+#[cfg(feature = "san")]
+pub use replay::replay_check;
+pub use report::{constraint_report, BranchingReport};
+#[cfg(feature = "script")]
+pub use script::{Script, SCRIPT_LANGUAGE};
+pub use search::{count_solutions, default_split_ply, perft, solve, solve_after_prefix, solve_two_stage, solve_with_callback, Shard, Solution, SolutionHook, SolveOptions, DEFAULT_TASKS_PER_WORKER, SHARD_PLY};
+#[cfg(not(target_arch = "wasm32"))]
+pub use search::{solve_in_background, SolveHandle};
+#[cfg(feature = "server")]
+pub use server::{serve, ServerConfig};
+#[cfg(feature = "san")]
+pub use sort::{sort_solutions, SolutionSort};
+pub use spec::{load_spec, spec_constraints, PlySpec, PuzzleSpec};
+pub use stats::{SearchStats, TaskTime};
+pub use steno::{check_steno_length, explain_steno, halfmove_clock, intersect_stenos, lint_steno, parse_steno_string, parse_steno_with, steno_string, verify_game, CastleSide, Constraint, Lint, MoveContext, Zone, CONSTRAINT_LANGUAGE, DEFAULT_MAX_PLIES};
+#[cfg(feature = "async")]
+pub use stream::{CancellationToken, SolutionStream, Solver};
+pub use suggest::{suggest_unique, Suggestion, MAX_SUGGEST_SOLUTIONS};
+#[cfg(feature = "syzygy")]
+pub use syzygy::{load_syzygy, probes_tablebase, syzygy_loaded, TablebaseOutcome};
+pub use target::{TargetMatch, TargetPosition};
+#[cfg(feature = "tui")]
+pub use tui::explore;
+pub use writer::{SolutionWriter, Tee};
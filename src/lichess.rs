@@ -0,0 +1,79 @@
+use serde_json::Value;
+use std::thread;
+use std::time::Duration;
+
+use crate::pgn::PgnGame;
+
+const LICHESS_API: &str = "https://lichess.org/api";
+// Lichess refuses to add chapters past this many per study.
+pub const MAX_STUDY_CHAPTERS: usize = 64;
+const MAX_RETRIES: u32 = 5;
+
+// Lichess asks API clients to wait a full minute after a 429 unless told otherwise.
+fn retry_delay(response: &ureq::Response) -> Duration {
+    let seconds = response.header("Retry-After").and_then(|value| value.parse().ok()).unwrap_or(60);
+    Duration::from_secs(seconds)
+}
+
+fn import_chapter(token: &str, study_id: &str, name: &str, pgn: &str) -> Result<(), String> {
+    let url = format!("{}/study/{}/import-pgn", LICHESS_API, study_id);
+    let mut attempts = 0;
+    loop {
+        let result = ureq::post(&url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .send_form(&[("name", name), ("pgn", pgn)]);
+        match result {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(429, response)) if attempts < MAX_RETRIES => {
+                let delay = retry_delay(&response);
+                eprintln!("Rate limited by Lichess, retrying in {}s", delay.as_secs());
+                thread::sleep(delay);
+                attempts += 1;
+            }
+            Err(ureq::Error::Status(status, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                return Err(format!("Lichess returned {} for chapter {}: {}", status, name, body.trim()));
+            }
+            Err(err) => return Err(err.to_string()),
+        }
+    }
+}
+
+// Uploads each PGN as its own chapter named "<chapter_name> #<n>". Returns
+// how many chapters were created; solutions past the study limit are skipped.
+pub fn export_to_study(token: &str, study_id: &str, chapter_name: &str, games: &[String]) -> Result<usize, String> {
+    if games.len() > MAX_STUDY_CHAPTERS {
+        eprintln!("Lichess studies hold at most {} chapters, exporting the first {} of {} solutions", MAX_STUDY_CHAPTERS, MAX_STUDY_CHAPTERS, games.len());
+    }
+
+    let games = &games[..games.len().min(MAX_STUDY_CHAPTERS)];
+    for (index, pgn) in games.iter().enumerate() {
+        import_chapter(token, study_id, &format!("{} #{}", chapter_name, index + 1), pgn)?;
+    }
+    Ok(games.len())
+}
+
+// Accepts a bare game id or any lichess.org game URL (including the
+// 12-character player-specific ids and /white or /black suffixes).
+fn lichess_game_id(game: &str) -> Result<String, String> {
+    let path = game.split("lichess.org/").nth(1).unwrap_or(game);
+    let id: String = path.split(['/', '?', '#']).next().unwrap_or("").chars().take(8).collect();
+    if id.len() == 8 && id.chars().all(|ch| ch.is_ascii_alphanumeric()) {
+        Ok(id)
+    } else {
+        Err(format!("Not a Lichess game id or URL: {}", game))
+    }
+}
+
+pub fn fetch_lichess_game(game: &str) -> Result<PgnGame, String> {
+    let id = lichess_game_id(game)?;
+    let url = format!("https://lichess.org/game/export/{}?clocks=false&evals=false&opening=false", id);
+    let response = ureq::get(&url).set("Accept", "application/json").call().map_err(|err| err.to_string())?;
+    let game: Value = response.into_string().map_err(|err| err.to_string()).and_then(|body| serde_json::from_str(&body).map_err(|err| err.to_string()))?;
+
+    let moves = game["moves"].as_str().ok_or_else(|| format!("Lichess game {} has no moves", id))?;
+    Ok(PgnGame {
+        fen: game["initialFen"].as_str().map(str::to_string),
+        san_moves: moves.split_whitespace().map(str::to_string).collect(),
+    })
+}
@@ -1,192 +1,2293 @@
-use chess::{Board, ChessMove, MoveGen, Piece, BoardStatus, Square};
-use shakmaty::{Chess, Position, uci::Uci, san::San, fen::Fen, fen, CastlingMode};
-use rayon::prelude::*;
-use std::sync::Mutex;
-use std::env;
-use std::io;
-use std::io::Write;
+use chess::{Board, ChessMove};
+use clap::{ArgAction, ArgGroup, Args, CommandFactory, Parser, Subcommand};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use steno_solver::{builtin_puzzles, cache_dir, chrome_trace, cli_schema, completion_script, solve_from_starts, sort_solutions, continue_positions, explain_steno, halfmove_clock, read_prefix_positions, render_prefix_positions, check_steno_length, color_symmetric, goal_steno, solve_forced, constraint_report, count_solutions, format_solution_id, default_split_ply, estimate_search, intersect_stenos, lint_steno, load_collection, load_spec, matches_pins, moves_from_san, parse_pgn, parse_steno_string, parse_steno_with, parse_memory_size, parse_pins, perft, pin_steno, prefix_positions, promotion_class_key, random_game, annotated_movetext, pgn_with_movetext, render_broadcast, render_opening_groups, render_pgn, render_series, render_solution, render_tree, render_with_boards, replay_check, run_bench, solution_id, solve_two_stage, solve_with_callback, spec_constraints, steno_for_game, steno_string, suggest_unique, tag_solution_id, translate_steno, verify_collection, verify_game, weaken_to_unique, write_diagram, BloomFilter, BranchingReport, MirrorCounter, Config, Pin, SolutionDigest, Engine, Constraint, CountBy, DiagramFormat, Dialect, DistinctCounter, Goal, MemoryBudget, MoveOrder, OpeningGroup, OpeningGroups, OpeningIndex, ResultCache, SearchStats, CachedResult, Shell, OutputFormat, PgnGame, PuzzleStatus, Shard, ShowBoards, SolutionSort, SolutionWriter, SolveOptions, SpillSet, TargetMatch, TargetPosition, CONSTRAINT_LANGUAGE, DEFAULT_ENGINE_DEPTH, DEFAULT_MAX_PLIES, DEFAULT_TASKS_PER_WORKER, MAX_CACHED_SOLUTIONS};
+#[cfg(feature = "server")]
+use steno_solver::{coordinate, serve, work, CoordinatorConfig, ServerConfig, SHARD_PLY};
+#[cfg(feature = "tui")]
+use steno_solver::explore;
+#[cfg(feature = "script")]
+use steno_solver::{Script, SCRIPT_LANGUAGE};
+#[cfg(feature = "syzygy")]
+use steno_solver::{load_syzygy, probes_tablebase};
+#[cfg(feature = "online")]
+use steno_solver::{export_to_study, fetch_chesscom_game, fetch_lichess_game};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::str::FromStr;
-use std::error::Error;
-
-fn parse_steno_string(steno: &str) -> Result<Vec<char>, String> {
-    let valid_chars = [
-        '~', '1', '2', '3', '4', '5', '6', '7', '8', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'x',
-        '+', '#', 'L', 'N', 'R', 'Q', 'K', 'P', '%', '=', 'o', '0', 'r', 'n', 'l', 'q'
-    ];
-    let mut parsed_chars = Vec::new();
-
-    for ch in steno.chars() {
-        if valid_chars.contains(&ch) {
-            parsed_chars.push(ch);
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::Level;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+// Runs `f` on a pool of `threads` workers (one per core by default) and
+// returns how many workers it had.
+#[cfg(feature = "parallel")]
+fn with_threads<T: Send>(threads: Option<usize>, f: impl FnOnce() -> T + Send) -> (T, usize) {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads.unwrap_or(0)).build().unwrap();
+    (pool.install(f), pool.current_num_threads())
+}
+
+#[cfg(not(feature = "parallel"))]
+fn with_threads<T>(_threads: Option<usize>, f: impl FnOnce() -> T) -> (T, usize) {
+    (f(), 1)
+}
+
+fn steno_arg(steno: &str) -> Result<String, String> {
+    parse_steno_string(steno).map(|_| steno.to_string())
+}
+
+#[cfg(feature = "script")]
+fn define_arg(define: &str) -> Result<(char, Constraint), String> {
+    let (name, source) = define.split_once('=').ok_or_else(|| format!("Expected CHAR=PREDICATE, got {}", define))?;
+    let mut chars = name.chars();
+    let (Some(ch), None) = (chars.next(), chars.next()) else {
+        return Err(format!("A defined constraint has to be a single character, not '{}'", name));
+    };
+    if Constraint::from_char(ch).is_some() {
+        return Err(format!("'{}' is already a built-in constraint", ch));
+    }
+    Ok((ch, Constraint::Custom(ch, Arc::new(Script::parse(source)?))))
+}
+
+#[cfg(not(feature = "script"))]
+fn define_arg(_define: &str) -> Result<(char, Constraint), String> {
+    Err("steno_solver was built without the script feature".to_string())
+}
+
+fn fen_arg(fen: &str) -> Result<String, String> {
+    Board::from_str(fen).map(|_| fen.to_string()).map_err(|err| format!("Invalid FEN: {}", err))
+}
+
+// The positions of a --fen-file, with their halfmove clocks and FENs as
+// written. Blank lines and lines starting with # are skipped.
+fn read_fen_file(path: &PathBuf) -> Result<Vec<(Board, u32, String)>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    contents.lines().enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, fen)| {
+            let board = Board::from_str(fen).map_err(|err| format!("{}:{}: Invalid FEN: {}", path.display(), index + 1, err))?;
+            Ok((board, halfmove_clock(&Some(fen.to_string())), fen.to_string()))
+        })
+        .collect()
+}
+
+// A steno made to start at game ply `start_ply`. From a FEN, that is the
+// position at that ply, so its side to move and move number are set to match,
+// dropping an en passant square that no longer applies; from the initial
+// position, the plies before it match any move.
+fn at_start_ply(steno_constraints: Vec<Constraint>, fen_string: Option<String>, start_ply: Option<u32>) -> Result<(Vec<Constraint>, Option<String>), String> {
+    let Some(start_ply) = start_ply else {
+        return Ok((steno_constraints, fen_string));
+    };
+    let Some(fen) = fen_string else {
+        let free = iter::repeat_n(Constraint::Any, start_ply as usize - 1);
+        return Ok((free.chain(steno_constraints).collect(), None));
+    };
+    let mut fields: Vec<String> = fen.split_whitespace().map(str::to_string).collect();
+    fields.resize(6, "-".to_string());
+    let side = if start_ply % 2 == 1 { "w" } else { "b" };
+    if fields[1] != side {
+        fields[1] = side.to_string();
+        fields[3] = "-".to_string();
+    }
+    if fields[4] == "-" {
+        fields[4] = "0".to_string();
+    }
+    fields[5] = start_ply.div_ceil(2).to_string();
+    let fen = fields.join(" ");
+    Board::from_str(&fen).map_err(|err| format!("--start-ply {} puts {} to move, which can't be: {}", start_ply, if side == "w" { "White" } else { "Black" }, err))?;
+    Ok((steno_constraints, Some(fen)))
+}
+
+// What a game kept in memory costs, for the --max-memory budget.
+fn game_bytes(path: &[ChessMove]) -> u64 {
+    (mem::size_of::<Vec<ChessMove>>() + mem::size_of_val(path)) as u64
+}
+
+// The one generator a run draws from, reproducible given its seed.
+fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+fn start_board(fen_string: &Option<String>) -> Result<Board, String> {
+    match fen_string {
+        Some(fen) => Board::from_str(fen).map_err(|err| format!("Invalid FEN: {}", err)),
+        None => Ok(Board::default()),
+    }
+}
+
+// Exit statuses scripts can rely on. Malformed arguments, invalid stenos
+// and FENs included, are rejected by clap with EXIT_INVALID_STENO.
+const EXIT_NO_SOLUTIONS: u8 = 1;
+const EXIT_INVALID_STENO: u8 = 2;
+const EXIT_RUNTIME_ERROR: u8 = 3;
+
+fn runtime_error(message: impl Display) -> ExitCode {
+    eprintln!("{}", message);
+    ExitCode::from(EXIT_RUNTIME_ERROR)
+}
+
+fn found_exit(found: bool) -> ExitCode {
+    if found {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_NO_SOLUTIONS)
+    }
+}
+
+#[derive(Parser)]
+#[command(name = "steno_solver", version, about = "Finds every chess game that matches a steno, one constraint per ply")]
+#[command(long_about = "Finds every chess game that matches a steno, one constraint per ply.
+
+Defaults for --threads, --format, --limit, --max-plies and --cache are read from
+~/.config/steno-solver/config.toml (or $STENO_SOLVER_CONFIG) and from the STENO_SOLVER_THREADS,
+STENO_SOLVER_FORMAT, STENO_SOLVER_LIMIT, STENO_SOLVER_MAX_PLIES and STENO_SOLVER_CACHE environment variables. Flags on the command line take precedence.
+
+Exit status: 0 when at least one solution was found, 1 when there were none, 2 for an invalid
+steno or other bad arguments, and 3 for runtime errors.")]
+#[command(after_help = CONSTRAINT_LANGUAGE, args_conflicts_with_subcommands = true, arg_required_else_help = true)]
+struct Cli {
+    /// Log more: -v for per-branch timings, -vv for every pruned move
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Print nothing but errors; the exit status tells whether solutions exist
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print the options of every subcommand and the constraint language as JSON, for front ends
+    #[arg(long, exclusive = true)]
+    print_schema: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    solve: Option<SolveArgs>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Find every game matching a steno (the default when no subcommand is given)
+    #[command(after_help = CONSTRAINT_LANGUAGE)]
+    Solve(Box<SolveArgs>),
+    /// Check that a game satisfies a steno and whether it is the only solution
+    #[command(after_help = CONSTRAINT_LANGUAGE)]
+    Verify(VerifyArgs),
+    /// Check that every puzzle in a collection file is solvable, unique and solved by its intended solution
+    VerifyCollection(VerifyCollectionArgs),
+    /// Join the outputs of --shard runs, dropping solutions printed more than once
+    Merge(MergeArgs),
+    /// Compare the solutions of two runs by solution ID; exits 1 when they differ
+    Diff(DiffArgs),
+    /// Check a steno for plies that can never be satisfied, without solving it
+    #[command(after_help = CONSTRAINT_LANGUAGE)]
+    Lint(LintArgs),
+    /// Say in words what each ply of a steno asks for, and whose move it is
+    #[command(after_help = CONSTRAINT_LANGUAGE)]
+    Explain(LintArgs),
+    /// Propose the fewest ply changes that make a steno with several solutions unique
+    #[command(after_help = CONSTRAINT_LANGUAGE)]
+    Suggest(SuggestArgs),
+    /// Derive the steno of a Lichess, Chess.com or PGN game
+    FromGame(FromGameArgs),
+    /// Play a random game and print its steno
+    Generate(GenerateArgs),
+    /// Solve today's built-in steno puzzle, or a random one, entering the moves in SAN
+    Play(PlayArgs),
+    /// Count the games of a prefix steno followed by a suffix, searching the suffix from each position the prefix ends in
+    #[command(after_help = CONSTRAINT_LANGUAGE)]
+    Compose(ComposeArgs),
+    /// Count leaf positions to a fixed depth
+    Perft(PerftArgs),
+    /// Write an index of every game from the initial position, for solve --opening-index
+    Index(IndexArgs),
+    /// Run the built-in benchmark suite
+    Bench(BenchArgs),
+    /// Manage the result cache used by solve --cache
+    Cache(CacheArgs),
+    /// Print a shell completion script, e.g. `steno_solver completions bash > /etc/bash_completion.d/steno_solver`
+    Completions(CompletionsArgs),
+    /// Serve solve jobs over HTTP, or coordinate a cluster of workers solving one steno
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+    /// Search tasks handed out by a `serve --coordinator` until the steno is solved
+    #[cfg(feature = "server")]
+    Work(WorkArgs),
+}
+
+#[derive(Args)]
+struct SolveArgs {
+    /// Steno string, one constraint character per ply
+    #[arg(required_unless_present = "spec", default_value = "", hide_default_value = true)]
+    steno: String,
+    /// Read the steno from a JSON spec instead, each ply an object of piece, dest, dest_file, dest_rank, capture, check, mate, escapes_check, promotion and flags, with an optional fen
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["steno", "dialect", "watch"])]
+    spec: Option<PathBuf>,
+    /// Make CHAR a constraint for the moves matching PREDICATE, e.g. Z=captured == QUEEN
+    #[arg(long, value_name = "CHAR=PREDICATE", value_parser = define_arg)]
+    #[cfg_attr(feature = "script", arg(long_help = SCRIPT_LANGUAGE))]
+    define: Vec<(char, Constraint)>,
+    /// Directory of Syzygy tables for `$` and `_` to probe (repeatable)
+    #[cfg(feature = "syzygy")]
+    #[arg(long, value_name = "DIR")]
+    syzygy_path: Vec<PathBuf>,
+    /// Also match this steno of the same length, ply by ply (repeatable)
+    #[arg(long = "and", value_name = "STENO")]
+    and_stenos: Vec<String>,
+    /// Notation the stenos are written in: english (B bishop, =Q promotions), german (K D T L S B, =D promotions) or numeric (=1 to =4 promotions); all but default also take O-O, 0-0-0 and ep
+    #[arg(long, value_name = "default|english|german|numeric", default_value = "default")]
+    dialect: Dialect,
+    /// Start from this position instead of the initial one
+    #[arg(long, value_parser = fen_arg)]
+    fen: Option<String>,
+    /// Game ply the steno's first ply is: the side to move and move number of --fen are set to match, and without --fen the plies before it can be anything
+    #[arg(long, value_name = "K", value_parser = clap::value_parser!(u32).range(1..), conflicts_with_all = ["series", "fen_file"])]
+    start_ply: Option<u32>,
+    /// Solve from every FEN in this file, one per line, and print how many solutions each has
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["fen", "tui", "forced", "prefix_depth", "two_stage", "opening_index", "symmetry", "annotate", "broadcast", "export_study", "diagrams", "show_boards", "sort", "head", "limit", "dedup_final", "dedup_approx", "collapse_promotions", "branching_report", "estimate_first"])]
+    fen_file: Option<PathBuf>,
+    /// How to print each solution, or all of them as one variation tree [default: url, or san for --series]
+    #[arg(long, value_name = "url|san|uci|pgn|tree")]
+    format: Option<OutputFormat>,
+    /// Stop after this many solutions
+    #[arg(long, value_name = "N")]
+    limit: Option<u64>,
+    /// Print only the first N solutions, but search on for the full count
+    #[arg(long, value_name = "N", conflicts_with = "limit")]
+    head: Option<u64>,
+    /// Treat STENO as a file holding the steno, and solve it again each time the file changes, printing the count and the first --head solutions [default head: 5]
+    #[arg(long, conflicts_with_all = ["tui", "forced", "fen_file", "prefix_depth", "annotate", "broadcast", "sort", "cluster", "export_study", "diagrams", "branching_report", "estimate_first"])]
+    watch: bool,
+    /// Write where the search spent its time to FILE, per task, per ply and per first move, as a Chrome trace for chrome://tracing, Perfetto or speedscope
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["tui", "watch", "forced", "fen_file", "prefix_depth", "two_stage", "opening_index"])]
+    profile: Option<PathBuf>,
+    /// Fix plies to moves, numbered as in PGN, e.g. "1.e4 ... 5.Nxe5" or "3...Nc6"; the other plies are searched under the steno (repeatable)
+    #[arg(long, value_name = "MOVES", conflicts_with_all = ["tui", "watch", "forced", "fen_file", "prefix_depth", "series"])]
+    pin: Vec<String>,
+    /// Solve twice, on different numbers of threads, and check both find the same solutions, instead of printing them
+    #[arg(long, conflicts_with_all = ["tui", "watch", "forced", "fen_file", "prefix_depth", "limit", "dedup_final", "dedup_approx", "collapse_promotions", "only_ids", "estimate_first"])]
+    repro_check: bool,
+    /// Search even when the steno provably has no solutions, instead of saying why and stopping
+    #[arg(long)]
+    force: bool,
+    /// Replay every solution the search reports through a second move generator and check the steno on it again, reporting any that don't hold up
+    #[arg(long, conflicts_with_all = ["tui", "watch", "forced", "fen_file", "prefix_depth", "series"])]
+    self_check: bool,
+    /// Also write everything printed to standard output to FILE (repeatable)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["tui", "watch", "forced", "fen_file", "prefix_depth"])]
+    tee: Vec<PathBuf>,
+    /// Refuse stenos longer than this many plies [default: 400]
+    #[arg(long, value_name = "N")]
+    max_plies: Option<usize>,
+    /// Print the solutions as PGN with engine evaluations and blunders marked, once the search is done
+    #[arg(long, conflicts_with_all = ["format", "tui", "show_boards"])]
+    annotate: bool,
+    /// Print the solutions as PGN games for a Lichess broadcast round named EVENT, one board per solution, once the search is done
+    #[arg(long, value_name = "EVENT", conflicts_with_all = ["format", "annotate", "tui", "show_boards"])]
+    broadcast: Option<String>,
+    /// UCI engine for --annotate and --sort engine-eval [default: engine from the config]
+    #[arg(long, value_name = "PATH")]
+    engine: Option<PathBuf>,
+    /// Search depth for each evaluation
+    #[arg(long, value_name = "PLIES", default_value_t = DEFAULT_ENGINE_DEPTH)]
+    engine_depth: u32,
+    /// Print the solutions once the search is done, fewest movetext characters, most captures, best final evaluation for the last mover, or SAN order first
+    #[arg(long, value_name = "shortest-pgn|most-captures|engine-eval|alphabetical", conflicts_with_all = ["broadcast", "tui", "forced", "prefix_depth"])]
+    sort: Option<SolutionSort>,
+    /// Group the solutions by their first PLIES moves and print each group's size with one of its games, biggest first, once the search is done
+    #[arg(long, value_name = "PLIES", conflicts_with_all = ["sort", "annotate", "broadcast", "tui", "forced", "series", "prefix_depth", "show_boards"])]
+    cluster: Option<usize>,
+    /// Also write each --cluster group's games to DIR/cluster_NNN.pgn, biggest group first
+    #[arg(long, value_name = "DIR", requires = "cluster")]
+    cluster_dir: Option<PathBuf>,
+    /// Worker threads (defaults to one per core)
+    #[arg(long, value_name = "N")]
+    threads: Option<usize>,
+    /// Order to search each position's moves in; changes which solutions come first, not which exist
+    #[arg(long, value_name = "default|checks-first|captures-first|random|constraint-guided", default_value = "default")]
+    move_order: MoveOrder,
+    /// Seed for --move-order random and the --estimate-first probes, so a run can be repeated exactly
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+    /// Only search the I-th of N disjoint parts of the games, split by their first moves; `merge` joins the outputs
+    #[arg(long, value_name = "I/N", conflicts_with = "prefix_depth")]
+    shard: Option<Shard>,
+    /// Work-queue tasks to split the search into per thread; more balances better, fewer costs less
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_TASKS_PER_WORKER)]
+    task_granularity: usize,
+    /// Also count distinct final positions, or solution classes that only differ in move order
+    #[arg(long, value_name = "games|positions|classes", default_value = "games")]
+    count_by: CountBy,
+    /// Print only the first solution to reach each final position
+    #[arg(long)]
+    dedup_final: bool,
+    /// Count and print solutions that only differ in a promotion piece the steno doesn't ask for once
+    #[arg(long)]
+    collapse_promotions: bool,
+    /// Only keep games whose last ply mates, stalemates, or leaves the other side only mating replies
+    #[arg(long, value_name = "helpmate|selfmate|stalemate")]
+    goal: Option<Goal>,
+    /// Number of moves in the --goal problem; a shorter steno constrains its last plies
+    #[arg(long = "in", value_name = "N", requires = "goal")]
+    goal_moves: Option<usize>,
+    /// Print the first moves after which the side to move can force the steno whatever the other side replies
+    #[arg(long, conflicts_with_all = ["two_stage", "series", "final_fen", "tui", "symmetry", "prefix_depth", "dedup_final", "dedup_approx", "collapse_promotions", "annotate", "broadcast", "export_study", "diagrams", "show_boards", "branching_report", "limit"])]
+    forced: bool,
+    /// Solve a series: the side to move plays every ply of the steno while the other passes
+    #[arg(long, conflicts_with_all = ["two_stage", "final_fen", "dedup_final", "dedup_approx", "show_boards", "diagrams", "annotate", "broadcast", "export_study", "tui", "symmetry", "prefix_depth"])]
+    series: bool,
+    /// Skip lines once a position occurs for the third time, except at plies asking for it with @
+    #[arg(long)]
+    prune_repetitions: bool,
+    /// Also count solutions that are color mirror images of each other once, when the steno and start position are color-symmetric
+    #[arg(long)]
+    symmetry: bool,
+    /// Keep at most this many MiB of --dedup-final, --collapse-promotions and --count-by keys in memory, spilling the rest to a temporary file
+    #[arg(long, value_name = "MIB")]
+    dedup_memory: Option<u64>,
+    /// Cap the dedup sets, the two-stage prefix table, the forced solver's proofs and the games kept for the cache at SIZE between them (e.g. 8G), spilling, shrinking or dropping them with a warning rather than growing past it
+    #[arg(long, value_name = "SIZE", value_parser = parse_memory_size)]
+    max_memory: Option<u64>,
+    /// Like --dedup-final, but in a Bloom filter of this many bits: constant memory, at the risk of skipping a few new positions
+    #[arg(long, value_name = "BITS", conflicts_with = "dedup_final")]
+    dedup_approx: Option<u64>,
+    /// Estimate the solution count and search size from random probes, then ask before solving
+    #[arg(long)]
+    estimate_first: bool,
+    /// Probes to run for --estimate-first
+    #[arg(long, value_name = "N", default_value_t = 10000, requires = "estimate_first")]
+    estimate_samples: usize,
+    /// Start the search after --estimate-first without asking
+    #[arg(short, long, requires = "estimate_first")]
+    yes: bool,
+    /// Search the steno in two halves joined on transpositions (see --split-ply)
+    #[arg(long)]
+    two_stage: bool,
+    /// Ply to split a two-stage search at [default: before the first #, =, castling, promotion or %]
+    #[arg(long, value_name = "PLY", requires = "two_stage")]
+    split_ply: Option<usize>,
+    /// Look the first plies up in this file written by `index` instead of searching them; implies --two-stage, split where the index ends
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["series", "forced", "prefix_depth", "branching_report"])]
+    opening_index: Option<PathBuf>,
+    /// Only report solutions ending in this position (a proof game)
+    #[arg(long, value_name = "FEN", value_parser = fen_arg, conflicts_with = "tui")]
+    final_fen: Option<String>,
+    /// Compare the whole final position, or only where the pieces stand
+    #[arg(long, value_name = "exact|pieces", default_value = "exact", requires = "final_fen")]
+    final_match: TargetMatch,
+    /// Stop after the first K plies and list the distinct positions reached, each with how many games reach it
+    #[arg(long, value_name = "K", conflicts_with_all = ["tui", "two_stage", "final_fen", "dedup_final", "dedup_approx", "diagrams", "export_study", "limit"])]
+    prefix_depth: Option<usize>,
+    /// After solving, print how many distinct moves each ply has across the solutions and how many moves each constraint pruned
+    #[arg(long, conflicts_with_all = ["two_stage", "prefix_depth"])]
+    branching_report: bool,
+    /// Print each solution's ID, a hash of its moves that is the same on every run
+    #[arg(long)]
+    ids: bool,
+    /// Only report the solutions whose IDs appear in this file, e.g. the output of an earlier run with --ids
+    #[arg(long, value_name = "FILE")]
+    only_ids: Option<PathBuf>,
+    /// Print search statistics after the solutions
+    #[arg(long)]
+    stats: bool,
+    /// Reuse the result of an earlier identical solve, and keep this one's, in the result cache [default: cache from the config]
+    #[arg(long)]
+    cache: bool,
+    /// Search even when the result cache is turned on, without reading or writing it
+    #[arg(long, conflicts_with = "cache")]
+    no_cache: bool,
+    /// Browse the solutions in a terminal UI
+    #[arg(long)]
+    tui: bool,
+    /// Draw the final position (or every position) under each solution
+    #[arg(long, value_name = "none|final|all", num_args = 0..=1, require_equals = true, default_value = "none", default_missing_value = "final")]
+    show_boards: ShowBoards,
+    /// Write a diagram of each solution's final position into this directory
+    #[arg(long, value_name = "DIR")]
+    diagrams: Option<PathBuf>,
+    /// Write at most this many diagrams
+    #[arg(long, value_name = "N", requires = "diagrams")]
+    diagram_limit: Option<u64>,
+    /// Diagram image format
+    #[arg(long, value_name = "svg|png", default_value = "svg", requires = "diagrams")]
+    diagram_format: DiagramFormat,
+    /// Upload the solutions as chapters of this Lichess study
+    #[arg(long, value_name = "STUDY_ID")]
+    export_study: Option<String>,
+    /// Lichess API token with study:write scope
+    #[arg(long, env = "LICHESS_TOKEN", hide_env_values = true)]
+    lichess_token: Option<String>,
+    /// Chapter name prefix for the exported solutions
+    #[arg(long, value_name = "NAME", requires = "export_study")]
+    chapter_name: Option<String>,
+}
+
+// Every word of 16 hex digits in `contents`, so any output format printed
+// with --ids can be read back.
+fn read_solution_ids(contents: &str) -> HashSet<u64> {
+    contents.split(|ch: char| ch.is_whitespace() || "[]{}\"".contains(ch))
+        .filter(|word| word.len() == 16)
+        .filter_map(|word| u64::from_str_radix(word, 16).ok())
+        .collect()
+}
+
+fn confirm(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+// The constraints `steno` and the solve flags ask for, and the position
+// they start from, or the exit status and message to fail with.
+fn solve_constraints(args: &SolveArgs, steno: &str, config: &Config) -> Result<(Vec<Constraint>, Option<String>), (u8, String)> {
+    // Checked here rather than by clap, since --define adds characters.
+    let definitions: HashMap<char, Constraint> = args.define.iter().cloned().collect();
+    let invalid = |err: String| (EXIT_INVALID_STENO, err);
+    let stenos: Vec<Vec<Constraint>> = iter::once(steno).chain(args.and_stenos.iter().map(String::as_str))
+        .map(|steno| parse_steno_with(&translate_steno(steno, args.dialect), &definitions))
+        .collect::<Result<_, _>>()
+        .map_err(invalid)?;
+    let steno_constraints = intersect_stenos(&stenos).map_err(invalid)?;
+    #[cfg(feature = "syzygy")]
+    if args.syzygy_path.is_empty() && probes_tablebase(&steno_constraints) {
+        return Err(invalid("'$' and '_' need tables to probe; pass --syzygy-path".to_string()));
+    }
+    let steno_constraints = match (args.goal, args.goal_moves) {
+        (Some(goal), Some(moves)) => goal_steno(steno_constraints, goal, moves).map_err(invalid)?,
+        _ => steno_constraints,
+    };
+    let (steno_constraints, fen_string) = at_start_ply(steno_constraints, args.fen.clone(), args.start_ply).map_err(invalid)?;
+    check_steno_length(&steno_constraints, args.max_plies.or(config.max_plies).unwrap_or(DEFAULT_MAX_PLIES))
+        .map_err(|err| invalid(format!("{} (raise it with --max-plies or max_plies in the config)", err)))?;
+    Ok((steno_constraints, fen_string))
+}
+
+// Solutions a --watch run prints without --head.
+const WATCH_SOLUTIONS: u64 = 5;
+
+// A --watch search, cancelled when the file changes before it's done.
+struct WatchedSolve {
+    cancel: Arc<AtomicBool>,
+    thread: thread::JoinHandle<(SearchStats, Vec<Vec<ChessMove>>)>,
+    board: Board,
+    fen_string: Option<String>,
+}
+
+fn start_watched_solve(args: &SolveArgs, steno: &str, config: &Config) -> Result<WatchedSolve, String> {
+    let (steno_constraints, fen_string) = solve_constraints(args, steno, config).map_err(|(_, err)| err)?;
+    let board = start_board(&fen_string)?;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let options = SolveOptions {
+        cancel: Some(cancel.clone()),
+        ..search_options(args, &fen_string)
+    };
+    let (shown, limit, threads) = (args.head.unwrap_or(WATCH_SOLUTIONS) as usize, args.limit.or(config.limit), args.threads.or(config.threads));
+    let thread = thread::spawn(move || {
+        let found = AtomicU64::new(0);
+        let first = Mutex::new(Vec::new());
+        let on_solution = |path: &[ChessMove]| {
+            let index = found.fetch_add(1, Ordering::Relaxed);
+            if (index as usize) < shown {
+                first.lock().unwrap().push(path.to_vec());
+            }
+            if limit.is_some_and(|limit| index + 1 >= limit) {
+                options.cancel.as_ref().unwrap().store(true, Ordering::Relaxed);
+            }
+        };
+        let (mut stats, _) = with_threads(threads, || solve_with_callback(board, &steno_constraints, &options, &on_solution));
+        stats.solutions = limit.map_or(stats.solutions, |limit| stats.solutions.min(limit));
+        (stats, first.into_inner().unwrap())
+    });
+    Ok(WatchedSolve { cancel, thread, board, fen_string })
+}
+
+// Solves the steno in the file again each time it's saved, giving up on the
+// previous search if it's still going. Runs until interrupted.
+fn run_watch(args: SolveArgs, config: &Config, quiet: bool) -> ExitCode {
+    let path = PathBuf::from(&args.steno);
+    let format = args.format.or(config.format).unwrap_or(if args.series { OutputFormat::San } else { OutputFormat::default() });
+    let mut modified = None;
+    let mut running: Option<WatchedSolve> = None;
+    loop {
+        match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+            Ok(time) if modified != Some(time) => {
+                modified = Some(time);
+                if let Some(previous) = running.take() {
+                    previous.cancel.store(true, Ordering::Relaxed);
+                    let _ = previous.thread.join();
+                }
+                let steno = match fs::read_to_string(&path) {
+                    Ok(contents) => contents.trim().to_string(),
+                    Err(err) => {
+                        eprintln!("{}: {}", path.display(), err);
+                        continue;
+                    }
+                };
+                if !quiet {
+                    eprintln!("Solving {}", steno);
+                }
+                match start_watched_solve(&args, &steno, config) {
+                    Ok(solve) => running = Some(solve),
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+            // Editors that save by replacing the file leave it missing for a moment.
+            Err(err) if modified.is_none() => return runtime_error(format!("{}: {}", path.display(), err)),
+            _ => {}
+        }
+        if running.as_ref().is_some_and(|solve| solve.thread.is_finished()) {
+            let solve = running.take().unwrap();
+            let (stats, first) = solve.thread.join().unwrap();
+            if !quiet {
+                for game in &first {
+                    let rendered = if args.series {
+                        format!("{}\n", render_series(solve.board, game, format))
+                    } else {
+                        render_with_boards(solve.board, &solve.fen_string, game, format, args.show_boards)
+                    };
+                    print!("{}", if args.ids { tag_solution_id(&rendered, game, format) } else { rendered });
+                }
+                println!("Number of solutions found: {} ({:?})", stats.solutions, stats.elapsed);
+            }
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+// Fills in the steno, and the FEN unless --fen is given, from a --spec, so
+// the rest of the solve reads them as if written on the command line.
+fn apply_spec(args: &mut SolveArgs, path: &Path) -> Result<(), String> {
+    let spec = load_spec(path)?;
+    let definitions: HashMap<char, Constraint> = args.define.iter().cloned().collect();
+    args.steno = steno_string(&spec_constraints(&spec, &definitions)?);
+    if args.fen.is_none() {
+        args.fen = spec.fen.as_deref().map(fen_arg).transpose().map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+    Ok(())
+}
+
+// The search settings every way of solving shares: how the search is split
+// and ordered, where the games must end, and the history the start position
+// brings with it.
+fn search_options(args: &SolveArgs, fen_string: &Option<String>) -> SolveOptions {
+    SolveOptions {
+        print_solutions: false,
+        tasks_per_worker: args.task_granularity,
+        move_order: args.move_order,
+        seed: args.seed,
+        shard: args.shard,
+        target: args.final_fen.as_deref().map(|fen| TargetPosition::new(Board::from_str(fen).unwrap(), args.final_match)),
+        halfmove_clock: halfmove_clock(fen_string),
+        prune_repetitions: args.prune_repetitions,
+        series: args.series,
+        force: args.force,
+        goal: args.goal,
+        ..SolveOptions::default()
+    }
+}
+
+// What a solve works on once the arguments are read: the steno with any
+// pins folded in, the start position, and how solutions are printed.
+struct Solve {
+    steno_constraints: Vec<Constraint>,
+    fen_string: Option<String>,
+    board: Board,
+    pins: Vec<Pin>,
+    format: OutputFormat,
+    limit: Option<u64>,
+    budget: Option<Arc<MemoryBudget>>,
+}
+
+impl Solve {
+    fn new(args: &SolveArgs, config: &Config) -> Result<Solve, (u8, String)> {
+        let (steno_constraints, fen_string) = solve_constraints(args, &args.steno, config)?;
+        let pins = parse_pins(&args.pin.join(" "), &fen_string).map_err(|err| (EXIT_INVALID_STENO, err))?;
+        // The pins' pieces, squares and captures prune; the exact moves are
+        // checked on each solution.
+        let steno_constraints = match pins.is_empty() {
+            true => steno_constraints,
+            false => pin_steno(&pins, steno_constraints.len()).and_then(|pinned| intersect_stenos(&[steno_constraints, pinned])).map_err(|err| (EXIT_INVALID_STENO, err))?,
+        };
+        let board = start_board(&fen_string).map_err(|err| (EXIT_RUNTIME_ERROR, err))?;
+        Ok(Solve {
+            steno_constraints,
+            fen_string,
+            board,
+            pins,
+            format: args.format.or(config.format).unwrap_or(if args.series { OutputFormat::San } else { OutputFormat::default() }),
+            limit: args.limit.or(config.limit),
+            budget: args.max_memory.map(|bytes| Arc::new(MemoryBudget::new(bytes))),
+        })
+    }
+
+    fn render(&self, args: &SolveArgs, path: &[ChessMove]) -> String {
+        let rendered = if args.series {
+            format!("{}\n", render_series(self.board, path, self.format))
         } else {
-            return Err(format!("Invalid character in steno string: {}", ch));
-        }
-    }
-
-    Ok(parsed_chars)
-}
-
-fn check_steno_constraints(board: &Board, last_move: Option<ChessMove>, last_piece_moved: Option<Piece>, piece_on_dest: Option<Piece>, depth: u8, steno_constraints: &[char]) -> bool {
-    if last_move.is_none() {
-        return true;
-    }
-
-    let constraint = steno_constraints[(depth - 1) as usize];
-    let last_move_unwrapped = last_move.unwrap();
-    let dest_square = last_move_unwrapped.get_dest();
-    let source_square = last_move_unwrapped.get_source();
-    match constraint {
-        '~' => true,
-        '1'..='8' => dest_square.get_rank().to_index() == constraint.to_digit(10).unwrap() as usize - 1,
-        'a'..='h' => dest_square.get_file().to_index() == constraint as usize - 'a' as usize,
-        '+' => board.checkers().count() > 0,
-        '#' => matches!(board.status(), BoardStatus::Checkmate),
-        'L' => last_piece_moved.unwrap() == Piece::Bishop,
-        'N' => last_piece_moved.unwrap() == Piece::Knight,
-        'R' => last_piece_moved.unwrap() == Piece::Rook,
-        'Q' => last_piece_moved.unwrap() == Piece::Queen,
-        'K' => last_piece_moved.unwrap() == Piece::King,
-        'P' => last_piece_moved.unwrap() == Piece::Pawn,
-        'x' => {
-            if piece_on_dest.is_some() {
-                return true;
-            }
-            if let Some(last_piece) = last_piece_moved {
-                // Check en passant
-                if last_piece == Piece::Pawn {
-                    let source_square = last_move_unwrapped.get_source();
-                    let dest_square = last_move_unwrapped.get_dest();
-                    let is_diagonal_move = source_square.get_file() != dest_square.get_file();
-
-                    return is_diagonal_move && piece_on_dest.is_none();
+            render_with_boards(self.board, &self.fen_string, path, self.format, args.show_boards)
+        };
+        if args.ids { tag_solution_id(&rendered, path, self.format) } else { rendered }
+    }
+}
+
+// Options that can't work together, or that this build can't carry out,
+// found before anything is searched.
+fn check_outputs(args: &SolveArgs, format: OutputFormat) -> Result<(), String> {
+    // Every other way of printing a game expects the sides to take turns.
+    if args.series && (!matches!(format, OutputFormat::San | OutputFormat::Uci) || args.count_by == CountBy::Positions) {
+        return Err("--series prints solutions with --format san or uci, and can't --count-by positions".to_string());
+    }
+    if args.export_study.is_some() && !cfg!(feature = "online") {
+        return Err("steno_solver was built without the online feature".to_string());
+    }
+    if args.export_study.is_some() && args.lichess_token.is_none() {
+        return Err("--export-study needs a Lichess API token with study:write scope (--lichess-token or LICHESS_TOKEN)".to_string());
+    }
+    if args.diagram_format == DiagramFormat::Png && !cfg!(feature = "png") {
+        return Err("steno_solver was built without the png feature".to_string());
+    }
+    // A tree is laid out in move order whatever order its solutions come in.
+    if args.sort.is_some() && format == OutputFormat::Tree {
+        return Err("--sort can't reorder --format tree".to_string());
+    }
+    if let Some(dir) = &args.diagrams {
+        fs::create_dir_all(dir).map_err(|err| format!("Could not create {}: {}", dir.display(), err))?;
+    }
+    Ok(())
+}
+
+// Prints the estimate, and asks whether to go on unless --yes says so.
+fn confirm_estimate(args: &SolveArgs, solve: &Solve) -> Result<(), String> {
+    eprintln!("{}", estimate_search(solve.board, &solve.steno_constraints, args.estimate_samples, &mut seeded_rng(args.seed)));
+    if args.yes {
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        return Err("Pass --yes to run the search after --estimate-first when stdin isn't a terminal".to_string());
+    }
+    if !confirm("Run the full search?") {
+        return Err("Search cancelled".to_string());
+    }
+    Ok(())
+}
+
+fn run_solve(mut args: SolveArgs, config: &Config, quiet: bool, verbose: u8) -> ExitCode {
+    if let Some(path) = args.spec.clone() {
+        if let Err(err) = apply_spec(&mut args, &path) {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_INVALID_STENO);
+        }
+    }
+    #[cfg(feature = "syzygy")]
+    if !args.syzygy_path.is_empty() {
+        match load_syzygy(&args.syzygy_path) {
+            Ok(0) => return runtime_error(format!("No Syzygy tables in {}", args.syzygy_path.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", "))),
+            Ok(_) => {}
+            Err(err) => return runtime_error(err),
+        }
+    }
+    if args.watch {
+        return run_watch(args, config, quiet);
+    }
+    let solve = match Solve::new(&args, config) {
+        Ok(solve) => solve,
+        Err((status, err)) => {
+            eprintln!("{}", err);
+            return ExitCode::from(status);
+        }
+    };
+    if let Err(err) = check_outputs(&args, solve.format) {
+        return runtime_error(err);
+    }
+    if args.estimate_first {
+        if let Err(err) = confirm_estimate(&args, &solve) {
+            return runtime_error(err);
+        }
+    }
+
+    if args.tui {
+        return run_tui(solve.board, solve.fen_string, &solve.steno_constraints);
+    }
+    if args.forced {
+        return run_forced(&args, &solve, config, quiet);
+    }
+    if let Some(path) = &args.fen_file {
+        return run_fen_file(&args, &solve, path, config, quiet);
+    }
+    if let Some(depth) = args.prefix_depth {
+        return run_prefix_depth(&args, &solve, depth, config, quiet);
+    }
+    if args.repro_check {
+        return run_repro_check(&args, &solve, config);
+    }
+    solve_and_print(args, solve, config, quiet, verbose)
+}
+
+fn run_forced(args: &SolveArgs, solve: &Solve, config: &Config, quiet: bool) -> ExitCode {
+    let options = SolveOptions { memory_budget: solve.budget.clone(), ..search_options(args, &solve.fen_string) };
+    let ((keys, stats), _) = with_threads(args.threads.or(config.threads), || solve_forced(solve.board, &solve.steno_constraints, &options));
+    if !quiet {
+        for &key in &keys {
+            let rendered = render_solution(&solve.fen_string, &[key], solve.format);
+            println!("{}", if args.ids { tag_solution_id(&rendered, &[key], solve.format) } else { rendered });
+        }
+        println!("Number of solutions found: {}", keys.len());
+        if args.stats {
+            println!("{}", stats);
+        }
+    }
+    found_exit(!keys.is_empty())
+}
+
+// Each start's own halfmove clock replaces the one from --fen.
+fn run_fen_file(args: &SolveArgs, solve: &Solve, path: &PathBuf, config: &Config, quiet: bool) -> ExitCode {
+    let starts = match read_fen_file(path) {
+        Ok(starts) => starts,
+        Err(err) => return runtime_error(err),
+    };
+    let options = search_options(args, &solve.fen_string);
+    let boards: Vec<(Board, u32)> = starts.iter().map(|(board, clock, _)| (*board, *clock)).collect();
+    let (results, _) = with_threads(args.threads.or(config.threads), || solve_from_starts(&boards, &solve.steno_constraints, &options));
+    let total: u64 = results.iter().map(|stats| stats.solutions).sum();
+    if !quiet {
+        for ((_, _, fen), stats) in starts.iter().zip(&results) {
+            println!("{} {}", stats.solutions, fen);
+        }
+        println!("Starts with solutions: {} of {}", results.iter().filter(|stats| stats.solutions > 0).count(), results.len());
+        println!("Number of solutions found: {}", total);
+        if args.stats {
+            let nodes: u64 = results.iter().map(|stats| stats.nodes_visited).sum();
+            println!("Nodes visited: {}", nodes);
+        }
+    }
+    found_exit(total > 0)
+}
+
+fn run_prefix_depth(args: &SolveArgs, solve: &Solve, depth: usize, config: &Config, quiet: bool) -> ExitCode {
+    if depth > solve.steno_constraints.len() {
+        eprintln!("--prefix-depth {} is longer than the steno ({} plies)", depth, solve.steno_constraints.len());
+        return ExitCode::from(EXIT_INVALID_STENO);
+    }
+    // The shard, final position and goal are the whole game's, not its
+    // first plies'.
+    let options = SolveOptions {
+        record_ply_times: args.stats,
+        shard: None,
+        target: None,
+        goal: None,
+        ..search_options(args, &solve.fen_string)
+    };
+    let ((positions, stats), _) = with_threads(args.threads.or(config.threads), || prefix_positions(solve.board, &solve.steno_constraints[..depth], &options));
+    if !quiet {
+        // Without the move counters, which depend on the game rather than the position.
+        print!("{}", render_prefix_positions(&positions, depth));
+        if args.stats {
+            println!("{}", stats);
+        }
+    }
+    found_exit(!positions.is_empty())
+}
+
+fn read_only_ids(args: &SolveArgs) -> Result<Option<HashSet<u64>>, String> {
+    let Some(path) = &args.only_ids else {
+        return Ok(None);
+    };
+    let contents = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(Some(read_solution_ids(&contents)))
+}
+
+// Started before the search, so a bad engine fails fast.
+fn start_engine(args: &SolveArgs, config: &Config) -> Result<Option<Engine>, String> {
+    if !args.annotate && args.sort != Some(SolutionSort::EngineEval) {
+        return Ok(None);
+    }
+    let flag = if args.annotate { "--annotate" } else { "--sort engine-eval" };
+    let Some(path) = args.engine.clone().or_else(|| config.engine.clone()) else {
+        return Err(format!("{} needs a UCI engine (--engine, or engine in the config)", flag));
+    };
+    Engine::start(&path).map(Some)
+}
+
+fn load_opening_index(args: &SolveArgs) -> Result<Option<Arc<OpeningIndex>>, String> {
+    args.opening_index.as_ref().map(|path| OpeningIndex::load(path).map(Arc::new)).transpose()
+}
+
+// Stdout, and a file for each --tee.
+fn open_writer(args: &SolveArgs) -> Result<SolutionWriter, String> {
+    let mut tees: Vec<Box<dyn io::Write + Send>> = Vec::new();
+    for path in &args.tee {
+        let file = fs::File::create(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        tees.push(Box::new(io::BufWriter::new(file)));
+    }
+    Ok(SolutionWriter::stdout_and(tees))
+}
+
+// Custom constraints are only known by their character, and the branching
+// and constraint reports need the search's own statistics.
+fn result_cache(args: &SolveArgs, config: &Config, verbose: u8) -> Option<ResultCache> {
+    ((args.cache || config.cache == Some(true)) && !args.no_cache && args.define.is_empty() && !args.branching_report && args.profile.is_none() && verbose < 2)
+        .then(cache_dir)
+        .flatten()
+        .map(|dir| ResultCache::new(&dir))
+}
+
+// Everything that changes which games are solutions.
+fn cache_query(args: &SolveArgs, solve: &Solve) -> String {
+    format!(
+        "{} fen={} series={} goal={:?} prune_repetitions={} final={:?}/{:?} shard={:?}",
+        steno_string(&solve.steno_constraints),
+        solve.fen_string.as_deref().unwrap_or("startpos"),
+        args.series,
+        args.goal,
+        args.prune_repetitions,
+        args.final_fen,
+        args.final_match,
+        args.shard
+    )
+}
+
+// A --dedup-approx filter, shrunk to what the budget has room for at the cost
+// of more false positives.
+fn bloom_filter(bits: u64, budget: &Option<Arc<MemoryBudget>>) -> BloomFilter {
+    let bits = match budget {
+        Some(budget) if !budget.reserve(bits.div_ceil(8)) => {
+            let fitting = budget.available() * 8;
+            eprintln!("--max-memory leaves room for a Bloom filter of {} bits, not {}", fitting, bits);
+            budget.reserve(fitting.div_ceil(8));
+            fitting.max(64)
+        }
+        _ => bits,
+    };
+    BloomFilter::new(bits)
+}
+
+// The solutions held back until the search was done: sorted, annotated,
+// as a broadcast or a tree, or just in the order they came.
+fn write_deferred(args: &SolveArgs, solve: &Solve, mut deferred: Vec<Vec<ChessMove>>, engine: &mut Option<Engine>, writer: &SolutionWriter) -> Result<(), String> {
+    if let Some(sort) = args.sort {
+        sort_solutions(&mut deferred, solve.board, &solve.fen_string, sort, engine.as_mut().map(|engine| (engine, args.engine_depth)))?;
+        deferred.truncate(args.head.map_or(usize::MAX, |head| head as usize));
+    }
+    if let (true, Some(engine)) = (args.annotate, engine) {
+        if args.sort.is_none() {
+            deferred.sort_by_key(|path| path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>());
+        }
+        for path in &deferred {
+            let id = format_solution_id(solution_id(path));
+            let headers: &[(&str, &str)] = if args.ids { &[("SolutionId", &id)] } else { &[] };
+            let movetext = annotated_movetext(engine, solve.board, &solve.fen_string, path, args.engine_depth)?;
+            writer.write(&format!("{}\n", pgn_with_movetext(&solve.fen_string, &movetext, headers)));
+        }
+    } else if let Some(event) = &args.broadcast {
+        writer.write(&render_broadcast(&solve.fen_string, &deferred, event, &args.steno, args.ids));
+    } else if solve.format == OutputFormat::Tree {
+        writer.write(&render_tree(&solve.fen_string, &deferred, args.ids));
+    } else {
+        for path in &deferred {
+            writer.write(&solve.render(args, path));
+        }
+    }
+    Ok(())
+}
+
+fn solve_and_print(args: SolveArgs, solve: Solve, config: &Config, quiet: bool, verbose: u8) -> ExitCode {
+    let only_ids = match read_only_ids(&args) {
+        Ok(only_ids) => only_ids,
+        Err(err) => return runtime_error(err),
+    };
+    let mut engine = match start_engine(&args, config) {
+        Ok(engine) => engine,
+        Err(err) => return runtime_error(err),
+    };
+    let opening_index = match load_opening_index(&args) {
+        Ok(opening_index) => opening_index,
+        Err(err) => return runtime_error(err),
+    };
+    let writer = match open_writer(&args) {
+        Ok(writer) => writer,
+        Err(err) => return runtime_error(err),
+    };
+    let Solve { ref steno_constraints, ref fen_string, board, ref pins, format, limit, ref budget } = solve;
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let options = SolveOptions {
+        record_ply_times: args.stats || args.profile.is_some(),
+        record_task_times: args.profile.is_some(),
+        record_pruning: args.branching_report || verbose >= 2,
+        cancel: (!quiet || limit.is_some() || args.dedup_final || args.collapse_promotions || args.dedup_memory.is_some()).then(|| cancel.clone()),
+        opening_index: opening_index.clone(),
+        memory_budget: budget.clone(),
+        ..search_options(&args, fen_string)
+    };
+    let cache = result_cache(&args, config, verbose);
+    let cache_query = cache_query(&args, &solve);
+    // Every game the search reports, before the filters below, to be cached.
+    let recorded = cache.as_ref().map(|_| Mutex::new(Vec::new()));
+    // Set once the memory budget refuses a game, and the cache gets only the count.
+    let recording_dropped = AtomicBool::new(false);
+    let found = AtomicU64::new(0);
+    let write_failed = AtomicBool::new(false);
+    let (replayed, diverged) = (AtomicU64::new(0), AtomicU64::new(0));
+    let exported = Mutex::new(Vec::new());
+    // Printed once the search is done: as one tree, or after the engine has annotated them.
+    let deferred = Mutex::new(Vec::new());
+    let branching = args.branching_report.then(|| BranchingReport::new(steno_constraints.len()));
+    let groups = args.cluster.map(|plies| OpeningGroups::new(plies, args.cluster_dir.is_some()));
+    let memory_limit = args.dedup_memory.map(|mib| mib * 1024 * 1024);
+    let spill_set = || {
+        let set = memory_limit.map_or_else(SpillSet::new, SpillSet::with_memory_limit);
+        match budget {
+            Some(budget) => set.with_budget(budget.clone()),
+            None => set,
+        }
+    };
+    let counter = DistinctCounter::with_set(args.count_by, spill_set());
+    let dedup_set = || Mutex::new(spill_set());
+    let final_positions = args.dedup_final.then(dedup_set);
+    let promotion_classes = args.collapse_promotions.then(dedup_set);
+    let symmetric = args.symmetry && color_symmetric(&board, steno_constraints);
+    if args.symmetry && !symmetric {
+        eprintln!("The steno and start position aren't color-symmetric, so there are no mirror pairs to count");
+    }
+    let mirror_pairs = symmetric.then(|| MirrorCounter::new(spill_set()));
+    let approx_final_positions = args.dedup_approx.map(|bits| bloom_filter(bits, budget));
+    let on_solution = |path: &[ChessMove]| {
+        if args.self_check {
+            replayed.fetch_add(1, Ordering::Relaxed);
+            if let Err(err) = replay_check(fen_string, steno_constraints, path) {
+                eprintln!("Self-check failed for {}: {}", path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>().join(" "), err);
+                diverged.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if let Some(recorded) = &recorded {
+            let mut recorded = recorded.lock().unwrap();
+            if !recording_dropped.load(Ordering::Relaxed) && (recorded.len() as u64) < MAX_CACHED_SOLUTIONS {
+                if budget.as_ref().is_none_or(|budget| budget.reserve(game_bytes(path))) {
+                    recorded.push(path.to_vec());
+                } else {
+                    eprintln!("--max-memory reached, caching only the solution count");
+                    recording_dropped.store(true, Ordering::Relaxed);
+                    budget.as_ref().unwrap().release(recorded.iter().map(|path| game_bytes(path)).sum());
+                    *recorded = Vec::new();
                 }
             }
-            return false;
         }
-        '%' => {
-            if let Some(last_piece) = last_piece_moved {
-                // Check en passant
-                if last_piece == Piece::Pawn {
-                    let source_square = last_move_unwrapped.get_source();
-                    let dest_square = last_move_unwrapped.get_dest();
-                    let is_diagonal_move = source_square.get_file() != dest_square.get_file();
+        if only_ids.as_ref().is_some_and(|ids| !ids.contains(&solution_id(path))) {
+            return;
+        }
+        if !pins.is_empty() && !matches_pins(fen_string, pins, path) {
+            return;
+        }
+        let final_hash = || path.iter().fold(board, |board, &mov| board.make_move_new(mov)).get_hash();
+        if approx_final_positions.as_ref().is_some_and(|filter| !filter.insert(final_hash())) {
+            return;
+        }
+        // Spill errors stop the search, and the solution isn't reported either.
+        let seen_before = |set: &Mutex<SpillSet>, key: u64| match set.lock().unwrap().insert(key) {
+            Ok(new) => !new,
+            Err(err) => {
+                eprintln!("Could not spill dedup keys: {}", err);
+                write_failed.store(true, Ordering::Relaxed);
+                cancel.store(true, Ordering::Relaxed);
+                true
+            }
+        };
+        if final_positions.as_ref().is_some_and(|set| seen_before(set, final_hash())) {
+            return;
+        }
+        if promotion_classes.as_ref().is_some_and(|set| seen_before(set, promotion_class_key(steno_constraints, path))) {
+            return;
+        }
+
+        // Other workers may still report a solution or two after the limit is hit.
+        let index = found.fetch_add(1, Ordering::Relaxed);
+        if limit.is_some_and(|limit| index >= limit) {
+            return;
+        }
+        if limit == Some(index + 1) {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        if let Err(err) = counter.record(board, path) {
+            eprintln!("Could not spill --count-by keys: {}", err);
+            write_failed.store(true, Ordering::Relaxed);
+            cancel.store(true, Ordering::Relaxed);
+        }
+        if let Some(mirror_pairs) = &mirror_pairs {
+            if let Err(err) = mirror_pairs.record(board, path) {
+                eprintln!("Could not spill --symmetry keys: {}", err);
+                write_failed.store(true, Ordering::Relaxed);
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+        if let Some(branching) = &branching {
+            branching.record(path);
+        }
+
+        // Sorted solutions are cut down to the head once they're in order,
+        // and grouped ones print as groups.
+        if let Some(groups) = &groups {
+            groups.record(path);
+        } else if !quiet && (args.sort.is_some() || args.head.is_none_or(|head| index < head)) {
+            if format == OutputFormat::Tree || args.annotate || args.broadcast.is_some() || args.sort.is_some() {
+                deferred.lock().unwrap().push(path.to_vec());
+            } else {
+                writer.write(&solve.render(&args, path));
+                if writer.failed() {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        if let Some(dir) = &args.diagrams {
+            if args.diagram_limit.is_none_or(|limit| index < limit) {
+                let output = dir.join(format!("solution_{:05}.{}", index + 1, args.diagram_format.extension()));
+                if let Err(err) = write_diagram(board, path, args.diagram_format, &output) {
+                    eprintln!("Could not write {}: {}", output.display(), err);
+                    write_failed.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        if args.export_study.is_some() {
+            exported.lock().unwrap().push(path.to_vec());
+        }
+    };
+    // An entry without its games only answers a run that just counts them.
+    let counts_only = quiet && only_ids.is_none() && pins.is_empty() && args.cluster_dir.is_none() && !args.dedup_final && args.dedup_approx.is_none() && !args.collapse_promotions && args.diagrams.is_none() && args.export_study.is_none();
+    let cached = cache.as_ref().and_then(|cache| cache.get(&cache_query)).filter(|hit| hit.games.is_some() || counts_only);
+    let stats = if let Some(hit) = &cached {
+        if !quiet {
+            eprintln!("Results from the cache, solved before in {:?}; --no-cache searches again", hit.elapsed);
+        }
+        match &hit.games {
+            Some(games) => games.iter().for_each(|game| on_solution(game)),
+            None => found.store(hit.solutions, Ordering::Relaxed),
+        }
+        SearchStats { solutions: hit.solutions, nodes_visited: hit.nodes_visited, elapsed: hit.elapsed, ..SearchStats::default() }
+    } else {
+        with_threads(args.threads.or(config.threads), || {
+            if let (Some(index), None) = (&opening_index, args.split_ply) {
+                solve_two_stage(board, steno_constraints, index.plies().min(steno_constraints.len()), &options, &on_solution)
+            } else if args.two_stage || opening_index.is_some() {
+                let split_ply = args.split_ply.unwrap_or_else(|| default_split_ply(steno_constraints));
+                solve_two_stage(board, steno_constraints, split_ply, &options, &on_solution)
+            } else {
+                solve_with_callback(board, steno_constraints, &options, &on_solution)
+            }
+        }).0
+    };
+    if let (Some(cache), None) = (&cache, &cached) {
+        // A limit or a failed write stops the search short of every game.
+        if !cancel.load(Ordering::Relaxed) && !write_failed.load(Ordering::Relaxed) {
+            let games = recorded.map(|recorded| recorded.into_inner().unwrap());
+            let result = CachedResult { solutions: stats.solutions, nodes_visited: stats.nodes_visited, elapsed: stats.elapsed, games: games.filter(|_| stats.solutions <= MAX_CACHED_SOLUTIONS && !recording_dropped.load(Ordering::Relaxed)) };
+            if let Err(err) = cache.put(&cache_query, &result) {
+                eprintln!("Could not write to the result cache in {}: {}", cache.dir().display(), err);
+            }
+        }
+    }
+    if let Some(path) = &args.profile {
+        let trace = serde_json::to_string(&chrome_trace(&stats, &args.steno)).unwrap();
+        if let Err(err) = fs::write(path, trace) {
+            let _ = writer.finish();
+            return runtime_error(format!("Could not write {}: {}", path.display(), err));
+        }
+    }
+    let found = found.into_inner();
+    let solutions = limit.map_or(found, |limit| found.min(limit));
+    let groups = groups.map(OpeningGroups::into_groups);
+    if let (Some(dir), Some(groups)) = (&args.cluster_dir, &groups) {
+        if let Err(err) = write_clusters(dir, fen_string, groups, &args.steno) {
+            let _ = writer.finish();
+            return runtime_error(format!("Could not write the clusters to {}: {}", dir.display(), err));
+        }
+    }
+    if !quiet {
+        if let Some(groups) = &groups {
+            let shown = &groups[..groups.len().min(args.head.map_or(usize::MAX, |head| head as usize))];
+            writer.write(&render_opening_groups(fen_string, shown, format));
+            writer.write(&format!("Groups by the first {} plies: {}\n", args.cluster.unwrap(), groups.len()));
+        }
+        if let Err(err) = write_deferred(&args, &solve, deferred.into_inner().unwrap(), &mut engine, &writer) {
+            let _ = writer.finish();
+            return runtime_error(err);
+        }
+        writer.write(&format!("Number of solutions found: {}\n", solutions));
+        match args.count_by {
+            CountBy::Games => {}
+            CountBy::Positions => writer.write(&format!("Distinct final positions: {}\n", counter.count())),
+            CountBy::Classes => writer.write(&format!("Distinct solution classes: {}\n", counter.count())),
+        }
+        if let Some(mirror_pairs) = &mirror_pairs {
+            writer.write(&format!("Solutions up to color symmetry: {}\n", mirror_pairs.count()));
+        }
+        if let Some(branching) = &branching {
+            writer.write(&branching.render(steno_constraints, &stats));
+        }
+        if verbose >= 2 {
+            if stats.ply_tested.is_empty() {
+                eprintln!("No per-constraint statistics: the two-stage search doesn't record them");
+            } else {
+                eprint!("{}", constraint_report(steno_constraints, &stats));
+            }
+        }
+        if args.stats {
+            writer.write(&format!("{}\n", stats));
+            let spilled = counter.spilled() + [&final_positions, &promotion_classes].iter().filter_map(|set| set.as_ref()).map(|set| set.lock().unwrap().spilled()).sum::<u64>();
+            if spilled > 0 {
+                writer.write(&format!("Dedup keys spilled to disk: {}\n", spilled));
+            }
+            if let Some(filter) = &approx_final_positions {
+                writer.write(&format!(
+                    "Approximate dedup: {} positions in {} bits, ~{:.2} new positions expected to be skipped as false positives\n",
+                    filter.len(),
+                    filter.bits(),
+                    filter.expected_false_positives()
+                ));
+            }
+        }
+    }
+
+    if let Err(err) = writer.finish() {
+        // The reader has what it wanted, as `head` does, so there is no one
+        // left to tell the count either.
+        if err.kind() == io::ErrorKind::BrokenPipe {
+            return found_exit(solutions > 0);
+        }
+        return runtime_error(err);
+    }
+
+    if let (Some(study_id), Some(token)) = (&args.export_study, &args.lichess_token) {
+        let chapter_name = args.chapter_name.unwrap_or_else(|| format!("Steno {}", args.steno));
+        match export_study(token, study_id, &chapter_name, &args.steno, fen_string, exported.into_inner().unwrap()) {
+            Ok(chapters) if !quiet => println!("Exported {} chapters to https://lichess.org/study/{}", chapters, study_id),
+            Ok(_) => {}
+            Err(err) => return runtime_error(format!("Study export failed: {}", err)),
+        }
+    }
+
+    if args.self_check {
+        let diverged = diverged.into_inner();
+        if !quiet || diverged > 0 {
+            eprintln!("Self-check: {} solutions replayed, {} diverged", replayed.into_inner(), diverged);
+        }
+        if diverged > 0 {
+            return ExitCode::from(EXIT_RUNTIME_ERROR);
+        }
+    }
+    if write_failed.into_inner() {
+        return ExitCode::from(EXIT_RUNTIME_ERROR);
+    }
+    found_exit(solutions > 0)
+}
+
+// Solves twice, the second time on half the threads (or two, after one),
+// and compares the solution counts, digests of the games and --count-by
+// counts, none of which may depend on which worker found what first.
+fn run_repro_check(args: &SolveArgs, solve: &Solve, config: &Config) -> ExitCode {
+    let Solve { ref steno_constraints, ref fen_string, board, ref pins, .. } = *solve;
+    let options = search_options(args, fen_string);
+    let solve = |threads: Option<usize>| {
+        let digest = SolutionDigest::new();
+        let counter = DistinctCounter::new(args.count_by);
+        let spill_failed = AtomicBool::new(false);
+        let on_solution = |path: &[ChessMove]| {
+            if !pins.is_empty() && !matches_pins(fen_string, pins, path) {
+                return;
+            }
+            digest.record(path);
+            if counter.record(board, path).is_err() {
+                spill_failed.store(true, Ordering::Relaxed);
+            }
+        };
+        let (stats, used) = with_threads(threads, || {
+            if args.two_stage {
+                solve_two_stage(board, steno_constraints, args.split_ply.unwrap_or_else(|| default_split_ply(steno_constraints)), &options, &on_solution)
+            } else {
+                solve_with_callback(board, steno_constraints, &options, &on_solution)
+            }
+        });
+        ((digest.solutions(), digest.digest(), counter.count()), used, stats.elapsed, spill_failed.into_inner())
+    };
+
+    let first = solve(args.threads.or(config.threads));
+    let second = solve(Some(if first.1 > 1 { first.1 / 2 } else { 2 }));
+    for (run, (found, threads, elapsed, spill_failed)) in [&first, &second].into_iter().enumerate() {
+        if *spill_failed {
+            return runtime_error("Could not spill --count-by keys");
+        }
+        let distinct = match args.count_by {
+            CountBy::Games => String::new(),
+            CountBy::Positions => format!(", {} distinct final positions", found.2),
+            CountBy::Classes => format!(", {} distinct solution classes", found.2),
+        };
+        println!("Run {} on {} threads: {} solutions, digest {}{} ({:?})", run + 1, threads, found.0, format_solution_id(found.1), distinct, elapsed);
+    }
+    if first.0 != second.0 {
+        eprintln!("Repro check failed: the runs found different solutions");
+        return ExitCode::from(EXIT_RUNTIME_ERROR);
+    }
+    println!("Repro check passed: both runs found the same solutions");
+    found_exit(first.0 .0 > 0)
+}
+
+// One PGN file per group, each game tagged with its group and place in it.
+fn write_clusters(dir: &PathBuf, fen_string: &Option<String>, groups: &[OpeningGroup], steno: &str) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (number, group) in groups.iter().enumerate() {
+        let games: Vec<String> = group.games.iter().enumerate().map(|(index, path)| {
+            let event = format!("Cluster {} #{}", number + 1, index + 1);
+            render_pgn(fen_string, path, &[("Event", &event), ("Annotator", steno)])
+        }).collect();
+        fs::write(dir.join(format!("cluster_{:03}.pgn", number + 1)), games.join("\n"))?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "online")]
+fn export_study(token: &str, study_id: &str, chapter_name: &str, steno: &str, fen_string: &Option<String>, mut solutions: Vec<Vec<ChessMove>>) -> Result<usize, String> {
+    solutions.sort_by_key(|path| path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>());
+    let games: Vec<String> = solutions.iter().enumerate().map(|(index, path)| {
+        let event = format!("{} #{}", chapter_name, index + 1);
+        render_pgn(fen_string, path, &[("Event", &event), ("Annotator", steno)])
+    }).collect();
+
+    export_to_study(token, study_id, chapter_name, &games)
+}
+
+#[cfg(not(feature = "online"))]
+fn export_study(_token: &str, _study_id: &str, _chapter_name: &str, _steno: &str, _fen_string: &Option<String>, _solutions: Vec<Vec<ChessMove>>) -> Result<usize, String> {
+    Err("steno_solver was built without the online feature".to_string())
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(board: Board, fen_string: Option<String>, steno_constraints: &[Constraint]) -> ExitCode {
+    let solutions = std::sync::Mutex::new(Vec::new());
+    let options = SolveOptions {
+        print_solutions: false,
+        ..SolveOptions::default()
+    };
+    solve_with_callback(board, steno_constraints, &options, &|path| solutions.lock().unwrap().push(path.to_vec()));
+
+    let mut solutions = solutions.into_inner().unwrap();
+    solutions.sort_by_key(|path| path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>());
+    let found = !solutions.is_empty();
+    match explore(board, &fen_string, solutions) {
+        Ok(()) => found_exit(found),
+        Err(err) => runtime_error(err),
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(_board: Board, _fen_string: Option<String>, _steno_constraints: &[Constraint]) -> ExitCode {
+    runtime_error("steno_solver was built without the tui feature")
+}
+
+enum GameSource {
+    Lichess(String),
+    Chesscom(String),
+    Pgn(PathBuf),
+}
+
+fn fetch_game(source: &GameSource) -> Result<PgnGame, String> {
+    match source {
+        GameSource::Pgn(path) => fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err)).and_then(|pgn| parse_pgn(&pgn)),
+        #[cfg(feature = "online")]
+        GameSource::Lichess(game) => fetch_lichess_game(game),
+        #[cfg(feature = "online")]
+        GameSource::Chesscom(url) => fetch_chesscom_game(url),
+        #[cfg(not(feature = "online"))]
+        GameSource::Lichess(game) | GameSource::Chesscom(game) => Err(format!("Cannot fetch {}: steno_solver was built without the online feature", game)),
+    }
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("source").required(true).args(["lichess", "chesscom", "pgn"])))]
+struct FromGameArgs {
+    /// Lichess game id or URL
+    #[arg(long, value_name = "GAME")]
+    lichess: Option<String>,
+    /// Chess.com game URL
+    #[arg(long, value_name = "URL")]
+    chesscom: Option<String>,
+    /// PGN file (the first game is used)
+    #[arg(long, value_name = "FILE")]
+    pgn: Option<PathBuf>,
+    /// Also print the weakest variant of the steno that is still unique
+    #[arg(long)]
+    weaken: bool,
+}
+
+fn run_from_game(args: FromGameArgs) -> ExitCode {
+    let source = match (args.lichess, args.chesscom, args.pgn) {
+        (Some(game), _, _) => GameSource::Lichess(game),
+        (_, Some(url), _) => GameSource::Chesscom(url),
+        (_, _, Some(path)) => GameSource::Pgn(path),
+        _ => unreachable!("clap requires one game source"),
+    };
+
+    let moves = fetch_game(&source).and_then(|game| {
+        let moves = moves_from_san(&game.fen, &game.san_moves)?;
+        Ok((game.fen, moves))
+    });
+    let (board, clock, steno) = match moves.and_then(|(fen_string, moves)| {
+        let board = start_board(&fen_string)?;
+        Ok((board, halfmove_clock(&fen_string), steno_for_game(board, &moves)?))
+    }) {
+        Ok(game) => game,
+        Err(err) => return runtime_error(err),
+    };
+    println!("{}", steno);
+
+    if args.weaken {
+        match weaken_to_unique(board, clock, &steno) {
+            Some(weakened) => println!("Unique variant: {}", weakened),
+            None => {
+                eprintln!("The steno has more than one solution, so it has no unique variant");
+                return ExitCode::from(EXIT_NO_SOLUTIONS);
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("game").required(true).args(["moves", "pgn"])))]
+struct VerifyArgs {
+    /// Steno string, one constraint character per ply
+    #[arg(value_parser = steno_arg)]
+    steno: String,
+    /// The game as SAN movetext, e.g. "1. e4 e5 2. Nf3"
+    #[arg(long)]
+    moves: Option<String>,
+    /// PGN file holding the game (the first game is used)
+    #[arg(long, value_name = "FILE")]
+    pgn: Option<PathBuf>,
+    /// Start position, if the game doesn't begin from the initial one
+    #[arg(long, value_parser = fen_arg)]
+    fen: Option<String>,
+}
+
+fn run_verify(args: VerifyArgs, quiet: bool) -> ExitCode {
+    let game = match (&args.moves, &args.pgn) {
+        (Some(moves), _) => parse_pgn(moves),
+        (_, Some(path)) => fetch_game(&GameSource::Pgn(path.clone())),
+        _ => unreachable!("clap requires a game"),
+    };
+    let game = game.and_then(|game| {
+        let fen_string = args.fen.clone().or(game.fen);
+        let moves = moves_from_san(&fen_string, &game.san_moves)?;
+        Ok((fen_string, moves))
+    });
+    let (board, clock, moves) = match game.and_then(|(fen_string, moves)| Ok((start_board(&fen_string)?, halfmove_clock(&fen_string), moves))) {
+        Ok(game) => game,
+        Err(err) => return runtime_error(err),
+    };
+
+    let steno_constraints = parse_steno_string(&args.steno).unwrap();
+    if let Err(err) = verify_game(board, clock, &steno_constraints, &moves) {
+        if !quiet {
+            println!("The game does not match the steno: {}", err);
+        }
+        return ExitCode::from(EXIT_NO_SOLUTIONS);
+    }
+
+    if !quiet {
+        println!("The game matches the steno");
+        if count_solutions(board, clock, &steno_constraints, Some(2)) == 1 {
+            println!("It is the only solution");
+        } else {
+            println!("The steno has other solutions too");
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[derive(Args)]
+#[command(after_help = "A collection is a TOML file of [[puzzle]] tables, or a .json file of the form {\"puzzle\": [...]}:
+
+  [[puzzle]]
+  name = \"Fool's mate\"        # optional, as are author and source
+  steno = \"~~~#\"
+  solution = \"1. f3 e5 2. g4 Qh4#\"
+  fen = \"...\"                 # optional start position")]
+struct VerifyCollectionArgs {
+    /// Collection file
+    file: PathBuf,
+    /// Worker threads (defaults to one per core)
+    #[arg(long, value_name = "N")]
+    threads: Option<usize>,
+}
+
+fn run_verify_collection(args: VerifyCollectionArgs, config: &Config, quiet: bool) -> ExitCode {
+    let puzzles = match load_collection(&args.file) {
+        Ok(puzzles) => puzzles,
+        Err(err) => return runtime_error(err),
+    };
+    let (statuses, _) = with_threads(args.threads.or(config.threads), || verify_collection(&puzzles));
+
+    let failed = statuses.iter().filter(|&status| *status != PuzzleStatus::Ok).count();
+    if !quiet {
+        let names: Vec<String> = puzzles.iter().enumerate().map(|(index, puzzle)| puzzle.name.clone().unwrap_or_else(|| format!("#{}", index + 1))).collect();
+        let name_width = names.iter().map(|name| name.chars().count()).chain([4]).max().unwrap();
+        let steno_width = puzzles.iter().map(|puzzle| puzzle.steno.chars().count()).chain([5]).max().unwrap();
+        println!("{:<name_width$}  {:<steno_width$}  Result", "Name", "Steno");
+        for ((name, puzzle), status) in names.iter().zip(&puzzles).zip(&statuses) {
+            println!("{:<name_width$}  {:<steno_width$}  {}", name, puzzle.steno, status);
+        }
+        println!("{} of {} puzzles passed", puzzles.len() - failed, puzzles.len());
+    }
+    found_exit(failed == 0)
+}
+
+#[derive(Args)]
+struct MergeArgs {
+    /// Outputs of `solve --shard`, in any solution format but tree
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+}
 
-                    return is_diagonal_move && piece_on_dest.is_none();
+// The summary lines solve prints after the solutions.
+const SUMMARY_PREFIXES: [&str; 4] = ["Number of solutions found: ", "Distinct final positions: ", "Distinct solution classes: ", "Solutions up to color symmetry: "];
+
+// Solutions as printed: a line each, but a PGN game from its first header to
+// its movetext.
+fn printed_solutions(output: &str) -> Vec<String> {
+    let mut solutions = Vec::new();
+    let mut game = String::new();
+    for line in output.lines() {
+        if game.is_empty() && (line.is_empty() || SUMMARY_PREFIXES.iter().any(|prefix| line.starts_with(prefix))) {
+            continue;
+        }
+        game.push_str(line);
+        game.push('\n');
+        if !line.starts_with('[') && !line.is_empty() {
+            solutions.push(std::mem::take(&mut game));
+        }
+    }
+    solutions
+}
+
+fn run_merge(args: MergeArgs, quiet: bool) -> ExitCode {
+    let mut seen = HashSet::new();
+    let mut solutions = 0u64;
+    for path in &args.files {
+        let output = match fs::read_to_string(path) {
+            Ok(output) => output,
+            Err(err) => return runtime_error(format!("{}: {}", path.display(), err)),
+        };
+        for solution in printed_solutions(&output) {
+            if seen.insert(solution.clone()) {
+                solutions += 1;
+                if !quiet {
+                    print!("{}", solution);
+                    if solution.starts_with('[') {
+                        println!();
+                    }
                 }
             }
-            return false;
         }
-        '=' => matches!(board.status(), BoardStatus::Stalemate),
-        'o' => {
-            (last_piece_moved.unwrap() == Piece::King) &&
-                ((source_square == Square::E1 && dest_square == Square::G1) || // White castling kingside
-                    (source_square == Square::E8 && dest_square == Square::G8)) // Black castling kingside
+    }
+    if !quiet {
+        println!("Number of solutions found: {}", solutions);
+    }
+    found_exit(solutions > 0)
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    /// Output of the first run, in any solution format but tree
+    before: PathBuf,
+    /// Output of the second run
+    after: PathBuf,
+    /// The runs started from this position instead of the initial one
+    #[arg(long, value_parser = fen_arg)]
+    fen: Option<String>,
+}
+
+// The ID of a printed solution: the one it was tagged with by --ids, or else
+// that of its moves, read as UCI, SAN, a Lichess URL or a PGN game.
+fn printed_solution_id(solution: &str, fen_string: &Option<String>) -> Result<u64, String> {
+    if solution.starts_with('[') {
+        if let Some(id) = solution.lines().find_map(|line| line.strip_prefix("[SolutionId \"")?.strip_suffix("\"]")) {
+            return u64::from_str_radix(id, 16).map_err(|_| format!("Invalid solution ID: {}", id));
+        }
+        let game = parse_pgn(solution)?;
+        return Ok(solution_id(&moves_from_san(&game.fen.or(fen_string.clone()), &game.san_moves)?));
+    }
+    let line = solution.trim();
+    if let Some((id, _)) = line.split_once(' ').filter(|(id, _)| id.len() == 16) {
+        if let Ok(id) = u64::from_str_radix(id, 16) {
+            return Ok(id);
+        }
+    }
+    let tokens: Vec<String> = match line.strip_prefix("https://lichess.org/analysis/pgn/") {
+        // Numbered moves after a FEN header, from a run with --fen.
+        Some(url) if url.starts_with('[') => {
+            let game = parse_pgn(&url.replace("%22", "\"").replace('_', " ").replacen("] ", "]\n\n", 1))?;
+            return Ok(solution_id(&moves_from_san(&game.fen, &game.san_moves)?));
         }
-        '0' => {
-            (last_piece_moved.unwrap() == Piece::King) &&
-                ((source_square == Square::E1 && dest_square == Square::C1) || // White castling queenside
-                    (source_square == Square::E8 && dest_square == Square::C8)) // Black castling queenside
+        Some(url) => url.split('_').map(str::to_string).collect(),
+        None => line.split_whitespace().map(str::to_string).collect(),
+    };
+    if let Ok(moves) = tokens.iter().map(|token| ChessMove::from_str(token)).collect::<Result<Vec<_>, _>>() {
+        return Ok(solution_id(&moves));
+    }
+    Ok(solution_id(&moves_from_san(fen_string, &tokens)?))
+}
+
+// A run's solutions by ID, in the order they were printed.
+fn read_run(path: &PathBuf, fen_string: &Option<String>) -> Result<Vec<(u64, String)>, String> {
+    let output = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    printed_solutions(&output).into_iter().map(|solution| Ok((printed_solution_id(&solution, fen_string).map_err(|err| format!("{}: {}", path.display(), err))?, solution))).collect()
+}
+
+fn run_diff(args: DiffArgs, quiet: bool) -> ExitCode {
+    let (before, after) = match (read_run(&args.before, &args.fen), read_run(&args.after, &args.fen)) {
+        (Ok(before), Ok(after)) => (before, after),
+        (Err(err), _) | (_, Err(err)) => return runtime_error(err),
+    };
+    let before_ids: HashSet<u64> = before.iter().map(|(id, _)| *id).collect();
+    let after_ids: HashSet<u64> = after.iter().map(|(id, _)| *id).collect();
+    let lost: Vec<_> = before.iter().filter(|(id, _)| !after_ids.contains(id)).collect();
+    let gained: Vec<_> = after.iter().filter(|(id, _)| !before_ids.contains(id)).collect();
+    if !quiet {
+        for (sign, solutions) in [('-', &lost), ('+', &gained)] {
+            for (id, solution) in solutions {
+                let first_line = solution.lines().find(|line| !line.starts_with('[')).unwrap_or_default();
+                println!("{} {} {}", sign, format_solution_id(*id), first_line);
+            }
         }
-        'r' => {
-            let promotion = last_move_unwrapped.get_promotion();
-            promotion == Some(Piece::Rook)
+        println!("Only in {}: {}", args.before.display(), lost.len());
+        println!("Only in {}: {}", args.after.display(), gained.len());
+        println!("In both: {}", before_ids.intersection(&after_ids).count());
+    }
+    found_exit(lost.is_empty() && gained.is_empty())
+}
+
+#[derive(Args)]
+struct LintArgs {
+    /// Steno string, one constraint character per ply
+    steno: String,
+    /// Also match this steno of the same length, ply by ply (repeatable)
+    #[arg(long = "and", value_name = "STENO")]
+    and_stenos: Vec<String>,
+    /// Start from this position instead of the initial one
+    #[arg(long, value_parser = fen_arg)]
+    fen: Option<String>,
+    /// Game ply the steno's first ply is, as for solve
+    #[arg(long, value_name = "K", value_parser = clap::value_parser!(u32).range(1..))]
+    start_ply: Option<u32>,
+    /// Notation the stenos are written in
+    #[arg(long, value_name = "default|english|german|numeric", default_value = "default")]
+    dialect: Dialect,
+}
+
+// Exits with EXIT_NO_SOLUTIONS when a warning shows the steno can't be solved.
+fn run_lint(args: LintArgs) -> ExitCode {
+    let stenos: Result<Vec<Vec<Constraint>>, String> = iter::once(&args.steno).chain(&args.and_stenos)
+        .map(|steno| parse_steno_string(&translate_steno(steno, args.dialect)))
+        .collect();
+    let steno_constraints = match stenos.and_then(|stenos| intersect_stenos(&stenos)) {
+        Ok(steno_constraints) => steno_constraints,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_INVALID_STENO);
         }
-        'n' => {
-            let promotion = last_move_unwrapped.get_promotion();
-            promotion == Some(Piece::Knight)
+    };
+    let (steno_constraints, fen_string) = match at_start_ply(steno_constraints, args.fen, args.start_ply) {
+        Ok(started) => started,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_INVALID_STENO);
+        }
+    };
+    let board = match start_board(&fen_string) {
+        Ok(board) => board,
+        Err(err) => return runtime_error(err),
+    };
+
+    let lint = lint_steno(&board, halfmove_clock(&fen_string), &steno_constraints);
+    for warning in &lint.warnings {
+        println!("warning: {}", warning);
+    }
+    for note in &lint.notes {
+        println!("note: {}", note);
+    }
+    if lint.warnings.is_empty() && lint.notes.is_empty() {
+        println!("No problems found");
+    }
+    found_exit(lint.warnings.is_empty())
+}
+
+fn run_explain(args: LintArgs) -> ExitCode {
+    let stenos: Result<Vec<Vec<Constraint>>, String> = iter::once(&args.steno).chain(&args.and_stenos)
+        .map(|steno| parse_steno_string(&translate_steno(steno, args.dialect)))
+        .collect();
+    let steno_constraints = match stenos.and_then(|stenos| intersect_stenos(&stenos)) {
+        Ok(steno_constraints) => steno_constraints,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_INVALID_STENO);
         }
-        'l' => {
-            let promotion = last_move_unwrapped.get_promotion();
-            promotion == Some(Piece::Bishop)
+    };
+    let (steno_constraints, fen_string) = match at_start_ply(steno_constraints, args.fen, args.start_ply) {
+        Ok(started) => started,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_INVALID_STENO);
         }
-        'q' => {
-            let promotion = last_move_unwrapped.get_promotion();
-            promotion == Some(Piece::Queen)
+    };
+    let board = match start_board(&fen_string) {
+        Ok(board) => board,
+        Err(err) => return runtime_error(err),
+    };
+    let fullmove = fen_string.as_deref().and_then(|fen| fen.split_whitespace().nth(5)?.parse().ok()).unwrap_or(1);
+
+    for line in explain_steno(&steno_constraints, board.side_to_move(), fullmove) {
+        println!("{}", line);
+    }
+    ExitCode::SUCCESS
+}
+
+#[derive(Args)]
+struct SuggestArgs {
+    /// Steno string, one constraint character per ply
+    #[arg(value_parser = steno_arg)]
+    steno: String,
+    /// Start from this position instead of the initial one
+    #[arg(long, value_parser = fen_arg)]
+    fen: Option<String>,
+    /// Most plies to change in one suggestion
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    max_changes: usize,
+    /// Stop after this many suggestions
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    limit: usize,
+    /// Worker threads (defaults to one per core)
+    #[arg(long, value_name = "N")]
+    threads: Option<usize>,
+}
+
+fn run_suggest(args: SuggestArgs, config: &Config) -> ExitCode {
+    let board = match start_board(&args.fen) {
+        Ok(board) => board,
+        Err(err) => return runtime_error(err),
+    };
+    let steno_constraints = parse_steno_string(&args.steno).unwrap();
+
+    let (suggestions, _) = with_threads(args.threads.or(config.threads), || suggest_unique(board, halfmove_clock(&args.fen), &steno_constraints, args.max_changes, args.limit));
+    let suggestions = match suggestions {
+        Ok(suggestions) => suggestions,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_NO_SOLUTIONS);
         }
-        _ => false,
+    };
+    if suggestions.is_empty() {
+        println!("The steno already has a single solution");
+        return ExitCode::SUCCESS;
     }
+
+    for suggestion in suggestions {
+        let changes: Vec<String> = suggestion.changes.iter()
+            .map(|(ply, constraint)| format!("ply {} from {} to {}", ply + 1, steno_constraints[*ply], constraint))
+            .collect();
+        println!("{}  ({})", steno_string(&suggestion.steno_constraints), changes.join(", "));
+    }
+    ExitCode::SUCCESS
+}
+
+#[derive(Args)]
+struct GenerateArgs {
+    /// Length of the random game
+    #[arg(long, default_value_t = 6)]
+    plies: usize,
+    /// Seed for a reproducible game
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Keep playing games until one's steno has a single solution, then weaken it
+    #[arg(long)]
+    unique: bool,
+    /// How many games to try with --unique
+    #[arg(long, default_value_t = 50)]
+    attempts: usize,
+    /// Start position for the random game
+    #[arg(long, value_parser = fen_arg)]
+    fen: Option<String>,
 }
 
-fn enumerate_positions(board: Board, depth: u8, path: Vec<ChessMove>, fen_string: &Option<String>, last_move: Option<ChessMove>, last_piece_moved: Option<Piece>, piece_on_dest: Option<Piece>, results: &Mutex<u32>, steno_constraints: &[char]) {
-    if !check_steno_constraints(&board, last_move, last_piece_moved, piece_on_dest, depth, steno_constraints) {
-        return;
+fn run_generate(args: GenerateArgs) -> ExitCode {
+    let board = match start_board(&args.fen) {
+        Ok(board) => board,
+        Err(err) => return runtime_error(err),
+    };
+    let mut rng = seeded_rng(args.seed);
+
+    let attempts = if args.unique { args.attempts } else { 1 };
+    for _ in 0..attempts {
+        let Some(moves) = random_game(board, args.plies, &mut rng) else {
+            eprintln!("Could not play a {}-ply game from this position", args.plies);
+            return ExitCode::from(EXIT_NO_SOLUTIONS);
+        };
+        let steno = steno_for_game(board, &moves).unwrap();
+        let steno = if args.unique {
+            match weaken_to_unique(board, halfmove_clock(&args.fen), &steno) {
+                Some(weakened) => weakened,
+                None => continue,
+            }
+        } else {
+            steno
+        };
+        println!("{}", steno);
+        println!("Game: {}", render_solution(&args.fen, &moves, OutputFormat::San));
+        return ExitCode::SUCCESS;
     }
 
-    if depth as usize == steno_constraints.len() {
-        let mut num_results = results.lock().unwrap();
-        *num_results += 1;
+    eprintln!("None of {} random games had a unique steno", attempts);
+    ExitCode::from(EXIT_NO_SOLUTIONS)
+}
+
+#[derive(Args)]
+struct PlayArgs {
+    /// A random puzzle instead of today's
+    #[arg(long)]
+    random: bool,
+    /// Seed for a reproducible --random pick
+    #[arg(long, requires = "random")]
+    seed: Option<u64>,
+    /// The puzzle with this number in --list
+    #[arg(long, value_name = "N", conflicts_with = "random")]
+    number: Option<usize>,
+    /// Play the puzzles of a collection file instead of the built-in ones
+    #[arg(long, value_name = "FILE")]
+    collection: Option<PathBuf>,
+    /// List the puzzles and exit
+    #[arg(long)]
+    list: bool,
+}
 
-        let mut moves = Vec::new();
-        let mut position = Chess::default();
-        if let Some(fen) = fen_string.clone() {
-            let fen_position: Fen = fen.parse().unwrap();
-            position = fen_position.into_position(CastlingMode::Standard).unwrap();
+// A move number as a prompt: "3." for White's move, "3..." for Black's.
+fn move_prompt(board: Board, fullmove: u32, ply: usize) -> String {
+    let black_starts = board.side_to_move() == chess::Color::Black;
+    let number = fullmove as usize + (ply + black_starts as usize) / 2;
+    if (ply + black_starts as usize).is_multiple_of(2) { format!("{}.", number) } else { format!("{}...", number) }
+}
+
+// Serves one puzzle and checks each move entered as soon as it's played,
+// so a wrong one can be taken back, then tells how many games the steno
+// has. Exits 1 when the player gives up.
+fn run_play(args: PlayArgs) -> ExitCode {
+    let puzzles = match &args.collection {
+        Some(path) => match load_collection(path) {
+            Ok(puzzles) => puzzles,
+            Err(err) => return runtime_error(err),
+        },
+        None => builtin_puzzles(),
+    };
+    if puzzles.is_empty() {
+        return runtime_error("The collection has no puzzles");
+    }
+    let name = |index: usize| puzzles[index].name.clone().unwrap_or_else(|| format!("#{}", index + 1));
+    if args.list {
+        for (index, puzzle) in puzzles.iter().enumerate() {
+            println!("{:>3}. {}  {}", index + 1, name(index), puzzle.steno);
         }
-        for mov in &path {
-            let uci: Uci = mov.to_string().parse().unwrap();
-            let uci_move = uci.to_move(&position).unwrap();
-            let san_move = San::from_move(&position, &uci_move);
-            moves.push(san_move.to_string());
-            position = position.clone().play(&uci_move).unwrap();
+        return ExitCode::SUCCESS;
+    }
+    let index = match args.number {
+        Some(number) if number == 0 || number > puzzles.len() => return runtime_error(format!("There is no puzzle {}; the puzzles are numbered 1 to {}", number, puzzles.len())),
+        Some(number) => number - 1,
+        None if args.random => seeded_rng(args.seed).gen_range(0..puzzles.len()),
+        // The same puzzle all day, whoever asks.
+        None => {
+            let days = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / (24 * 60 * 60);
+            (days % puzzles.len() as u64) as usize
         }
+    };
+    let puzzle = &puzzles[index];
+
+    let steno_constraints = match parse_steno_string(&puzzle.steno) {
+        Ok(steno_constraints) => steno_constraints,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_INVALID_STENO);
+        }
+    };
+    let board = match start_board(&puzzle.fen) {
+        Ok(board) => board,
+        Err(err) => return runtime_error(err),
+    };
+    let fullmove = puzzle.fen.as_deref().and_then(|fen| fen.split_whitespace().nth(5)?.parse().ok()).unwrap_or(1);
+    let explained = explain_steno(&steno_constraints, board.side_to_move(), fullmove);
 
-        let lichess_url = format!("https://lichess.org/analysis/pgn/{}", moves.join("_"));
+    println!("Puzzle {} of {}: {}", index + 1, puzzles.len(), name(index));
+    if let Some(fen) = &puzzle.fen {
+        println!("From: {}", fen);
+    }
+    println!("Steno: {}", puzzle.steno);
+    for line in &explained {
+        println!("  {}", line);
+    }
+    println!("Enter the moves in SAN, one or more at a time. 'undo' takes back the last, 'give up' shows the intended solution.");
 
-        let stdout = io::stdout();
-        let mut handle = stdout.lock();
-        writeln!(handle, "{}", lichess_url).unwrap();
-        return;
+    let mut san_moves: Vec<String> = Vec::new();
+    let mut moves = Vec::new();
+    let solved = 'play: loop {
+        if san_moves.len() == steno_constraints.len() {
+            break true;
+        }
+        eprint!("{} ", move_prompt(board, fullmove, san_moves.len()));
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            println!();
+            break false;
+        }
+        match line.trim() {
+            "give up" | "quit" => break false,
+            "undo" => {
+                san_moves.pop();
+                moves.pop();
+                continue;
+            }
+            _ => {}
+        }
+        // Move numbers may be typed along, "1.e4" or "1. e4 e5".
+        let tokens = line.split_whitespace().map(|token| match token.split_once('.') {
+            Some((number, san)) if number.bytes().all(|byte| byte.is_ascii_digit()) => san.trim_start_matches('.'),
+            _ => token,
+        });
+        for token in tokens.filter(|token| !token.is_empty()) {
+            if san_moves.len() == steno_constraints.len() {
+                println!("The steno ends at ply {}; ignoring {} and after", steno_constraints.len(), token);
+                continue 'play;
+            }
+            let mut tried = san_moves.clone();
+            tried.push(token.to_string());
+            let played = match moves_from_san(&puzzle.fen, &tried) {
+                Ok(played) => played,
+                Err(err) => {
+                    println!("{}", err);
+                    continue 'play;
+                }
+            };
+            if verify_game(board, halfmove_clock(&puzzle.fen), &steno_constraints[..tried.len()], &played).is_err() {
+                println!("{} doesn't fit {}", token, explained[san_moves.len()]);
+                continue 'play;
+            }
+            san_moves = tried;
+            moves = played;
+        }
+    };
+
+    let intended = parse_pgn(&puzzle.solution).and_then(|game| moves_from_san(&puzzle.fen, &game.san_moves));
+    if solved {
+        println!("Solved: {}", render_solution(&puzzle.fen, &moves, OutputFormat::San));
+        match &intended {
+            Ok(intended) if *intended == moves => println!("That's the intended solution"),
+            _ => println!("The intended solution was {}", puzzle.solution),
+        }
+    } else {
+        println!("The intended solution: {}", puzzle.solution);
+    }
+    match count_solutions(board, halfmove_clock(&puzzle.fen), &steno_constraints, None) {
+        1 => println!("The steno has 1 solution"),
+        solutions => println!("The steno has {} solutions", solutions),
     }
+    found_exit(solved)
+}
 
-    let moves: Vec<ChessMove> = MoveGen::new_legal(&board).collect();
+#[derive(Args)]
+struct ComposeArgs {
+    /// Steno of the first plies
+    prefix: String,
+    /// Steno of the plies after them
+    suffix: String,
+    /// The prefix's positions as printed by `solve PREFIX --prefix-depth N` or --save-positions, instead of solving it again
+    #[arg(long, value_name = "FILE")]
+    positions: Option<PathBuf>,
+    /// Write the prefix's positions to this file, to pass as --positions while changing the suffix
+    #[arg(long, value_name = "FILE", conflicts_with = "positions")]
+    save_positions: Option<PathBuf>,
+    /// Start from this position instead of the initial one
+    #[arg(long, value_parser = fen_arg)]
+    fen: Option<String>,
+    /// Notation the stenos are written in
+    #[arg(long, value_name = "default|english|german|numeric", default_value = "default")]
+    dialect: Dialect,
+    /// Worker threads (defaults to one per core)
+    #[arg(long, value_name = "N")]
+    threads: Option<usize>,
+    /// Print search statistics for the suffix searches
+    #[arg(long)]
+    stats: bool,
+}
 
-    moves.par_iter().for_each(|&mov| {
-        let mut new_board = board.clone();
-        let piece_moved = board.piece_on(mov.get_source());
-        let piece_on_dest = board.piece_on(mov.get_dest());
-        board.make_move(mov, &mut new_board);
-        let mut new_path = path.clone();
-        new_path.push(mov);
+fn run_compose(args: ComposeArgs, config: &Config, quiet: bool) -> ExitCode {
+    let (prefix_constraints, suffix_constraints) = match (parse_steno_string(&translate_steno(&args.prefix, args.dialect)), parse_steno_string(&translate_steno(&args.suffix, args.dialect))) {
+        (Ok(prefix), Ok(suffix)) => (prefix, suffix),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_INVALID_STENO);
+        }
+    };
+    let board = match start_board(&args.fen) {
+        Ok(board) => board,
+        Err(err) => return runtime_error(err),
+    };
+    let options = SolveOptions {
+        print_solutions: false,
+        ..SolveOptions::default()
+    };
 
-        enumerate_positions(new_board, depth + 1, new_path, &fen_string, Some(mov), piece_moved, piece_on_dest, results, steno_constraints);
+    let (outcome, _) = with_threads(args.threads.or(config.threads), || {
+        let positions = match &args.positions {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|contents| read_prefix_positions(&contents, &board, prefix_constraints.len()))
+                .map_err(|err| format!("{}: {}", path.display(), err))?,
+            None => prefix_positions(board, &prefix_constraints, &options).0,
+        };
+        if let Some(path) = &args.save_positions {
+            fs::write(path, render_prefix_positions(&positions, prefix_constraints.len())).map_err(|err| format!("{}: {}", path.display(), err))?;
+        }
+        let prefix_count = positions.len();
+        continue_positions(&positions, &suffix_constraints, &options).map(|(continued, stats)| (prefix_count, continued, stats))
     });
+    let (prefix_count, mut continued, stats) = match outcome {
+        Ok(outcome) => outcome,
+        Err(err) => return runtime_error(err),
+    };
+    continued.retain(|continued| continued.suffix_solutions > 0);
+    continued.sort_by_cached_key(|continued| (std::cmp::Reverse(continued.games()), continued.position.to_string()));
+    if !quiet {
+        for continued in &continued {
+            let fen = continued.position.to_string();
+            println!("{} {}", continued.games(), fen.split(' ').take(4).collect::<Vec<_>>().join(" "));
+        }
+        println!("Composed steno: {}{}", steno_string(&prefix_constraints), steno_string(&suffix_constraints));
+        println!("Prefix positions continued: {} of {}", continued.len(), prefix_count);
+        println!("Number of solutions found: {}", stats.solutions);
+        if args.stats {
+            println!("{}", stats);
+        }
+    }
+    found_exit(stats.solutions > 0)
+}
+
+#[derive(Args)]
+struct PerftArgs {
+    /// Number of plies to search
+    depth: usize,
+    /// Start from this position instead of the initial one
+    #[arg(long, value_parser = fen_arg)]
+    fen: Option<String>,
+}
+
+fn run_perft(args: PerftArgs) -> ExitCode {
+    let board = match start_board(&args.fen) {
+        Ok(board) => board,
+        Err(err) => return runtime_error(err),
+    };
+    let depth = args.depth;
+
+    let stats = perft(board, depth);
+    println!("perft({}) = {}", depth, stats.solutions);
+    println!("Nodes visited: {}", stats.nodes_visited);
+    println!("Elapsed: {:?}", stats.elapsed);
+    println!("Nodes/sec: {:.0}", stats.nodes_per_sec());
+
+    ExitCode::SUCCESS
+}
+
+#[derive(Args)]
+struct IndexArgs {
+    /// Plies of every game to index; each ply costs about 30 times the last, 50 MB at 5
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    plies: usize,
+    /// File to write the index to
+    #[arg(short, long, value_name = "FILE")]
+    output: PathBuf,
 }
 
-fn solve(board: Board, fen_string: Option<String>, steno_constraints: &[char]) {
-    let results = Mutex::new(0);
-    enumerate_positions(board, 0, Vec::new(), &fen_string, None, None, None, &results, &steno_constraints);
+fn run_index(args: IndexArgs) -> ExitCode {
+    let index = OpeningIndex::build(args.plies);
+    if let Err(err) = index.save(&args.output) {
+        return runtime_error(format!("{}: {}", args.output.display(), err));
+    }
+    println!("Indexed {} moves to ply {}", index.moves(), index.plies());
+    ExitCode::SUCCESS
+}
 
-    let solutions_count = results.lock().unwrap();
-    println!("Number of solutions found: {}", solutions_count);
+#[derive(Args)]
+struct CacheArgs {
+    #[command(subcommand)]
+    command: CacheCommand,
 }
 
-fn main() -> Result<(), Box<chess::Error>> {
-    let args: Vec<String> = env::args().collect();
-    let mut fen_string = None;
-    let mut steno_string = None;
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Remove every cached result
+    Clear,
+    /// Print where cached results are kept ($STENO_SOLVER_CACHE_DIR, else ~/.cache/steno-solver)
+    Dir,
+}
 
-    let mut args_iter = args.iter().skip(1);
-    while let Some(arg) = args_iter.next() {
-        match arg.as_str() {
-            "--fen" => fen_string = args_iter.next().cloned(),
-            _ => steno_string = Some(arg.clone()),
+fn run_cache(args: CacheArgs, quiet: bool) -> ExitCode {
+    let Some(dir) = cache_dir() else {
+        return runtime_error("No cache directory: set STENO_SOLVER_CACHE_DIR or HOME");
+    };
+    match args.command {
+        CacheCommand::Clear => match ResultCache::new(&dir).clear() {
+            Ok(removed) => {
+                if !quiet {
+                    println!("Removed {} cached results from {}", removed, dir.display());
+                }
+                ExitCode::SUCCESS
+            }
+            Err(err) => runtime_error(format!("{}: {}", dir.display(), err)),
+        },
+        CacheCommand::Dir => {
+            println!("{}", dir.display());
+            ExitCode::SUCCESS
         }
     }
+}
 
-    if steno_string.is_none() {
-        eprintln!("Usage: steno_solver [--fen \"<fen_string>\"] <steno_string>");
-        return Ok(());
+#[derive(Args)]
+struct CompletionsArgs {
+    #[arg(value_name = "bash|zsh|fish")]
+    shell: Shell,
+}
+
+fn run_completions(args: CompletionsArgs) -> ExitCode {
+    print!("{}", completion_script(&Cli::command(), args.shell));
+    ExitCode::SUCCESS
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// Worker threads (defaults to one per core)
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+fn run_bench_suite(args: BenchArgs, config: &Config) -> ExitCode {
+    let (results, threads) = with_threads(args.threads.or(config.threads), run_bench);
+
+    println!("steno\tplies\tthreads\tsolutions\tnodes\tms\tnodes_per_sec");
+    for result in results {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{:.3}\t{:.0}",
+            result.steno,
+            result.steno.chars().count(),
+            threads,
+            result.stats.solutions,
+            result.stats.nodes_visited,
+            result.stats.elapsed.as_secs_f64() * 1000.0,
+            result.stats.nodes_per_sec(),
+        );
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(feature = "server")]
+#[derive(Args)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, value_name = "HOST:PORT")]
+    addr: Option<String>,
+    /// How many jobs may run at once
+    #[arg(long, value_name = "N", conflicts_with = "coordinator")]
+    max_concurrent: Option<usize>,
+    /// Keep jobs and their solutions in this directory, so they survive a restart
+    #[arg(long, value_name = "DIR", conflicts_with = "coordinator")]
+    data_dir: Option<PathBuf>,
+    /// Keep at most N solutions of each job to serve and save, counting the rest
+    #[arg(long, value_name = "N", conflicts_with = "coordinator")]
+    max_solutions: Option<usize>,
+    /// Instead of serving jobs, split this steno into tasks for `work` processes and print the solutions once all are searched
+    #[arg(long, value_name = "STENO", value_parser = steno_arg)]
+    coordinator: Option<String>,
+    /// Start position for the coordinated steno
+    #[arg(long, value_parser = fen_arg, requires = "coordinator")]
+    fen: Option<String>,
+    /// Make each task the games' first PLY moves
+    #[arg(long, value_name = "PLY", default_value_t = SHARD_PLY, requires = "coordinator")]
+    task_ply: usize,
+    /// Hand a task to another worker when it isn't reported back in this many seconds
+    #[arg(long, value_name = "SECS", default_value_t = 300, requires = "coordinator")]
+    lease: u64,
+    /// How to print each solution [default: url]
+    #[arg(long, value_name = "url|san|uci|pgn|tree", requires = "coordinator")]
+    format: Option<OutputFormat>,
+}
+
+#[cfg(feature = "server")]
+fn run_coordinator(args: ServeArgs, steno: String, config: &Config, quiet: bool) -> ExitCode {
+    let mut coordinator = CoordinatorConfig {
+        steno,
+        fen: args.fen,
+        task_ply: args.task_ply,
+        lease: std::time::Duration::from_secs(args.lease),
+        ..CoordinatorConfig::default()
+    };
+    if let Some(addr) = args.addr {
+        coordinator.addr = addr;
+    }
+
+    let mut result = match coordinate(&coordinator) {
+        Ok(result) => result,
+        Err(err) => return runtime_error(err),
+    };
+    if !quiet {
+        let format = args.format.or(config.format).unwrap_or_default();
+        result.solutions.sort_by_key(|path| path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>());
+        if format == OutputFormat::Tree {
+            print!("{}", render_tree(&coordinator.fen, &result.solutions, false));
+        } else {
+            for path in &result.solutions {
+                println!("{}", render_solution(&coordinator.fen, path, format));
+            }
+        }
+        println!("Number of solutions found: {}", result.solutions.len());
+        eprintln!("{} tasks, {} reassigned, {} nodes in {:?}", result.tasks, result.reassigned, result.nodes_visited, result.elapsed);
     }
+    found_exit(!result.solutions.is_empty())
+}
+
+#[cfg(feature = "server")]
+#[derive(Args)]
+struct WorkArgs {
+    /// Coordinator address
+    #[arg(long, value_name = "HOST:PORT")]
+    connect: String,
+    /// Name to report to the coordinator [default: host name and process id]
+    #[arg(long)]
+    name: Option<String>,
+    /// Worker threads (defaults to one per core)
+    #[arg(long, value_name = "N")]
+    threads: Option<usize>,
+}
 
-    let board = match fen_string.clone() {
-        Some(fen) => Board::from_str(&fen)?,
-        None => Board::default(),
+#[cfg(feature = "server")]
+fn run_worker(args: WorkArgs, config: &Config, quiet: bool) -> ExitCode {
+    let name = args.name.unwrap_or_else(|| {
+        let host = std::env::var("HOSTNAME").or_else(|_| fs::read_to_string("/etc/hostname").map(|host| host.trim().to_string())).unwrap_or_else(|_| "worker".to_string());
+        format!("{}-{}", host, std::process::id())
+    });
+    let (result, _) = with_threads(args.threads.or(config.threads), || work(&args.connect, &name));
+    match result {
+        Ok(stats) => {
+            if !quiet {
+                println!("Searched {} tasks, {} solutions", stats.tasks, stats.solutions);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => runtime_error(err),
+    }
+}
+
+#[cfg(feature = "server")]
+fn run_server(args: ServeArgs, config: &Config, quiet: bool) -> ExitCode {
+    if let Some(steno) = args.coordinator.clone() {
+        return run_coordinator(args, steno, config, quiet);
+    }
+    let mut config = ServerConfig::default();
+    if let Some(addr) = args.addr {
+        config.addr = addr;
+    }
+    if let Some(n) = args.max_concurrent {
+        config.max_concurrent = n;
+    }
+    if let Some(n) = args.max_solutions {
+        config.max_solutions = n;
+    }
+    config.data_dir = args.data_dir;
+
+    match serve(&config) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => runtime_error(err),
+    }
+}
+
+// Logs go to stderr so they never mix with solutions on stdout.
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match verbose {
+        _ if quiet => Level::ERROR,
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_target(false)
+        .with_writer(io::stderr)
+        .init();
+}
+
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        // --help and --version land here too, and aren't failures.
+        Err(err) if !err.use_stderr() => {
+            err.print().unwrap();
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            let _ = err.print();
+            return ExitCode::from(EXIT_INVALID_STENO);
+        }
+    };
+    init_logging(cli.verbose, cli.quiet);
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => return runtime_error(err),
     };
 
-    match parse_steno_string(&steno_string.unwrap()) {
-        Ok(steno_constraints) => solve(board, fen_string, &steno_constraints),
-        Err(err) => eprintln!("{}", err),
+    if cli.print_schema {
+        println!("{}", serde_json::to_string_pretty(&cli_schema(&Cli::command())).unwrap());
+        return ExitCode::SUCCESS;
     }
 
-    Ok(())
+    match cli.command {
+        Some(Command::Solve(args)) => run_solve(*args, &config, cli.quiet, cli.verbose),
+        Some(Command::Verify(args)) => run_verify(args, cli.quiet),
+        Some(Command::VerifyCollection(args)) => run_verify_collection(args, &config, cli.quiet),
+        Some(Command::Merge(args)) => run_merge(args, cli.quiet),
+        Some(Command::Diff(args)) => run_diff(args, cli.quiet),
+        Some(Command::Lint(args)) => run_lint(args),
+        Some(Command::Explain(args)) => run_explain(args),
+        Some(Command::Suggest(args)) => run_suggest(args, &config),
+        Some(Command::FromGame(args)) => run_from_game(args),
+        Some(Command::Generate(args)) => run_generate(args),
+        Some(Command::Play(args)) => run_play(args),
+        Some(Command::Compose(args)) => run_compose(args, &config, cli.quiet),
+        Some(Command::Perft(args)) => run_perft(args),
+        Some(Command::Index(args)) => run_index(args),
+        Some(Command::Bench(args)) => run_bench_suite(args, &config),
+        Some(Command::Cache(args)) => run_cache(args, cli.quiet),
+        Some(Command::Completions(args)) => run_completions(args),
+        #[cfg(feature = "server")]
+        Some(Command::Serve(args)) => run_server(args, &config, cli.quiet),
+        #[cfg(feature = "server")]
+        Some(Command::Work(args)) => run_worker(args, &config, cli.quiet),
+        None => run_solve(cli.solve.expect("clap requires a steno without a subcommand"), &config, cli.quiet, cli.verbose),
+    }
 }
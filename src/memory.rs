@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Bytes the big solve-time tables may hold between them: dedup sets, the
+// two-stage prefix table, the forced solver's proofs and the games kept for
+// the result cache. Each reserves what it adds, and when refused shrinks or
+// does without rather than grow past the cap.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: u64,
+    used: AtomicU64,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: u64) -> MemoryBudget {
+        MemoryBudget { limit, used: AtomicU64::new(0) }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn available(&self) -> u64 {
+        self.limit.saturating_sub(self.used())
+    }
+
+    // Takes `bytes` out of the budget, or nothing when that would go over.
+    pub fn reserve(&self, bytes: u64) -> bool {
+        self.used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| used.checked_add(bytes).filter(|&total| total <= self.limit)).is_ok()
+    }
+
+    pub fn release(&self, bytes: u64) {
+        let _ = self.used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| Some(used.saturating_sub(bytes)));
+    }
+}
+
+// A size like 8G, 512M, 64K or a plain count of bytes; the units are powers
+// of 1024.
+pub fn parse_memory_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (digits, shift) = match size.char_indices().last() {
+        Some((at, 'K' | 'k')) => (&size[..at], 10),
+        Some((at, 'M' | 'm')) => (&size[..at], 20),
+        Some((at, 'G' | 'g')) => (&size[..at], 30),
+        Some((at, 'T' | 't')) => (&size[..at], 40),
+        _ => (size, 0),
+    };
+    let count: u64 = digits.parse().map_err(|_| format!("Invalid memory size: {} (expected a number of bytes, or one with K, M, G or T)", size))?;
+    count.checked_mul(1 << shift).ok_or_else(|| format!("Memory size too large: {}", size))
+}
@@ -0,0 +1,67 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tiny_http::{Header, Request, Response};
+
+use crate::stats::{memory_bytes, peak_memory_bytes};
+
+// What GET /metrics reports, for both the job server and the coordinator.
+pub(crate) struct Metrics {
+    // Handed to the searches as SolveOptions::nodes_visited.
+    pub nodes_visited: Arc<AtomicU64>,
+    pub solutions: AtomicU64,
+    // When the last scrape was, and the node count then, for the rate.
+    last_scrape: Mutex<(Instant, u64)>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            nodes_visited: Arc::new(AtomicU64::new(0)),
+            solutions: AtomicU64::new(0),
+            last_scrape: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    // Nodes per second since the previous scrape, or since startup for the
+    // first one.
+    fn nodes_per_sec(&self, nodes: u64) -> f64 {
+        let mut last_scrape = self.last_scrape.lock().unwrap();
+        let (then, nodes_then) = *last_scrape;
+        *last_scrape = (Instant::now(), nodes);
+        let secs = then.elapsed().as_secs_f64();
+        if secs > 0.0 {
+            nodes.saturating_sub(nodes_then) as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    // The Prometheus text format. On the coordinator, jobs are its tasks and
+    // nodes are counted as workers report them.
+    pub fn render(&self, active_jobs: usize, queued_jobs: usize) -> String {
+        let nodes = self.nodes_visited.load(Ordering::Relaxed);
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = write!(out, "# HELP {} {}\n# TYPE {} {}\n{} {}\n", name, help, name, kind, name, value);
+        };
+        metric("steno_nodes_visited_total", "counter", "Search nodes visited.", nodes.to_string());
+        metric("steno_nodes_per_second", "gauge", "Nodes visited per second since the previous scrape.", format!("{:.1}", self.nodes_per_sec(nodes)));
+        metric("steno_active_jobs", "gauge", "Jobs being solved.", active_jobs.to_string());
+        metric("steno_queued_jobs", "gauge", "Jobs waiting to be solved.", queued_jobs.to_string());
+        metric("steno_solutions_emitted_total", "counter", "Solutions found.", self.solutions.load(Ordering::Relaxed).to_string());
+        if let Some(bytes) = memory_bytes() {
+            metric("steno_resident_memory_bytes", "gauge", "Resident memory.", bytes.to_string());
+        }
+        if let Some(bytes) = peak_memory_bytes() {
+            metric("steno_peak_memory_bytes", "gauge", "Peak resident memory.", bytes.to_string());
+        }
+        out
+    }
+}
+
+pub(crate) fn respond_metrics(request: Request, body: String) {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..]).unwrap();
+    let _ = request.respond(Response::from_string(body).with_header(content_type));
+}
@@ -0,0 +1,217 @@
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, ALL_PIECES, ALL_SQUARES};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::steno::{CastleSide, Constraint, MoveContext};
+
+const MAGIC: &[u8; 8] = b"STENOIDX";
+const VERSION: u32 = 1;
+// Move, facts and subtree size, little-endian.
+const NODE_BYTES: usize = 10;
+
+// What a steno can ask of a move, packed: file, rank, mover, mover's color,
+// capture, en passant, check, mate, stalemate, castling side and promotion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct MoveFacts(u32);
+
+impl MoveFacts {
+    fn new(context: &MoveContext) -> MoveFacts {
+        let dest = context.mov.get_dest();
+        let status = context.board.status();
+        let castling = match context.castling {
+            None => 0,
+            Some(CastleSide::Kingside) => 1,
+            Some(CastleSide::Queenside) => 2,
+        };
+        MoveFacts(
+            dest.get_file().to_index() as u32
+                | (dest.get_rank().to_index() as u32) << 3
+                | (context.mover.to_index() as u32) << 6
+                | ((context.color == Color::Black) as u32) << 9
+                | (context.captured.is_some() as u32) << 10
+                | (context.en_passant as u32) << 11
+                | ((context.checkers.popcnt() > 0) as u32) << 12
+                | ((status == BoardStatus::Checkmate) as u32) << 13
+                | ((status == BoardStatus::Stalemate) as u32) << 14
+                | castling << 15
+                | (context.mov.get_promotion().map_or(0, |piece| piece.to_index() as u32)) << 17
+                | (context.in_check as u32) << 20,
+        )
+    }
+
+    fn field(self, shift: u32, bits: u32) -> usize {
+        ((self.0 >> shift) & ((1 << bits) - 1)) as usize
+    }
+
+    fn flag(self, shift: u32) -> bool {
+        self.field(shift, 1) == 1
+    }
+
+    // Whether the move satisfies `constraint`, or None when that takes more
+    // than these facts, as material, the line and --define predicates do.
+    fn satisfy(self, constraint: &Constraint) -> Option<bool> {
+        let castling = self.field(15, 2);
+        Some(match constraint {
+            Constraint::Any => true,
+            Constraint::File(file) => self.field(0, 3) == file.to_index(),
+            Constraint::Rank(rank) => self.field(3, 3) == rank.to_index(),
+            Constraint::Square(square) => self.field(0, 3) == square.get_file().to_index() && self.field(3, 3) == square.get_rank().to_index(),
+            Constraint::Mover(piece) => self.field(6, 3) == piece.to_index(),
+            Constraint::Capture => self.flag(10),
+            Constraint::EnPassant => self.flag(11),
+            Constraint::Check => self.flag(12),
+            Constraint::CheckNotMate => self.flag(12) && !self.flag(13),
+            Constraint::Checkmate => self.flag(13),
+            Constraint::Stalemate => self.flag(14),
+            Constraint::EscapeCheck => self.flag(20),
+            Constraint::Castle { side, color } => {
+                let side_matches = match side {
+                    None => castling != 0,
+                    Some(CastleSide::Kingside) => castling == 1,
+                    Some(CastleSide::Queenside) => castling == 2,
+                };
+                side_matches && color.is_none_or(|color| (color == Color::Black) == self.flag(9))
+            }
+            Constraint::Promotion(piece) => self.field(17, 3) == piece.to_index(),
+            Constraint::All(parts) => parts.iter().map(|part| self.satisfy(part)).collect::<Option<Vec<bool>>>()?.into_iter().all(|satisfied| satisfied),
+            _ => return None,
+        })
+    }
+}
+
+fn encode_move(mov: ChessMove) -> u16 {
+    mov.get_source().to_index() as u16 | (mov.get_dest().to_index() as u16) << 6 | (mov.get_promotion().map_or(0, |piece| piece.to_index() as u16)) << 12
+}
+
+fn decode_move(code: u16) -> ChessMove {
+    let square = |index: u16| ALL_SQUARES[index as usize];
+    let promotion = match code >> 12 {
+        0 => None,
+        piece => Some(ALL_PIECES[piece as usize]),
+    };
+    ChessMove::new(square(code & 63), square((code >> 6) & 63), promotion)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct IndexNode {
+    mov: u16,
+    facts: MoveFacts,
+    // This node and everything below it, so siblings can be skipped to.
+    size: u32,
+}
+
+// Every game from the initial position up to `plies`, as a move tree in
+// preorder. The first plies of a steno are matched against the facts stored
+// with each move, without generating or making any.
+pub struct OpeningIndex {
+    plies: usize,
+    nodes: Vec<IndexNode>,
+}
+
+fn add_games(board: &Board, plies: usize, nodes: &mut Vec<IndexNode>) {
+    if plies == 0 {
+        return;
+    }
+    for mov in MoveGen::new_legal(board) {
+        let after = board.make_move_new(mov);
+        let index = nodes.len();
+        nodes.push(IndexNode { mov: encode_move(mov), facts: MoveFacts::new(&MoveContext::new(board, mov, &after)), size: 1 });
+        add_games(&after, plies - 1, nodes);
+        nodes[index].size = (nodes.len() - index) as u32;
+    }
+}
+
+impl OpeningIndex {
+    // About 200k moves to ply 4 and 5M to ply 5, at NODE_BYTES each.
+    pub fn build(plies: usize) -> OpeningIndex {
+        let mut nodes = Vec::new();
+        add_games(&Board::default(), plies, &mut nodes);
+        OpeningIndex { plies, nodes }
+    }
+
+    pub fn plies(&self) -> usize {
+        self.plies
+    }
+
+    pub fn moves(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.write_all(&(self.plies as u32).to_le_bytes())?;
+        file.write_all(&(self.nodes.len() as u64).to_le_bytes())?;
+        for node in &self.nodes {
+            file.write_all(&node.mov.to_le_bytes())?;
+            file.write_all(&node.facts.0.to_le_bytes())?;
+            file.write_all(&node.size.to_le_bytes())?;
+        }
+        file.flush()
+    }
+
+    pub fn load(path: &Path) -> Result<OpeningIndex, String> {
+        let bytes = fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let invalid = || format!("{}: not an opening index of this version", path.display());
+        if bytes.len() < 24 || &bytes[..8] != MAGIC || bytes[8..12] != VERSION.to_le_bytes() {
+            return Err(invalid());
+        }
+        let plies = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        let count = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+        let body = &bytes[24..];
+        if body.len() != count * NODE_BYTES {
+            return Err(invalid());
+        }
+        let nodes = body.chunks_exact(NODE_BYTES).map(|node| IndexNode {
+            mov: u16::from_le_bytes(node[0..2].try_into().unwrap()),
+            facts: MoveFacts(u32::from_le_bytes(node[2..6].try_into().unwrap())),
+            size: u32::from_le_bytes(node[6..10].try_into().unwrap()),
+        }).collect();
+        Ok(OpeningIndex { plies, nodes })
+    }
+
+    // Whether the index can stand in for searching these plies: no deeper
+    // than it goes, and asking only what its facts answer.
+    pub(crate) fn covers(&self, prefix_constraints: &[Constraint]) -> bool {
+        let probe = MoveFacts(0);
+        prefix_constraints.len() <= self.plies && prefix_constraints.iter().all(|constraint| probe.satisfy(constraint).is_some())
+    }
+
+    // Every game matching `prefix_constraints`, which the index covers, and
+    // how many index nodes were looked at.
+    pub(crate) fn games(&self, prefix_constraints: &[Constraint]) -> (Vec<Vec<ChessMove>>, u64) {
+        let mut games = Vec::new();
+        let mut examined = 0;
+        if prefix_constraints.is_empty() {
+            return (vec![Vec::new()], 0);
+        }
+        self.collect(0, self.nodes.len(), prefix_constraints, &mut Vec::new(), &mut games, &mut examined);
+        (games, examined)
+    }
+
+    fn collect(&self, start: usize, end: usize, constraints: &[Constraint], path: &mut Vec<ChessMove>, games: &mut Vec<Vec<ChessMove>>, examined: &mut u64) {
+        let mut index = start;
+        while index < end {
+            let node = self.nodes[index];
+            *examined += 1;
+            if node.facts.satisfy(&constraints[path.len()]) == Some(true) {
+                path.push(decode_move(node.mov));
+                if path.len() == constraints.len() {
+                    games.push(path.clone());
+                } else {
+                    self.collect(index + 1, index + node.size as usize, constraints, path, games, examined);
+                }
+                path.pop();
+            }
+            index += node.size as usize;
+        }
+    }
+}
+
+impl std::fmt::Debug for OpeningIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "OpeningIndex {{ plies: {}, nodes: {} }}", self.plies, self.nodes.len())
+    }
+}
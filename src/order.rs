@@ -0,0 +1,71 @@
+use chess::{Board, ChessMove, MoveGen};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::str::FromStr;
+
+use crate::steno::{Constraint, MoveContext};
+
+// The order a node's moves are searched in. Every order finds the same
+// solutions; they only differ in which come first, which matters with a
+// limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MoveOrder {
+    // As the move generator produces them.
+    #[default]
+    Default,
+    ChecksFirst,
+    CapturesFirst,
+    Random,
+    // Moves the next constraint accepts first, those leaving the most
+    // replies for the constraint after it leading.
+    ConstraintGuided,
+}
+
+impl FromStr for MoveOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(MoveOrder::Default),
+            "checks-first" => Ok(MoveOrder::ChecksFirst),
+            "captures-first" => Ok(MoveOrder::CapturesFirst),
+            "random" => Ok(MoveOrder::Random),
+            "constraint-guided" => Ok(MoveOrder::ConstraintGuided),
+            _ => Err(format!("Unknown move order: {} (expected default, checks-first, captures-first, random or constraint-guided)", s)),
+        }
+    }
+}
+
+fn accepted(constraint: &Constraint, before: &Board, mov: ChessMove, after: &Board) -> bool {
+    constraint.matches(&MoveContext::new(before, mov, after))
+}
+
+// Reorders the legal moves of `board`, the node at `depth`, whose children
+// have to satisfy `steno_constraints[depth]`. A random order is drawn from
+// the seed and the node, so it doesn't depend on which thread gets there.
+pub(crate) fn order_moves(order: MoveOrder, seed: u64, board: &Board, steno_constraints: &[Constraint], depth: usize, moves: &mut [ChessMove]) {
+    match order {
+        MoveOrder::Default => {}
+        MoveOrder::ChecksFirst => moves.sort_by_cached_key(|&mov| board.make_move_new(mov).checkers().popcnt() == 0),
+        MoveOrder::CapturesFirst => moves.sort_by_cached_key(|&mov| {
+            let after = board.make_move_new(mov);
+            !accepted(&Constraint::Capture, board, mov, &after)
+        }),
+        MoveOrder::Random => moves.shuffle(&mut StdRng::seed_from_u64(seed ^ board.get_hash() ^ (depth as u64).wrapping_mul(0x9e3779b97f4a7c15))),
+        MoveOrder::ConstraintGuided => moves.sort_by_cached_key(|&mov| {
+            let after = board.make_move_new(mov);
+            if !steno_constraints.get(depth).is_none_or(|constraint| accepted(constraint, board, mov, &after)) {
+                return Reverse(0);
+            }
+            let replies = match steno_constraints.get(depth + 1) {
+                Some(next) => MoveGen::new_legal(&after).filter(|&reply| accepted(next, &after, reply, &after.make_move_new(reply))).count(),
+                None => 0,
+            };
+            Reverse(replies + 1)
+        }),
+    }
+}
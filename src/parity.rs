@@ -0,0 +1,101 @@
+use chess::{Board, Color, File, Piece, Rank};
+
+use crate::steno::{conflict, parts, Constraint};
+
+// Where `color`'s moves of each kind land: promotions on the last rank,
+// en passant captures on the sixth, castling on the first and pawns' double
+// steps on the fourth, counted from its own side.
+fn own_rank(color: Color, rank: usize) -> Rank {
+    match color {
+        Color::White => Rank::from_index(rank - 1),
+        Color::Black => Rank::from_index(8 - rank),
+    }
+}
+
+fn rank_name(rank: Rank) -> &'static str {
+    ["first", "second", "third", "fourth", "fifth", "sixth", "seventh", "eighth"][rank.to_index()]
+}
+
+// The destination rank or file a ply's constraint asks for, if any.
+fn dest_rank(parts: &[&Constraint]) -> Option<Rank> {
+    parts.iter().find_map(|part| match part {
+        Constraint::Rank(rank) => Some(*rank),
+        Constraint::Square(square) => Some(square.get_rank()),
+        _ => None,
+    })
+}
+
+fn dest_file(parts: &[&Constraint]) -> Option<File> {
+    parts.iter().find_map(|part| match part {
+        Constraint::File(file) => Some(*file),
+        Constraint::Square(square) => Some(square.get_file()),
+        _ => None,
+    })
+}
+
+// Reasons the steno can't have solutions that follow from whose move each
+// ply is, both alone and next to the ply before: what a side's promotions,
+// en passant captures and castling look like, and what a ply asks of the
+// opponent's move just before it. Each one is a proof.
+pub(crate) fn parity_conflicts(board: &Board, steno_constraints: &[Constraint]) -> Vec<String> {
+    let mut reasons = Vec::new();
+    let color_at = |ply: usize| if ply.is_multiple_of(2) { board.side_to_move() } else { !board.side_to_move() };
+    for (ply, constraint) in steno_constraints.iter().enumerate() {
+        let color = color_at(ply);
+        let asked = parts(constraint);
+        let requires = |test: fn(&Constraint) -> bool| asked.iter().any(|part| test(part));
+
+        // The other side's rank for each, which conflict() doesn't tell from
+        // the right one without knowing whose move it is.
+        if let Some(rank) = dest_rank(&asked) {
+            let kinds: [(bool, usize, &str); 3] = [
+                (requires(|part| matches!(part, Constraint::Promotion(_))), 8, "promotes"),
+                (requires(|part| matches!(part, Constraint::EnPassant)), 6, "takes en passant"),
+                (requires(|part| matches!(part, Constraint::Castle { color: None, .. })), 1, "castles"),
+            ];
+            for (wanted, own, kind) in kinds {
+                if wanted && rank == own_rank(!color, own) {
+                    reasons.push(format!("'{}' at ply {} is a {:?} move, and {:?} {} on the {} rank, not the {}", constraint, ply + 1, color, color, kind, rank_name(own_rank(color, own)), rank_name(rank)));
+                }
+            }
+        }
+
+        let Some(before) = ply.checked_sub(1).map(|before| parts(&steno_constraints[before])) else {
+            continue;
+        };
+        let opponent = color_at(ply - 1);
+
+        // En passant takes a pawn that just stepped two squares, to the
+        // fourth rank, on the file the capture lands on.
+        if requires(|part| matches!(part, Constraint::EnPassant)) {
+            let mut double_step = vec![Constraint::Mover(Piece::Pawn), Constraint::Rank(own_rank(opponent, 4))];
+            double_step.extend(dest_file(&asked).map(Constraint::File));
+            let refused = before.iter().find(|part| {
+                matches!(part, Constraint::Capture | Constraint::EnPassant | Constraint::Promotion(_)) || double_step.iter().any(|step| conflict(part, step))
+            });
+            if let Some(refused) = refused {
+                reasons.push(format!("'{}' at ply {} takes en passant, but '{}' at ply {} can't be the {:?} pawn's double step it needs", constraint, ply + 1, refused, ply, opponent));
+            }
+        }
+
+        // A recapture needs a capture just before, and getting out of check
+        // a check.
+        if requires(|part| matches!(part, Constraint::Recapture)) {
+            if let Some(refused) = before.iter().find(|part| conflict(part, &Constraint::Capture)) {
+                reasons.push(format!("'{}' at ply {} recaptures, but '{}' at ply {} can't be the capture it needs", constraint, ply + 1, refused, ply));
+            }
+        }
+        if requires(|part| matches!(part, Constraint::EscapeCheck)) {
+            if let Some(refused) = before.iter().find(|part| conflict(part, &Constraint::Check)) {
+                reasons.push(format!("'{}' at ply {} gets out of check, but '{}' at ply {} can't give the check", constraint, ply + 1, refused, ply));
+            }
+        }
+        // And after a check the move has to get out of it.
+        if before.iter().any(|part| matches!(part, Constraint::Check | Constraint::CheckNotMate)) {
+            if let Some(refused) = asked.iter().find(|part| conflict(part, &Constraint::EscapeCheck)) {
+                reasons.push(format!("'{}' at ply {} gives check, so '{}' at ply {} has to get out of it, which it can't", steno_constraints[ply - 1], ply, refused, ply + 1));
+            }
+        }
+    }
+    reasons
+}
@@ -0,0 +1,101 @@
+use chess::ChessMove;
+use shakmaty::{fen::Fen, san::San, uci::Uci, CastlingMode, Chess, Position};
+use std::str::FromStr;
+
+pub struct PgnGame {
+    pub fen: Option<String>,
+    pub san_moves: Vec<String>,
+}
+
+fn header_value(line: &str, name: &str) -> Option<String> {
+    let rest = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let value = rest.strip_prefix(name)?.trim();
+    Some(value.strip_prefix('"')?.strip_suffix('"')?.to_string())
+}
+
+fn san_token(token: &str) -> Option<String> {
+    if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") || token.starts_with('$') {
+        return None;
+    }
+    // "12.e4" and "12...Nf6" carry their move number glued on.
+    let san = token.rsplit('.').next().unwrap_or(token).trim_end_matches(['!', '?']);
+    if san.is_empty() {
+        return None;
+    }
+    Some(san.replace("0-0-0", "O-O-O").replace("0-0", "O-O"))
+}
+
+// Reads the first game of a PGN: its FEN header (if any) and the mainline
+// SAN moves, skipping comments, variations, NAGs, move numbers and results.
+pub fn parse_pgn(pgn: &str) -> Result<PgnGame, String> {
+    let mut fen = None;
+    let mut movetext = String::new();
+    let mut seen_moves = false;
+
+    for line in pgn.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            if seen_moves {
+                break;
+            }
+            if let Some(value) = header_value(line, "FEN") {
+                fen = Some(value);
+            }
+        } else if !line.is_empty() && !line.starts_with('%') {
+            seen_moves = true;
+            movetext.push_str(line);
+            movetext.push(' ');
+        }
+    }
+
+    let mut san_moves = Vec::new();
+    let mut comment_depth = 0;
+    let mut variation_depth = 0;
+    let mut token = String::new();
+    let mut flush = |token: &mut String| {
+        if let Some(san) = san_token(token) {
+            san_moves.push(san);
+        }
+        token.clear();
+    };
+
+    for ch in movetext.chars() {
+        match ch {
+            '{' => comment_depth += 1,
+            '}' => comment_depth -= 1,
+            '(' if comment_depth == 0 => variation_depth += 1,
+            ')' if comment_depth == 0 => variation_depth -= 1,
+            _ if comment_depth > 0 || variation_depth > 0 => {}
+            ch if ch.is_whitespace() => flush(&mut token),
+            ch => token.push(ch),
+        }
+    }
+    flush(&mut token);
+
+    if san_moves.is_empty() {
+        return Err("PGN contains no moves".to_string());
+    }
+    Ok(PgnGame { fen, san_moves })
+}
+
+// Replays SAN moves from the starting position and converts them to moves
+// the search understands.
+pub fn moves_from_san(fen_string: &Option<String>, san_moves: &[String]) -> Result<Vec<ChessMove>, String> {
+    let mut position = match fen_string {
+        Some(fen) => {
+            let fen_position: Fen = fen.parse().map_err(|err| format!("Invalid FEN: {}", err))?;
+            fen_position.into_position(CastlingMode::Standard).map_err(|err| format!("Invalid FEN: {}", err))?
+        }
+        None => Chess::default(),
+    };
+
+    let mut moves = Vec::new();
+    for (ply, san) in san_moves.iter().enumerate() {
+        let parsed: San = san.parse().map_err(|_| format!("Invalid SAN at ply {}: {}", ply + 1, san))?;
+        let mov = parsed.to_move(&position).map_err(|_| format!("Illegal move at ply {}: {}", ply + 1, san))?;
+        let uci = Uci::from_standard(&mov).to_string();
+        moves.push(ChessMove::from_str(&uci).map_err(|_| format!("Unsupported move at ply {}: {}", ply + 1, san))?);
+        position = position.play(&mov).map_err(|_| format!("Illegal move at ply {}: {}", ply + 1, san))?;
+    }
+    Ok(moves)
+}
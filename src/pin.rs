@@ -0,0 +1,131 @@
+use chess::{ChessMove, Piece, ALL_SQUARES};
+use shakmaty::fen::Fen;
+use shakmaty::san::{San, SanPlus, Suffix};
+use shakmaty::uci::Uci;
+use shakmaty::{CastlingMode, CastlingSide, Chess, Position, Role};
+
+use crate::steno::{CastleSide, Constraint};
+
+// A ply fixed to one move: SAN, read in the position it's played in.
+#[derive(Clone, Debug)]
+pub struct Pin {
+    // Counted from 0 at the start position.
+    pub ply: usize,
+    san: SanPlus,
+}
+
+impl Pin {
+    pub fn san(&self) -> String {
+        self.san.to_string()
+    }
+}
+
+// Pins written as PGN movetext, "1.e4 ... 5.Nxe5" or "3...Nc6 4.Bb5",
+// numbered as the game starting at `fen_string` numbers its moves. A move
+// without a number follows the one before; `...` alone skips ahead, so the
+// next move needs its own.
+pub fn parse_pins(text: &str, fen_string: &Option<String>) -> Result<Vec<Pin>, String> {
+    let fields: Vec<&str> = fen_string.as_deref().map_or(Vec::new(), |fen| fen.split_whitespace().collect());
+    let black_starts = fields.get(1) == Some(&"b");
+    let first_move: usize = fields.get(5).and_then(|number| number.parse().ok()).unwrap_or(1);
+
+    let mut pins = Vec::new();
+    let mut next_ply: Option<usize> = None;
+    for token in text.split_whitespace() {
+        if token == "..." {
+            next_ply = None;
+            continue;
+        }
+        let (number, black, san) = match token.split_once('.') {
+            Some((number, rest)) if !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit()) => {
+                let black = rest.starts_with("..");
+                (Some(number.parse::<usize>().map_err(|_| format!("Invalid move number in pin: {}", token))?), black, rest.trim_start_matches('.'))
+            }
+            _ => (None, false, token),
+        };
+        if let Some(number) = number {
+            let game_ply = 2 * number.checked_sub(first_move).ok_or_else(|| format!("Pin {} comes before the start position", token))? + black as usize;
+            next_ply = Some(game_ply.checked_sub(black_starts as usize).ok_or_else(|| format!("Pin {} comes before the start position", token))?);
+        }
+        if san.is_empty() {
+            continue;
+        }
+        let ply = next_ply.ok_or_else(|| format!("Pin {} needs a move number", token))?;
+        let san = san.parse().map_err(|_| format!("Invalid SAN in pin: {}", san))?;
+        pins.push(Pin { ply, san });
+        next_ply = Some(ply + 1);
+    }
+    Ok(pins)
+}
+
+fn piece(role: Role) -> Piece {
+    match role {
+        Role::Pawn => Piece::Pawn,
+        Role::Knight => Piece::Knight,
+        Role::Bishop => Piece::Bishop,
+        Role::Rook => Piece::Rook,
+        Role::Queen => Piece::Queen,
+        Role::King => Piece::King,
+    }
+}
+
+// What the pins say about their plies in the constraint language, to be
+// joined with the steno so the search prunes by them. Short of the exact
+// move, which matches_pins checks: the language can't say which of two
+// knights moved, or which file a pawn took from.
+pub fn pin_steno(pins: &[Pin], plies: usize) -> Result<Vec<Constraint>, String> {
+    let mut steno = vec![Constraint::Any; plies];
+    for pin in pins {
+        let Some(ply) = steno.get_mut(pin.ply) else {
+            return Err(format!("Pin {} is ply {}, past the steno's {} plies", pin.san(), pin.ply + 1, plies));
+        };
+        if *ply != Constraint::Any {
+            return Err(format!("Ply {} is pinned twice", pin.ply + 1));
+        }
+        let mut parts = match &pin.san.san {
+            San::Normal { role, capture, to, promotion, .. } => {
+                let mut parts = vec![Constraint::Mover(piece(*role)), Constraint::Square(ALL_SQUARES[*to as usize])];
+                if *capture {
+                    parts.push(Constraint::Capture);
+                }
+                parts.extend(promotion.map(|role| Constraint::Promotion(piece(role))));
+                parts
+            }
+            San::Castle(side) => {
+                let side = if *side == CastlingSide::KingSide { CastleSide::Kingside } else { CastleSide::Queenside };
+                vec![Constraint::Castle { side: Some(side), color: None }]
+            }
+            San::Put { .. } | San::Null => return Err(format!("Pin {} isn't a move of standard chess", pin.san())),
+        };
+        match pin.san.suffix {
+            Some(Suffix::Check) => parts.push(Constraint::Check),
+            Some(Suffix::Checkmate) => parts.push(Constraint::Checkmate),
+            None => {}
+        }
+        *ply = Constraint::All(parts);
+    }
+    Ok(steno)
+}
+
+// Whether each pinned ply of `path` is the pin's move, read in the position
+// it's played in. A pin that's ambiguous there matches no move.
+pub fn matches_pins(fen_string: &Option<String>, pins: &[Pin], path: &[ChessMove]) -> bool {
+    let mut position = match fen_string {
+        Some(fen) => match fen.parse::<Fen>().ok().and_then(|fen| fen.into_position::<Chess>(CastlingMode::Standard).ok()) {
+            Some(position) => position,
+            None => return false,
+        },
+        None => Chess::default(),
+    };
+    let last = pins.iter().map(|pin| pin.ply).max().unwrap_or(0);
+    for (ply, mov) in path.iter().enumerate().take(last + 1) {
+        let Some(played) = mov.to_string().parse::<Uci>().ok().and_then(|uci| uci.to_move(&position).ok()) else {
+            return false;
+        };
+        if pins.iter().filter(|pin| pin.ply == ply).any(|pin| pin.san.san.to_move(&position).ok() != Some(played.clone())) {
+            return false;
+        }
+        position.play_unchecked(&played);
+    }
+    true
+}
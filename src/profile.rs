@@ -0,0 +1,72 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::stats::SearchStats;
+
+const TASKS_PID: u32 = 1;
+const PLIES_PID: u32 = 2;
+const BRANCHES_PID: u32 = 3;
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
+
+fn process_name(pid: u32, name: &str) -> Value {
+    json!({ "name": "process_name", "ph": "M", "pid": pid, "tid": 0, "args": { "name": name } })
+}
+
+// Totals laid end to end, so each shows as a bar as long as its share, as a
+// flame graph one level deep would.
+fn totals(pid: u32, totals: Vec<(String, Duration, Value)>, events: &mut Vec<Value>) {
+    let mut start = Duration::ZERO;
+    for (name, duration, args) in totals {
+        events.push(json!({ "name": name, "ph": "X", "pid": pid, "tid": 0, "ts": micros(start), "dur": micros(duration), "args": args }));
+        start += duration;
+    }
+}
+
+// The search as Chrome trace events, which chrome://tracing, Perfetto and
+// speedscope load: every task on the worker that searched it, then the time
+// spent in the nodes at each ply and the time per first move, each summed
+// over the workers. Needs the stats of a search that recorded ply and task
+// times.
+pub fn chrome_trace(stats: &SearchStats, steno: &str) -> Value {
+    let mut events = vec![
+        process_name(TASKS_PID, &format!("Tasks of {}", steno)),
+        process_name(PLIES_PID, "Time per ply"),
+        process_name(BRANCHES_PID, "Time per first move"),
+    ];
+    for worker in 0..stats.worker_busy.len().max(1) {
+        events.push(json!({ "name": "thread_name", "ph": "M", "pid": TASKS_PID, "tid": worker, "args": { "name": format!("worker {}", worker) } }));
+    }
+    for task in &stats.task_times {
+        let name = if task.moves.is_empty() { "start".to_string() } else { task.moves.clone() };
+        events.push(json!({
+            "name": name,
+            "ph": "X",
+            "pid": TASKS_PID,
+            "tid": task.worker,
+            "ts": micros(task.start),
+            "dur": micros(task.duration),
+            "args": { "nodes": task.nodes, "solutions": task.solutions },
+        }));
+    }
+
+    let plies = stats.ply_times.iter().enumerate().map(|(ply, &time)| (format!("ply {}", ply), time, json!({}))).collect();
+    totals(PLIES_PID, plies, &mut events);
+
+    let mut branches: HashMap<&str, (Duration, u64)> = HashMap::new();
+    for task in &stats.task_times {
+        let first = task.moves.split(' ').next().filter(|first| !first.is_empty()).unwrap_or("start");
+        let branch = branches.entry(first).or_default();
+        branch.0 += task.duration;
+        branch.1 += task.nodes;
+    }
+    // The costliest first.
+    let mut branches: Vec<(String, Duration, Value)> = branches.into_iter().map(|(first, (time, nodes))| (first.to_string(), time, json!({ "nodes": nodes }))).collect();
+    branches.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals(BRANCHES_PID, branches, &mut events);
+
+    json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+}
@@ -0,0 +1,340 @@
+use chess::{Board, ChessMove, Color, File, Piece, Rank, Square};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "san")]
+use shakmaty::{Chess, Position, uci::Uci, san::San, fen::Fen, CastlingMode};
+#[cfg(feature = "san")]
+use std::fmt::Write;
+use std::str::FromStr;
+
+#[cfg(feature = "san")]
+use crate::san::{cached_san_moves, series_san_moves};
+
+// Every format but Uci needs the san feature.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[cfg_attr(feature = "san", default)]
+    Url,
+    San,
+    #[cfg_attr(not(feature = "san"), default)]
+    Uci,
+    Pgn,
+    Tree,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            #[cfg(feature = "san")]
+            "url" => Ok(OutputFormat::Url),
+            #[cfg(feature = "san")]
+            "san" => Ok(OutputFormat::San),
+            "uci" => Ok(OutputFormat::Uci),
+            #[cfg(feature = "san")]
+            "pgn" => Ok(OutputFormat::Pgn),
+            #[cfg(feature = "san")]
+            "tree" => Ok(OutputFormat::Tree),
+            #[cfg(not(feature = "san"))]
+            "url" | "san" | "pgn" | "tree" => Err(format!("Output format {} needs the san feature; this build writes uci only", s)),
+            _ => Err(format!("Unknown output format: {} (expected url, san, uci, pgn or tree)", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShowBoards {
+    #[default]
+    None,
+    Final,
+    All,
+}
+
+impl FromStr for ShowBoards {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ShowBoards::None),
+            "final" => Ok(ShowBoards::Final),
+            "all" => Ok(ShowBoards::All),
+            _ => Err(format!("Unknown board display: {} (expected none, final or all)", s)),
+        }
+    }
+}
+
+#[cfg(feature = "san")]
+fn start_position(fen_string: &Option<String>) -> Chess {
+    match fen_string {
+        Some(fen) => {
+            let fen_position: Fen = fen.parse().unwrap();
+            fen_position.into_position(CastlingMode::Standard).unwrap()
+        }
+        None => Chess::default(),
+    }
+}
+
+// Solutions mostly arrive in search order, so each is rendered from the
+// previous one on the same thread rather than replayed from the start.
+#[cfg(feature = "san")]
+pub fn san_moves(fen_string: &Option<String>, path: &[ChessMove]) -> Vec<String> {
+    cached_san_moves(fen_string, path)
+}
+
+// A series solution, whose moves are all the same side's, in SAN or UCI.
+#[cfg_attr(not(feature = "san"), allow(unused_variables))]
+pub fn render_series(board: Board, path: &[ChessMove], format: OutputFormat) -> String {
+    match format {
+        #[cfg(feature = "san")]
+        OutputFormat::Url | OutputFormat::San | OutputFormat::Pgn | OutputFormat::Tree => series_san_moves(board, path).join(" "),
+        _ => uci_moves(path),
+    }
+}
+
+fn uci_moves(path: &[ChessMove]) -> String {
+    path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+// A Lichess analysis board playing the solution. From a FEN the URL carries a
+// FEN header and numbered moves, which Lichess needs to know where they start.
+#[cfg(feature = "san")]
+fn lichess_url(fen_string: &Option<String>, path: &[ChessMove]) -> String {
+    let pgn = match fen_string {
+        Some(fen) => {
+            let movetext = pgn_movetext(fen_string, path);
+            format!("[FEN %22{}%22] {}", fen, movetext.trim_end_matches('*').trim_end()).trim_end().to_string()
+        }
+        None => san_moves(fen_string, path).join(" "),
+    };
+    format!("https://lichess.org/analysis/pgn/{}", pgn.replace(' ', "_"))
+}
+
+// Without the san feature, every format is written as UCI.
+#[cfg_attr(not(feature = "san"), allow(unused_variables))]
+pub fn render_solution(fen_string: &Option<String>, path: &[ChessMove], format: OutputFormat) -> String {
+    match format {
+        #[cfg(feature = "san")]
+        OutputFormat::Url => lichess_url(fen_string, path),
+        #[cfg(feature = "san")]
+        OutputFormat::San => san_moves(fen_string, path).join(" "),
+        OutputFormat::Uci => uci_moves(path),
+        #[cfg(feature = "san")]
+        OutputFormat::Pgn => render_pgn(fen_string, path, &[]),
+        #[cfg(feature = "san")]
+        OutputFormat::Tree => render_tree(fen_string, &[path.to_vec()], false),
+        #[cfg(not(feature = "san"))]
+        _ => uci_moves(path),
+    }
+}
+
+// A hash of the solution's UCI moves (64-bit FNV-1a), the same on every run
+// and machine, so solution sets can be diffed and joined.
+pub fn solution_id(path: &[ChessMove]) -> u64 {
+    uci_moves(path).bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn format_solution_id(id: u64) -> String {
+    format!("{:016x}", id)
+}
+
+// A rendered solution with its ID in front, or as a PGN header.
+pub fn tag_solution_id(rendered: &str, path: &[ChessMove], format: OutputFormat) -> String {
+    let id = format_solution_id(solution_id(path));
+    match format {
+        OutputFormat::Pgn => format!("[SolutionId \"{}\"]\n{}", id, rendered),
+        _ => format!("{} {}", id, rendered),
+    }
+}
+
+#[cfg(feature = "san")]
+#[derive(Default)]
+struct MoveTree {
+    children: Vec<(ChessMove, MoveTree)>,
+    // Set on the leaf a solution ends at.
+    id: Option<u64>,
+}
+
+#[cfg(feature = "san")]
+impl MoveTree {
+    fn insert(&mut self, path: &[ChessMove], id: u64) {
+        let Some((&mov, rest)) = path.split_first() else {
+            self.id = Some(id);
+            return;
+        };
+        let index = match self.children.iter().position(|(child, _)| *child == mov) {
+            Some(index) => index,
+            None => {
+                self.children.push((mov, MoveTree::default()));
+                self.children.len() - 1
+            }
+        };
+        self.children[index].1.insert(rest, id);
+    }
+
+    fn write(&self, position: &Chess, mut depth: usize, mut line: String, ids: bool, tree: &mut String) {
+        if let (true, Some(id)) = (ids, self.id) {
+            write!(line, "{{{}}}", format_solution_id(id)).unwrap();
+        }
+        if self.children.len() != 1 && !line.is_empty() {
+            writeln!(tree, "{}{}", "  ".repeat(depth), line.trim_end()).unwrap();
+            line.clear();
+            depth += 1;
+        }
+        for (mov, child) in &self.children {
+            let uci: Uci = mov.to_string().parse().unwrap();
+            let uci_move = uci.to_move(position).unwrap();
+            let san = San::from_move(position, &uci_move);
+            let mut line = line.clone();
+            match position.turn() {
+                shakmaty::Color::White => write!(line, "{}. {} ", position.fullmoves(), san).unwrap(),
+                shakmaty::Color::Black if line.is_empty() => write!(line, "{}... {} ", position.fullmoves(), san).unwrap(),
+                shakmaty::Color::Black => write!(line, "{} ", san).unwrap(),
+            }
+            child.write(&position.clone().play(&uci_move).unwrap(), depth, line, ids, tree);
+        }
+    }
+}
+
+// The solutions as a variation tree: moves shared by every solution below a
+// point are printed once, and each branch starts an indented line. With
+// `ids`, every line ending in a solution ends in its ID.
+#[cfg(feature = "san")]
+pub fn render_tree(fen_string: &Option<String>, solutions: &[Vec<ChessMove>], ids: bool) -> String {
+    let mut solutions = solutions.to_vec();
+    solutions.sort_by_key(|path| path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>());
+    let mut root = MoveTree::default();
+    for path in &solutions {
+        root.insert(path, solution_id(path));
+    }
+
+    let mut tree = String::new();
+    root.write(&start_position(fen_string), 0, String::new(), ids, &mut tree);
+    tree
+}
+
+// Numbered movetext, continuing from the FEN's move number and side to move.
+#[cfg(feature = "san")]
+pub fn pgn_movetext(fen_string: &Option<String>, path: &[ChessMove]) -> String {
+    let position = start_position(fen_string);
+    let mut white_to_move = position.turn() == shakmaty::Color::White;
+    let mut move_number = position.fullmoves().get();
+
+    let mut movetext = String::new();
+    for (ply, san) in san_moves(fen_string, path).iter().enumerate() {
+        if white_to_move {
+            write!(movetext, "{}. {} ", move_number, san).unwrap();
+        } else {
+            if ply == 0 {
+                write!(movetext, "{}... {} ", move_number, san).unwrap();
+            } else {
+                write!(movetext, "{} ", san).unwrap();
+            }
+            move_number += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+    movetext.push('*');
+    movetext
+}
+
+#[cfg(feature = "san")]
+pub fn render_pgn(fen_string: &Option<String>, path: &[ChessMove], headers: &[(&str, &str)]) -> String {
+    pgn_with_movetext(fen_string, &pgn_movetext(fen_string, path), headers)
+}
+
+// One game per solution, in UCI order, tagged for a Lichess broadcast round:
+// each is board N of round 1 between "Steno" and "Steno", with no clocks, so
+// a broadcast shows every solution as its own chapter.
+#[cfg(feature = "san")]
+pub fn render_broadcast(fen_string: &Option<String>, solutions: &[Vec<ChessMove>], event: &str, steno: &str, ids: bool) -> String {
+    let mut solutions = solutions.to_vec();
+    solutions.sort_by_key(|path| path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>());
+    solutions.iter().enumerate().map(|(index, path)| {
+        let (round, id) = (format!("1.{}", index + 1), format_solution_id(solution_id(path)));
+        let mut headers = vec![("Event", event), ("Round", &round), ("White", "Steno"), ("Black", "Steno"), ("Annotator", steno)];
+        if ids {
+            headers.push(("SolutionId", &id));
+        }
+        render_pgn(fen_string, path, &headers)
+    }).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(feature = "san")]
+pub fn pgn_with_movetext(fen_string: &Option<String>, movetext: &str, headers: &[(&str, &str)]) -> String {
+    let mut pgn = String::new();
+    for (name, value) in headers {
+        writeln!(pgn, "[{} \"{}\"]", name, value.replace('\\', "\\\\").replace('"', "\\\"")).unwrap();
+    }
+    writeln!(pgn, "[Result \"*\"]").unwrap();
+    if let Some(fen) = fen_string {
+        writeln!(pgn, "[SetUp \"1\"]").unwrap();
+        writeln!(pgn, "[FEN \"{}\"]", fen).unwrap();
+    }
+    writeln!(pgn).unwrap();
+    writeln!(pgn, "{}", movetext).unwrap();
+    pgn
+}
+
+fn piece_glyph(piece: Piece, color: Color) -> char {
+    match (color, piece) {
+        (Color::White, Piece::King) => '♔',
+        (Color::White, Piece::Queen) => '♕',
+        (Color::White, Piece::Rook) => '♖',
+        (Color::White, Piece::Bishop) => '♗',
+        (Color::White, Piece::Knight) => '♘',
+        (Color::White, Piece::Pawn) => '♙',
+        (Color::Black, Piece::King) => '♚',
+        (Color::Black, Piece::Queen) => '♛',
+        (Color::Black, Piece::Rook) => '♜',
+        (Color::Black, Piece::Bishop) => '♝',
+        (Color::Black, Piece::Knight) => '♞',
+        (Color::Black, Piece::Pawn) => '♟',
+    }
+}
+
+pub fn board_diagram(board: &Board) -> String {
+    let mut diagram = String::new();
+    for rank in (0..8).rev() {
+        diagram.push_str(&format!("{} ", rank + 1));
+        for file in 0..8 {
+            let square = Square::make_square(Rank::from_index(rank), File::from_index(file));
+            let glyph = match (board.piece_on(square), board.color_on(square)) {
+                (Some(piece), Some(color)) => piece_glyph(piece, color),
+                _ => '·',
+            };
+            diagram.push(glyph);
+            diagram.push(' ');
+        }
+        diagram.pop();
+        diagram.push('\n');
+    }
+    diagram.push_str("  a b c d e f g h\n");
+    diagram
+}
+
+// The solution line followed by diagrams of the requested positions.
+pub fn render_with_boards(board: Board, fen_string: &Option<String>, path: &[ChessMove], format: OutputFormat, show_boards: ShowBoards) -> String {
+    let mut rendered = render_solution(fen_string, path, format);
+    rendered.push('\n');
+    match show_boards {
+        ShowBoards::None => {}
+        ShowBoards::Final => {
+            let final_board = path.iter().fold(board, |board, &mov| board.make_move_new(mov));
+            rendered.push_str(&board_diagram(&final_board));
+        }
+        ShowBoards::All => {
+            #[cfg(feature = "san")]
+            let labels = san_moves(fen_string, path);
+            #[cfg(not(feature = "san"))]
+            let labels = path.iter().map(|mov| mov.to_string());
+            let mut board = board;
+            for (ply, (&mov, label)) in path.iter().zip(labels).enumerate() {
+                board = board.make_move_new(mov);
+                rendered.push_str(&format!("Ply {}: {}\n", ply + 1, label));
+                rendered.push_str(&board_diagram(&board));
+            }
+        }
+    }
+    rendered
+}
@@ -0,0 +1,161 @@
+use chess::{ChessMove, Color, Piece, Square, ALL_SQUARES};
+use shakmaty::fen::{Epd, Fen};
+use shakmaty::uci::Uci;
+use shakmaty::{CastlingMode, CastlingSide, Chess, EnPassantMode, Move, Position, Role};
+
+use crate::steno::{CastleSide, Constraint};
+#[cfg(feature = "syzygy")]
+use crate::syzygy::probe_position;
+
+// What a replayed move did, each fact worked out from shakmaty's positions
+// rather than by the chess crate the search runs on.
+struct Replayed<'a> {
+    played: &'a Move,
+    color: Color,
+    before: &'a Chess,
+    after: &'a Chess,
+    // The king's square for castling, as in the steno.
+    dest: Square,
+    captured_square: Option<Square>,
+    repetitions: usize,
+    own_last_square: Option<Square>,
+    capture_square: Option<Square>,
+    mover_origin: Option<Square>,
+}
+
+fn square(square: shakmaty::Square) -> Square {
+    ALL_SQUARES[square as usize]
+}
+
+fn role(piece: Piece) -> Role {
+    match piece {
+        Piece::Pawn => Role::Pawn,
+        Piece::Knight => Role::Knight,
+        Piece::Bishop => Role::Bishop,
+        Piece::Rook => Role::Rook,
+        Piece::Queen => Role::Queen,
+        Piece::King => Role::King,
+    }
+}
+
+fn shakmaty_color(color: Color) -> shakmaty::Color {
+    match color {
+        Color::White => shakmaty::Color::White,
+        Color::Black => shakmaty::Color::Black,
+    }
+}
+
+// Repetitions compare everything but the move counters.
+fn position_key(position: &Chess) -> String {
+    Epd::from_position(position.clone(), EnPassantMode::Legal).to_string()
+}
+
+fn satisfies(constraint: &Constraint, replayed: &Replayed) -> bool {
+    let mover = shakmaty_color(replayed.color);
+    let board = replayed.after.board();
+    match constraint {
+        Constraint::Any => true,
+        Constraint::File(file) => replayed.dest.get_file() == *file,
+        Constraint::Rank(rank) => replayed.dest.get_rank() == *rank,
+        Constraint::Square(square) => replayed.dest == *square,
+        Constraint::Eliminated(piece) => (board.by_role(role(*piece)) & board.by_color(!mover)).is_empty(),
+        Constraint::Mover(piece) => replayed.played.role() == role(*piece),
+        Constraint::Capture => replayed.played.is_capture(),
+        Constraint::EnPassant => replayed.played.is_en_passant(),
+        Constraint::Check => replayed.after.is_check(),
+        Constraint::CheckNotMate => replayed.after.is_check() && !replayed.after.is_checkmate(),
+        Constraint::Checkmate => replayed.after.is_checkmate(),
+        Constraint::Stalemate => replayed.after.is_stalemate(),
+        Constraint::EscapeCheck => replayed.before.is_check(),
+        Constraint::Repetition => replayed.repetitions >= 3,
+        Constraint::FiftyMoves => replayed.after.halfmoves() >= 100,
+        Constraint::SamePiece => replayed.own_last_square.is_some() && replayed.played.from().map(square) == replayed.own_last_square,
+        Constraint::Recapture => replayed.capture_square.is_some() && replayed.capture_square == replayed.captured_square,
+        Constraint::Origin(origin) => replayed.mover_origin == Some(*origin),
+        Constraint::KingZone { color, zone, inside } => board.king_of(shakmaty_color(*color)).is_some_and(|king| zone.contains(square(king)) == *inside),
+        Constraint::Castle { side, color } => {
+            let castled = replayed.played.castling_side().map(|castled| match castled {
+                CastlingSide::KingSide => CastleSide::Kingside,
+                CastlingSide::QueenSide => CastleSide::Queenside,
+            });
+            castled.is_some_and(|castled| side.is_none_or(|side| side == castled)) && color.is_none_or(|color| color == replayed.color)
+        }
+        Constraint::Promotion(piece) => replayed.played.promotion() == Some(role(*piece)),
+        Constraint::All(parts) => parts.iter().all(|part| satisfies(part, replayed)),
+        #[cfg(feature = "syzygy")]
+        Constraint::Tablebase(outcome) => probe_position(replayed.after) == Some(*outcome),
+        // Only the search's own evaluator runs scripts.
+        #[cfg(feature = "script")]
+        Constraint::Custom(..) => true,
+    }
+}
+
+// Replays a solution through shakmaty and checks every ply of the steno
+// against it again, so a disagreement between the two move generators, or a
+// constraint the search evaluates wrongly, shows up as an error naming the
+// ply. Not for series, whose passes shakmaty can't play.
+pub fn replay_check(fen_string: &Option<String>, steno_constraints: &[Constraint], path: &[ChessMove]) -> Result<(), String> {
+    if path.len() != steno_constraints.len() {
+        return Err(format!("{} moves for a steno of {} plies", path.len(), steno_constraints.len()));
+    }
+    let mut position = match fen_string {
+        Some(fen) => fen.parse::<Fen>().map_err(|err| format!("Invalid FEN: {}", err))?
+            .into_position::<Chess>(CastlingMode::Standard).map_err(|err| format!("Invalid FEN: {}", err))?,
+        None => Chess::default(),
+    };
+    let mut keys = vec![position_key(&position)];
+    // The start square of the piece on each square.
+    let mut origins = ALL_SQUARES.map(|square| position.board().occupied().contains(shakmaty::Square::new(square.to_index() as u32)).then_some(square));
+    // Each ply's side, destination and whether it captured.
+    let mut plies: Vec<(Color, Square, bool)> = Vec::with_capacity(path.len());
+    for (ply, (&mov, constraint)) in path.iter().zip(steno_constraints).enumerate() {
+        let uci: Uci = mov.to_string().parse().map_err(|_| format!("Ply {}: {} isn't UCI", ply + 1, mov))?;
+        let played = uci.to_move(&position).map_err(|_| format!("Ply {}: {} is illegal when replayed", ply + 1, mov))?;
+        let color = if position.turn() == shakmaty::Color::White { Color::White } else { Color::Black };
+        let source = square(played.from().expect("a standard chess move has a source square"));
+        let dest = square(played.castling_side().map_or(played.to(), |side| side.king_to(position.turn())));
+        let captured_square = match (played.is_en_passant(), played.is_capture()) {
+            (true, _) => Some(Square::make_square(source.get_rank(), dest.get_file())),
+            (false, true) => Some(dest),
+            (false, false) => None,
+        };
+
+        let mover_origin = origins[source.to_index()];
+        if let Some(captured) = captured_square {
+            origins[captured.to_index()] = None;
+        }
+        origins[dest.to_index()] = origins[source.to_index()].take();
+        // shakmaty's castling move goes to the rook's square.
+        if let Some(side) = played.castling_side() {
+            origins[square(side.rook_to(position.turn())).to_index()] = origins[square(played.to()).to_index()].take();
+        }
+
+        let mut after = position.clone();
+        after.play_unchecked(&played);
+        if played.is_zeroing() {
+            keys.clear();
+        }
+        let key = position_key(&after);
+        let repetitions = keys.iter().filter(|&other| *other == key).count() + 1;
+        keys.push(key);
+
+        let replayed = Replayed {
+            played: &played,
+            color,
+            before: &position,
+            after: &after,
+            dest,
+            captured_square,
+            repetitions,
+            own_last_square: plies.iter().rev().find(|(other, _, _)| *other == color).map(|&(_, dest, _)| dest),
+            capture_square: plies.last().filter(|(other, _, captured)| *other != color && *captured).map(|&(_, dest, _)| dest),
+            mover_origin,
+        };
+        if !satisfies(constraint, &replayed) {
+            return Err(format!("Ply {}: {} doesn't satisfy {} when replayed", ply + 1, mov, constraint));
+        }
+        plies.push((color, dest, captured_square.is_some()));
+        position = after;
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use chess::ChessMove;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use crate::stats::SearchStats;
+use crate::steno::Constraint;
+
+// For each ply, the moves its constraint was tested on and the share it
+// rejected, then the same summed over the plies of each constraint. From a
+// search run with `record_pruning`.
+pub fn constraint_report(steno_constraints: &[Constraint], stats: &SearchStats) -> String {
+    let percent = |rejected: u64, tested: u64| if tested > 0 { 100.0 * rejected as f64 / tested as f64 } else { 0.0 };
+    let mut report = String::new();
+    writeln!(report, "Ply  Constraint      Tested  Rejected").unwrap();
+    let mut by_constraint: Vec<(String, u64, u64)> = Vec::new();
+    for (ply, constraint) in steno_constraints.iter().enumerate() {
+        let tested = stats.ply_tested.get(ply + 1).copied().unwrap_or(0);
+        let rejected = stats.ply_pruned.get(ply + 1).copied().unwrap_or(0);
+        writeln!(report, "{:>3}  {:<10}  {:>10}  {:>6.1}%", ply + 1, constraint.to_string(), tested, percent(rejected, tested)).unwrap();
+        let name = constraint.to_string();
+        match by_constraint.iter_mut().find(|(other, _, _)| *other == name) {
+            Some((_, total_tested, total_rejected)) => {
+                *total_tested += tested;
+                *total_rejected += rejected;
+            }
+            None => by_constraint.push((name, tested, rejected)),
+        }
+    }
+    by_constraint.sort_by(|a, b| percent(b.2, b.1).total_cmp(&percent(a.2, a.1)).then_with(|| a.0.cmp(&b.0)));
+    let summary: Vec<String> = by_constraint.iter().map(|(name, tested, rejected)| format!("{} {:.1}% of {}", name, percent(*rejected, *tested), tested)).collect();
+    writeln!(report, "Rejected by constraint: {}", summary.join(", ")).unwrap();
+    report
+}
+
+// Fed every solution from the search callback: which moves were played at
+// each ply, across all solutions.
+pub struct BranchingReport {
+    moves: Mutex<Vec<HashSet<ChessMove>>>,
+}
+
+impl BranchingReport {
+    pub fn new(plies: usize) -> BranchingReport {
+        BranchingReport { moves: Mutex::new(vec![HashSet::new(); plies]) }
+    }
+
+    pub fn record(&self, path: &[ChessMove]) {
+        let mut moves = self.moves.lock().unwrap();
+        for (ply, &mov) in path.iter().enumerate() {
+            moves[ply].insert(mov);
+        }
+    }
+
+    // Distinct moves seen at each ply, the first ply at index 0.
+    pub fn distinct_moves(&self) -> Vec<usize> {
+        self.moves.lock().unwrap().iter().map(HashSet::len).collect()
+    }
+
+    // A table of the distinct moves and the moves pruned at each ply, then
+    // the constraints by how many moves they pruned in total. The pruned
+    // counts come from a search run with `record_pruning`.
+    pub fn render(&self, steno_constraints: &[Constraint], stats: &SearchStats) -> String {
+        let distinct = self.distinct_moves();
+        let pruned = |ply: usize| stats.ply_pruned.get(ply + 1).copied();
+
+        let mut report = String::new();
+        writeln!(report, "Ply  Constraint  Distinct moves  Pruned").unwrap();
+        for (ply, constraint) in steno_constraints.iter().enumerate() {
+            let pruned = pruned(ply).map_or("-".to_string(), |pruned| pruned.to_string());
+            writeln!(report, "{:>3}  {:<10}  {:>14}  {:>6}", ply + 1, constraint.to_string(), distinct[ply], pruned).unwrap();
+        }
+
+        let mut by_constraint: HashMap<String, u64> = HashMap::new();
+        for (ply, constraint) in steno_constraints.iter().enumerate() {
+            *by_constraint.entry(constraint.to_string()).or_default() += pruned(ply).unwrap_or(0);
+        }
+        let mut by_constraint: Vec<(String, u64)> = by_constraint.into_iter().filter(|&(_, pruned)| pruned > 0).collect();
+        by_constraint.sort_by_key(|(constraint, pruned)| (Reverse(*pruned), constraint.clone()));
+        if !by_constraint.is_empty() {
+            let ranking: Vec<String> = by_constraint.iter().map(|(constraint, pruned)| format!("{} {}", constraint, pruned)).collect();
+            writeln!(report, "Most pruning: {}", ranking.join(", ")).unwrap();
+        }
+        report
+    }
+}
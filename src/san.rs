@@ -0,0 +1,191 @@
+use chess::{BitBoard, Board, ChessMove, MoveGen, Piece, Square};
+use std::cell::RefCell;
+use std::str::FromStr;
+
+fn piece_letter(piece: Piece) -> &'static str {
+    match piece {
+        Piece::Pawn => "",
+        Piece::Knight => "N",
+        Piece::Bishop => "B",
+        Piece::Rook => "R",
+        Piece::Queen => "Q",
+        Piece::King => "K",
+    }
+}
+
+// SAN without a check or mate suffix, as shakmaty's San writes it, but
+// straight from our own board and move generator.
+pub(crate) fn move_san(board: &Board, mov: ChessMove) -> String {
+    let (from, to) = (mov.get_source(), mov.get_dest());
+    let piece = board.piece_on(from).unwrap();
+    if piece == Piece::King && from.get_file().to_index().abs_diff(to.get_file().to_index()) == 2 {
+        return if to.get_file().to_index() > from.get_file().to_index() { "O-O".to_string() } else { "O-O-O".to_string() };
+    }
+
+    let mut san = piece_letter(piece).to_string();
+    let file = |square: Square| (b'a' + square.get_file().to_index() as u8) as char;
+    let rank = |square: Square| (b'1' + square.get_rank().to_index() as u8) as char;
+    if piece == Piece::Pawn {
+        if from.get_file() != to.get_file() {
+            san.push(file(from));
+            san.push('x');
+        }
+    } else {
+        // Other pieces of the same kind that could go to the same square.
+        let mut moves = MoveGen::new_legal(board);
+        moves.set_iterator_mask(BitBoard::from_square(to));
+        let rivals: Vec<_> = moves.map(|other| other.get_source()).filter(|&source| source != from && board.piece_on(source) == Some(piece)).collect();
+        // A rival on another file is told apart by the file, one on the same
+        // file by the rank; with both kinds of rival, both are given.
+        if rivals.iter().any(|rival| rival.get_rank() == from.get_rank() || rival.get_file() != from.get_file()) {
+            san.push(file(from));
+        }
+        if rivals.iter().any(|rival| rival.get_rank() != from.get_rank() && rival.get_file() == from.get_file()) {
+            san.push(rank(from));
+        }
+        if board.piece_on(to).is_some() {
+            san.push('x');
+        }
+    }
+    san.push(file(to));
+    san.push(rank(to));
+    if let Some(promotion) = mov.get_promotion() {
+        san.push('=');
+        san.push_str(piece_letter(promotion));
+    }
+    san
+}
+
+// A series' moves, the other side passing after each but the last.
+pub(crate) fn series_san_moves(board: Board, path: &[ChessMove]) -> Vec<String> {
+    let mut board = board;
+    path.iter().map(|&mov| {
+        let san = move_san(&board, mov);
+        let after = board.make_move_new(mov);
+        board = after.null_move().unwrap_or(after);
+        san
+    }).collect()
+}
+
+// The game last rendered on this thread. SAN is only written once a solution
+// is printed, not as the search makes its moves, but the solutions a worker
+// finds one after another mostly share their first moves, so only the moves
+// after those are rendered again.
+struct SanLine {
+    fen_string: Option<String>,
+    moves: Vec<ChessMove>,
+    // The position before each move, and after the last.
+    boards: Vec<Board>,
+    sans: Vec<String>,
+}
+
+thread_local! {
+    static LINE: RefCell<Option<SanLine>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn cached_san_moves(fen_string: &Option<String>, path: &[ChessMove]) -> Vec<String> {
+    LINE.with_borrow_mut(|line| {
+        if line.as_ref().is_none_or(|line| line.fen_string != *fen_string) {
+            let board = match fen_string {
+                Some(fen) => Board::from_str(fen).unwrap(),
+                None => Board::default(),
+            };
+            *line = Some(SanLine { fen_string: fen_string.clone(), moves: Vec::new(), boards: vec![board], sans: Vec::new() });
+        }
+        let line = line.as_mut().unwrap();
+
+        let shared = line.moves.iter().zip(path).take_while(|(a, b)| a == b).count();
+        line.moves.truncate(shared);
+        line.boards.truncate(shared + 1);
+        line.sans.truncate(shared);
+        for &mov in &path[shared..] {
+            let board = *line.boards.last().unwrap();
+            line.sans.push(move_san(&board, mov));
+            line.boards.push(board.make_move_new(mov));
+            line.moves.push(mov);
+        }
+        line.sans.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::fen::Fen;
+    use shakmaty::san::San;
+    use shakmaty::uci::Uci;
+    use shakmaty::{CastlingMode, Chess};
+
+    // Every legal move from `fen`, written both ways.
+    fn compare(fen: &str) {
+        let board = Board::from_str(fen).unwrap();
+        let position: Chess = fen.parse::<Fen>().unwrap().into_position(CastlingMode::Standard).unwrap();
+        for mov in MoveGen::new_legal(&board) {
+            let uci: Uci = mov.to_string().parse().unwrap();
+            let expected = San::from_move(&position, &uci.to_move(&position).unwrap()).to_string();
+            assert_eq!(move_san(&board, mov), expected, "{} in {}", mov, fen);
+        }
+    }
+
+    fn san(fen: &str, uci: &str) -> String {
+        move_san(&Board::from_str(fen).unwrap(), ChessMove::from_str(uci).unwrap())
+    }
+
+    #[test]
+    fn disambiguation() {
+        // Knights on one file, and on one rank.
+        assert_eq!(san("4k3/8/8/1N6/8/1N6/8/4K3 w - - 0 1", "b5d4"), "N5d4");
+        assert_eq!(san("4k3/8/8/8/8/1N3N2/8/4K3 w - - 0 1", "b3d4"), "Nbd4");
+        // Rooks on one rank and one file.
+        assert_eq!(san("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1", "a1d1"), "Rad1");
+        assert_eq!(san("R7/4k3/8/8/8/8/8/R3K3 w - - 0 1", "a1a4"), "R1a4");
+        // Three queens: one shares the file, and one is told apart by the
+        // file, so both are given, as shakmaty does.
+        assert_eq!(san("4k3/Q5Q1/8/8/8/8/8/Q3K3 w - - 0 1", "a1d4"), "Qa1d4");
+        assert_eq!(san("4k3/Q5Q1/8/8/8/8/8/Q3K3 w - - 0 1", "g7d4"), "Qgd4");
+        // One shares the file and another the rank.
+        assert_eq!(san("6k1/8/8/8/Q7/8/7K/Q2Q4 w - - 0 1", "a1d4"), "Qa1d4");
+        // A pinned rival doesn't count.
+        assert_eq!(san("4k3/8/8/1N6/8/8/4N3/4K3 w - - 0 1", "b5c3"), "Nbc3");
+        assert_eq!(san("4k3/4r3/8/1N6/8/8/4N3/4K3 w - - 0 1", "b5c3"), "Nc3");
+    }
+
+    #[test]
+    fn captures_and_promotions() {
+        assert_eq!(san("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2", "e4d5"), "exd5");
+        assert_eq!(san("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q"), "a8=Q");
+        assert_eq!(san("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8n"), "axb8=N");
+        assert_eq!(san("r3k2r/8/8/8/8/8/8/4K3 b kq - 0 1", "e8c8"), "O-O-O");
+    }
+
+    // Lines rendered one after another, sharing moves or not, or from
+    // another start, come out as if each were replayed alone.
+    #[test]
+    fn cached_lines() {
+        let line = |ucis: &str| ucis.split(' ').map(|uci| ChessMove::from_str(uci).unwrap()).collect::<Vec<_>>();
+        let kiwipete = Some("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1".to_string());
+        for (fen_string, ucis, sans) in [
+            (None, "g1f3 g8f6 b1c3", "Nf3 Nf6 Nc3"),
+            (None, "g1f3 g8f6 f3g1", "Nf3 Nf6 Ng1"),
+            (None, "e2e4 d7d5 e4d5", "e4 d5 exd5"),
+            (kiwipete, "e1g1 h3g2", "O-O hxg2"),
+            (None, "e2e4", "e4"),
+        ] {
+            assert_eq!(cached_san_moves(&fen_string, &line(ucis)).join(" "), sans);
+        }
+    }
+
+    #[test]
+    fn same_as_shakmaty() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "6k1/8/8/8/Q7/8/7K/Q2Q4 w - - 0 1",
+            "4k3/Q5Q1/8/8/8/8/8/Q3K3 w - - 0 1",
+            "1n1nk3/P1P5/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/2N1N3/8/2N1N3/8/8/4K3 w - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 4",
+        ] {
+            compare(fen);
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use chess::{Color, Piece};
+use std::fmt;
+
+use crate::steno::MoveContext;
+
+// The expression language for user-defined constraints, e.g.
+// `captured == QUEEN && dest.file == 'g'`.
+pub const SCRIPT_LANGUAGE: &str = "\
+Predicates for --define (one expression per constraint character):
+  mover, captured, promotion    pieces: KING QUEEN ROOK BISHOP KNIGHT PAWN, or NONE
+  source.file, dest.file        'a' to 'h'
+  source.rank, dest.rank        1 to 8
+  color                         'white' or 'black', the side that moved
+  capture en_passant check checkmate stalemate
+                                true or false; check and checkmate are the opponent's
+  in_check                      true if the mover was in check before the move
+  checkers                      how many pieces give the opponent check
+  == != < <= > >= && || ! ( )
+
+Example: `steno_solver --define \"Z=captured == QUEEN && dest.file == 'g'\" ~~~~~Z`";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Type {
+    Bool,
+    Int,
+    Str,
+    Piece,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Type::Bool => "a boolean",
+            Type::Int => "a number",
+            Type::Str => "a string",
+            Type::Piece => "a piece",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Var {
+    Mover,
+    Captured,
+    Promotion,
+    Capture,
+    EnPassant,
+    Check,
+    Checkmate,
+    Stalemate,
+    InCheck,
+    Checkers,
+    Color,
+    SourceFile,
+    SourceRank,
+    DestFile,
+    DestRank,
+}
+
+impl Var {
+    fn value_type(self) -> Type {
+        match self {
+            Var::Mover | Var::Captured | Var::Promotion => Type::Piece,
+            Var::Capture | Var::EnPassant | Var::Check | Var::Checkmate | Var::Stalemate | Var::InCheck => Type::Bool,
+            Var::Checkers | Var::SourceRank | Var::DestRank => Type::Int,
+            Var::Color | Var::SourceFile | Var::DestFile => Type::Str,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Expr {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Piece(Option<Piece>),
+    Var(Var),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value<'a> {
+    Bool(bool),
+    Int(i64),
+    Str(&'a str),
+    Piece(Option<Piece>),
+}
+
+const FILES: [&str; 8] = ["a", "b", "c", "d", "e", "f", "g", "h"];
+
+impl Expr {
+    fn eval<'a>(&'a self, context: &MoveContext) -> Value<'a> {
+        match self {
+            Expr::Bool(value) => Value::Bool(*value),
+            Expr::Int(value) => Value::Int(*value),
+            Expr::Str(value) => Value::Str(value),
+            Expr::Piece(value) => Value::Piece(*value),
+            Expr::Var(var) => match var {
+                Var::Mover => Value::Piece(Some(context.mover)),
+                Var::Captured => Value::Piece(context.captured),
+                Var::Promotion => Value::Piece(context.mov.get_promotion()),
+                Var::Capture => Value::Bool(context.captured.is_some()),
+                Var::EnPassant => Value::Bool(context.en_passant),
+                Var::Check => Value::Bool(context.checkers.popcnt() > 0),
+                Var::Checkmate => Value::Bool(context.board.status() == chess::BoardStatus::Checkmate),
+                Var::Stalemate => Value::Bool(context.board.status() == chess::BoardStatus::Stalemate),
+                Var::InCheck => Value::Bool(context.in_check),
+                Var::Checkers => Value::Int(context.checkers.popcnt() as i64),
+                Var::Color => Value::Str(if context.color == Color::White { "white" } else { "black" }),
+                Var::SourceFile => Value::Str(FILES[context.mov.get_source().get_file().to_index()]),
+                Var::SourceRank => Value::Int(context.mov.get_source().get_rank().to_index() as i64 + 1),
+                Var::DestFile => Value::Str(FILES[context.mov.get_dest().get_file().to_index()]),
+                Var::DestRank => Value::Int(context.mov.get_dest().get_rank().to_index() as i64 + 1),
+            },
+            Expr::Not(inner) => Value::Bool(!inner.eval_bool(context)),
+            Expr::And(left, right) => Value::Bool(left.eval_bool(context) && right.eval_bool(context)),
+            Expr::Or(left, right) => Value::Bool(left.eval_bool(context) || right.eval_bool(context)),
+            Expr::Compare(op, left, right) => {
+                let (left, right) = (left.eval(context), right.eval(context));
+                let ordering = match (left, right) {
+                    (Value::Int(left), Value::Int(right)) => left.cmp(&right),
+                    _ => return Value::Bool((left == right) == (*op == Op::Eq)),
+                };
+                Value::Bool(match op {
+                    Op::Eq => ordering.is_eq(),
+                    Op::Ne => ordering.is_ne(),
+                    Op::Lt => ordering.is_lt(),
+                    Op::Le => ordering.is_le(),
+                    Op::Gt => ordering.is_gt(),
+                    Op::Ge => ordering.is_ge(),
+                })
+            }
+        }
+    }
+
+    // Only called on expressions the parser typed as booleans.
+    fn eval_bool(&self, context: &MoveContext) -> bool {
+        matches!(self.eval(context), Value::Bool(true))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(i64),
+    Str(String),
+    Op(&'static str),
+    Dot,
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        let rest: String = chars[i..].iter().take(2).collect();
+        if ch.is_whitespace() {
+            i += 1;
+        } else if ch.is_ascii_alphabetic() || ch == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if ch.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().collect();
+            tokens.push(Token::Int(digits.parse().map_err(|_| format!("Number too large: {}", digits))?));
+        } else if ch == '\'' || ch == '"' {
+            let end = chars[i + 1..].iter().position(|&c| c == ch).ok_or_else(|| format!("Unterminated string at column {}", i + 1))?;
+            tokens.push(Token::Str(chars[i + 1..i + 1 + end].iter().collect()));
+            i += end + 2;
+        } else if let Some(op) = ["==", "!=", "<=", ">=", "&&", "||"].into_iter().find(|&op| rest == op) {
+            tokens.push(Token::Op(op));
+            i += 2;
+        } else {
+            tokens.push(match ch {
+                '<' => Token::Op("<"),
+                '>' => Token::Op(">"),
+                '!' => Token::Op("!"),
+                '.' => Token::Dot,
+                '(' => Token::Open,
+                ')' => Token::Close,
+                _ => return Err(format!("Unexpected '{}' at column {}", ch, i + 1)),
+            });
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat_op(&mut self, op: &'static str) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn or(&mut self) -> Result<(Expr, Type), String> {
+        let mut left = self.and()?;
+        while self.eat_op("||") {
+            let right = self.and()?;
+            left = (Expr::Or(Box::new(expect_bool(left, "||")?), Box::new(expect_bool(right, "||")?)), Type::Bool);
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<(Expr, Type), String> {
+        let mut left = self.not()?;
+        while self.eat_op("&&") {
+            let right = self.not()?;
+            left = (Expr::And(Box::new(expect_bool(left, "&&")?), Box::new(expect_bool(right, "&&")?)), Type::Bool);
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<(Expr, Type), String> {
+        if self.eat_op("!") {
+            let inner = self.not()?;
+            return Ok((Expr::Not(Box::new(expect_bool(inner, "!")?)), Type::Bool));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<(Expr, Type), String> {
+        let (left, left_type) = self.atom()?;
+        let op = match self.peek() {
+            Some(Token::Op("==")) => Op::Eq,
+            Some(Token::Op("!=")) => Op::Ne,
+            Some(Token::Op("<")) => Op::Lt,
+            Some(Token::Op("<=")) => Op::Le,
+            Some(Token::Op(">")) => Op::Gt,
+            Some(Token::Op(">=")) => Op::Ge,
+            _ => return Ok((left, left_type)),
+        };
+        self.position += 1;
+        let (right, right_type) = self.atom()?;
+        if left_type != right_type {
+            return Err(format!("Can't compare {} with {}", left_type, right_type));
+        }
+        if !matches!(op, Op::Eq | Op::Ne) && left_type != Type::Int {
+            return Err(format!("Only numbers can be ordered, not {}", left_type));
+        }
+        Ok((Expr::Compare(op, Box::new(left), Box::new(right)), Type::Bool))
+    }
+
+    fn atom(&mut self) -> Result<(Expr, Type), String> {
+        match self.next() {
+            Some(Token::Open) => {
+                let inner = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err("Missing ')'".to_string()),
+                }
+            }
+            Some(Token::Int(value)) => Ok((Expr::Int(value), Type::Int)),
+            Some(Token::Str(value)) => Ok((Expr::Str(value), Type::Str)),
+            Some(Token::Ident(name)) => {
+                let name = if self.peek() == Some(&Token::Dot) {
+                    self.position += 1;
+                    match self.next() {
+                        Some(Token::Ident(field)) => format!("{}.{}", name, field),
+                        _ => return Err(format!("Expected a field name after '{}.'", name)),
+                    }
+                } else {
+                    name
+                };
+                name_value(&name)
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn expect_bool((expr, expr_type): (Expr, Type), op: &str) -> Result<Expr, String> {
+    if expr_type != Type::Bool {
+        return Err(format!("'{}' needs booleans, not {}", op, expr_type));
+    }
+    Ok(expr)
+}
+
+fn name_value(name: &str) -> Result<(Expr, Type), String> {
+    let piece = |piece| Ok((Expr::Piece(piece), Type::Piece));
+    let var = match name {
+        "true" => return Ok((Expr::Bool(true), Type::Bool)),
+        "false" => return Ok((Expr::Bool(false), Type::Bool)),
+        "KING" => return piece(Some(Piece::King)),
+        "QUEEN" => return piece(Some(Piece::Queen)),
+        "ROOK" => return piece(Some(Piece::Rook)),
+        "BISHOP" => return piece(Some(Piece::Bishop)),
+        "KNIGHT" => return piece(Some(Piece::Knight)),
+        "PAWN" => return piece(Some(Piece::Pawn)),
+        "NONE" => return piece(None),
+        "mover" => Var::Mover,
+        "captured" => Var::Captured,
+        "promotion" => Var::Promotion,
+        "capture" => Var::Capture,
+        "en_passant" => Var::EnPassant,
+        "check" => Var::Check,
+        "checkmate" => Var::Checkmate,
+        "stalemate" => Var::Stalemate,
+        "in_check" => Var::InCheck,
+        "checkers" => Var::Checkers,
+        "color" => Var::Color,
+        "source.file" => Var::SourceFile,
+        "source.rank" => Var::SourceRank,
+        "dest.file" => Var::DestFile,
+        "dest.rank" => Var::DestRank,
+        _ => return Err(format!("Unknown name '{}'", name)),
+    };
+    Ok((Expr::Var(var), var.value_type()))
+}
+
+// A user-defined per-ply predicate, type-checked when parsed so evaluating
+// it can't fail.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Script {
+    source: String,
+    expr: Expr,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script, String> {
+        let mut parser = Parser { tokens: tokenize(source)?, position: 0 };
+        let (expr, expr_type) = parser.or().map_err(|err| format!("Invalid predicate '{}': {}", source, err))?;
+        if expr_type != Type::Bool {
+            return Err(format!("Invalid predicate '{}': it is {}, not true or false", source, expr_type));
+        }
+        if let Some(token) = parser.peek() {
+            return Err(format!("Invalid predicate '{}': unexpected {:?}", source, token));
+        }
+        Ok(Script { source: source.to_string(), expr })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn matches(&self, context: &MoveContext) -> bool {
+        self.expr.eval_bool(context)
+    }
+}
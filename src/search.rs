@@ -0,0 +1,852 @@
+use chess::{Board, ChessMove, MoveGen};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering as CmpOrdering;
+use std::fmt;
+use std::mem;
+use std::ops::ControlFlow;
+use std::str::FromStr;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, info_span, warn};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use crate::goal::Goal;
+use crate::memory::MemoryBudget;
+use crate::opening::OpeningIndex;
+use crate::order::{order_moves, MoveOrder};
+use crate::render::{render_solution, render_with_boards, solution_id, OutputFormat, ShowBoards};
+use crate::stats::{peak_memory_bytes, NodeCounts, SearchStats, TaskTime};
+use crate::steno::{check_steno_constraints, line_state, requires_line, steno_string, suspicious_constraints, Constraint};
+use crate::target::TargetPosition;
+use crate::writer::SolutionWriter;
+
+// Games are dealt out to shards by their first SHARD_PLY moves.
+pub const SHARD_PLY: usize = 3;
+
+// One of `count` disjoint parts of the search, so separate machines can
+// each solve one. Which part a game falls in only depends on its first
+// moves, the same on every machine; `index` counts from 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    fn owns(&self, prefix: &[ChessMove]) -> bool {
+        solution_id(prefix) % self.count as u64 == self.index as u64
+    }
+}
+
+// Written i/n, counting from 1: 1/4 to 4/4.
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid shard: {} (expected i/n with 1 <= i <= n)", s);
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let (index, count): (usize, usize) = (index.parse().map_err(|_| invalid())?, count.parse().map_err(|_| invalid())?);
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Shard { index: index - 1, count })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Solution {
+    #[serde(with = "uci_moves")]
+    pub moves: Vec<ChessMove>,
+}
+
+// Moves as a list of UCI strings.
+mod uci_moves {
+    use chess::ChessMove;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer>(moves: &[ChessMove], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(moves.iter().map(|mov| mov.to_string()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ChessMove>, D::Error> {
+        let moves = Vec::<String>::deserialize(deserializer)?;
+        moves.iter().map(|mov| ChessMove::from_str(mov).map_err(|_| D::Error::custom(format!("Invalid UCI move: {}", mov)))).collect()
+    }
+}
+
+impl Solution {
+    pub fn id(&self) -> u64 {
+        solution_id(&self.moves)
+    }
+
+    pub fn render(&self, fen_string: &Option<String>, format: OutputFormat) -> String {
+        render_solution(fen_string, &self.moves, format)
+    }
+}
+
+// Called with each solution, one at a time, before it is printed or passed
+// to the search's callback. Breaking stops the search; solutions other
+// workers find after that are dropped.
+type HookFn = dyn Fn(&Solution) -> ControlFlow<()> + Send + Sync;
+
+#[derive(Clone)]
+pub struct SolutionHook(Arc<HookFn>);
+
+impl SolutionHook {
+    pub fn new(hook: impl Fn(&Solution) -> ControlFlow<()> + Send + Sync + 'static) -> SolutionHook {
+        SolutionHook(Arc::new(hook))
+    }
+}
+
+impl fmt::Debug for SolutionHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SolutionHook")
+    }
+}
+
+// Everything but the handles a caller shares with a running search, which
+// are left out of JSON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SolveOptions {
+    pub print_solutions: bool,
+    pub record_ply_times: bool,
+    // Time each work-queue task, for a profile of the search.
+    pub record_task_times: bool,
+    // Count the moves each ply's constraint is tested on and rejects.
+    pub record_pruning: bool,
+    pub show_boards: ShowBoards,
+    // Once set, every worker abandons its subtree at the next node it visits.
+    #[serde(skip)]
+    pub cancel: Option<Arc<AtomicBool>>,
+    // Only games ending in this position are solutions.
+    pub target: Option<TargetPosition>,
+    // How many work-queue tasks to split the search into per worker thread.
+    pub tasks_per_worker: usize,
+    pub move_order: MoveOrder,
+    // Seeds MoveOrder::Random, so a run can be repeated; a fresh seed each
+    // search without one.
+    pub seed: Option<u64>,
+    pub shard: Option<Shard>,
+    // Nodes visited so far, added to as each task finishes, for watching a
+    // search from another thread.
+    #[serde(skip)]
+    pub nodes_visited: Option<Arc<AtomicU64>>,
+    #[serde(skip)]
+    pub on_solution: Option<SolutionHook>,
+    // The start position's halfmove clock, the FEN's fifth field, which
+    // Board doesn't keep.
+    pub halfmove_clock: u32,
+    // Skip lines once a position occurs for the third time, unless that ply
+    // asks for the repetition.
+    pub prune_repetitions: bool,
+    // A series: the side to move plays every ply while the other passes.
+    pub series: bool,
+    // Search even when the steno provably has no solutions, rather than
+    // warning why and finding none.
+    pub force: bool,
+    // Only games whose last ply meets this goal are solutions.
+    pub goal: Option<Goal>,
+    // Looked up instead of searched for a two-stage search's first stage,
+    // when it starts from the initial position and the index covers it.
+    #[serde(skip)]
+    pub opening_index: Option<Arc<OpeningIndex>>,
+    // Caps the two-stage prefix table and the forced solver's proofs; past
+    // it the first searches in one stage and the second stops memoizing.
+    #[serde(skip)]
+    pub memory_budget: Option<Arc<MemoryBudget>>,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        SolveOptions {
+            print_solutions: true,
+            record_ply_times: false,
+            record_task_times: false,
+            record_pruning: false,
+            show_boards: ShowBoards::None,
+            cancel: None,
+            target: None,
+            tasks_per_worker: DEFAULT_TASKS_PER_WORKER,
+            move_order: MoveOrder::Default,
+            seed: None,
+            shard: None,
+            nodes_visited: None,
+            on_solution: None,
+            halfmove_clock: 0,
+            prune_repetitions: false,
+            series: false,
+            force: false,
+            goal: None,
+            opening_index: None,
+            memory_budget: None,
+        }
+    }
+}
+
+struct Search<'a> {
+    steno_constraints: &'a [Constraint],
+    on_solution: &'a (dyn Fn(&[ChessMove]) + Sync),
+    cancel: Option<&'a AtomicBool>,
+    target: Option<&'a TargetPosition>,
+    // Plies still to be played after this search's constraints, when it only
+    // covers a prefix of the game.
+    plies_after: usize,
+    move_order: MoveOrder,
+    seed: u64,
+    shard: Option<Shard>,
+    nodes_visited: Option<&'a AtomicU64>,
+    ply_nanos: Option<Vec<AtomicU64>>,
+    ply_pruned: Option<Vec<AtomicU64>>,
+    ply_tested: Option<Vec<AtomicU64>>,
+    record_task_times: bool,
+    // The root and its halfmove clock, kept when the line's history matters.
+    history: Option<(Board, u32)>,
+    prune_repetitions: bool,
+    series: bool,
+    goal: Option<Goal>,
+}
+
+// Checks the node `last_move` led to, reporting it when it completes a
+// solution. When the search has to go deeper, fills in the legal moves and
+// returns the position they're played from: the node itself, or in a series
+// the node after the other side's pass.
+fn visit(search: &Search, board: &Board, depth: usize, path: &[ChessMove], last: Option<(&Board, ChessMove)>, counts: &mut NodeCounts, moves: &mut Vec<ChessMove>) -> Option<Board> {
+    if search.cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+        return None;
+    }
+    // Other shards' games aren't visited at all.
+    if search.shard.is_some_and(|shard| depth == SHARD_PLY.min(search.steno_constraints.len()) && !shard.owns(path)) {
+        return None;
+    }
+
+    let started = search.ply_nanos.as_ref().map(|_| Instant::now());
+    let record_time = |started: Option<Instant>| {
+        if let (Some(ply_nanos), Some(started)) = (&search.ply_nanos, started) {
+            ply_nanos[depth].fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+    };
+
+    counts.visited += 1;
+    if let (Some(ply_tested), true) = (&search.ply_tested, depth > 0) {
+        ply_tested[depth].fetch_add(1, Ordering::Relaxed);
+    }
+
+    let line = search.history.filter(|_| depth > 0).map(|(root, halfmove_clock)| line_state(&root, halfmove_clock, path));
+    if !check_steno_constraints(board, last, depth, search.steno_constraints, line) {
+        debug!(ply = depth, mov = %last.unwrap().1, constraint = %search.steno_constraints[depth - 1], "pruned");
+        counts.pruned += 1;
+        if let Some(ply_pruned) = &search.ply_pruned {
+            ply_pruned[depth].fetch_add(1, Ordering::Relaxed);
+        }
+        record_time(started);
+        return None;
+    }
+
+    let repeated = line.is_some_and(|line| line.repetitions >= 3);
+    if search.prune_repetitions && repeated && !search.steno_constraints[depth - 1].requires(&|part| *part == Constraint::Repetition) {
+        counts.pruned += 1;
+        record_time(started);
+        return None;
+    }
+
+    if let Some(target) = search.target {
+        let plies_left = search.steno_constraints.len() - depth + search.plies_after;
+        let fits = if plies_left == 0 { target.matches(board) } else { target.reachable(board, plies_left) };
+        if !fits {
+            counts.pruned += 1;
+            record_time(started);
+            return None;
+        }
+    }
+
+    if depth == search.steno_constraints.len() {
+        if search.goal.is_some_and(|goal| !goal.reached(board)) {
+            counts.pruned += 1;
+            record_time(started);
+            return None;
+        }
+        (search.on_solution)(path);
+        counts.solutions += 1;
+        record_time(started);
+        return None;
+    }
+
+    // The side that passes can't while in check, so in a series only the
+    // last move may give one.
+    let board = match (search.series, depth) {
+        (false, _) | (true, 0) => *board,
+        (true, _) => match board.null_move() {
+            Some(passed) => passed,
+            None => {
+                counts.pruned += 1;
+                record_time(started);
+                return None;
+            }
+        },
+    };
+    moves.clear();
+    moves.extend(MoveGen::new_legal(&board));
+    order_moves(search.move_order, search.seed, &board, search.steno_constraints, depth, moves);
+    record_time(started);
+    Some(board)
+}
+
+// What a worker reuses from node to node instead of allocating: the line
+// being searched, pushed to and popped as it goes down and back up, and a
+// move list for each depth. Boards are small and copied on the stack.
+struct Scratch {
+    path: Vec<ChessMove>,
+    moves: Vec<Vec<ChessMove>>,
+}
+
+impl Scratch {
+    fn new(search: &Search, path: &[ChessMove]) -> Scratch {
+        let mut line = Vec::with_capacity(search.steno_constraints.len());
+        line.extend_from_slice(path);
+        Scratch { path: line, moves: vec![Vec::new(); search.steno_constraints.len() + 1] }
+    }
+}
+
+fn enumerate_positions(search: &Search, board: Board, depth: usize, last: Option<(&Board, ChessMove)>, scratch: &mut Scratch) -> NodeCounts {
+    let mut counts = NodeCounts::default();
+    // Taken out while the children use the deeper ones, and put back after.
+    let mut moves = std::mem::take(&mut scratch.moves[depth]);
+    if let Some(board) = visit(search, &board, depth, &scratch.path, last, &mut counts, &mut moves) {
+        counts = counts + search_children(search, board, depth, &moves, scratch);
+    }
+    scratch.moves[depth] = moves;
+    counts
+}
+
+fn search_children(search: &Search, board: Board, depth: usize, moves: &[ChessMove], scratch: &mut Scratch) -> NodeCounts {
+    let mut counts = NodeCounts::default();
+    for &mov in moves {
+        scratch.path.push(mov);
+        counts = counts + enumerate_positions(search, board.make_move_new(mov), depth + 1, Some((&board, mov)), scratch);
+        scratch.path.pop();
+    }
+    counts
+}
+
+pub const DEFAULT_TASKS_PER_WORKER: usize = 64;
+
+// A node whose children are still to be searched, queued for whichever
+// worker gets to it first.
+struct Task {
+    board: Board,
+    depth: usize,
+    path: Vec<ChessMove>,
+    moves: Vec<ChessMove>,
+    // Nodes below, guessed as if every node branched like this one.
+    estimate: u64,
+}
+
+impl Task {
+    fn new(search: &Search, board: Board, depth: usize, path: Vec<ChessMove>, moves: Vec<ChessMove>) -> Task {
+        let remaining = search.steno_constraints.len() - depth;
+        let estimate = (moves.len() as f64).powi(remaining as i32) as u64;
+        Task { board, depth, path, moves, estimate }
+    }
+}
+
+impl PartialEq for Task {
+    fn eq(&self, other: &Task) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for Task {}
+
+impl PartialOrd for Task {
+    fn partial_cmp(&self, other: &Task) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Task {
+    fn cmp(&self, other: &Task) -> CmpOrdering {
+        self.estimate.cmp(&other.estimate)
+    }
+}
+
+// Splits the largest task until there are `target` of them, or the largest
+// one's children are leaves and splitting it further wouldn't pay off.
+fn split_tasks(search: &Search, board: Board, target: usize, counts: &mut NodeCounts) -> Vec<Task> {
+    let mut tasks = BinaryHeap::new();
+    let mut moves = Vec::new();
+    if let Some(board) = visit(search, &board, 0, &[], None, counts, &mut moves) {
+        tasks.push(Task::new(search, board, 0, Vec::new(), moves));
+    }
+
+    while tasks.len() < target && tasks.peek().is_some_and(|task| task.depth + 1 < search.steno_constraints.len()) {
+        let task = tasks.pop().unwrap();
+        for &mov in &task.moves {
+            let child = task.board.make_move_new(mov);
+            let mut path = task.path.clone();
+            path.push(mov);
+            let mut moves = Vec::new();
+            if let Some(child) = visit(search, &child, task.depth + 1, &path, Some((&task.board, mov)), counts, &mut moves) {
+                tasks.push(Task::new(search, child, task.depth + 1, path, moves));
+            }
+        }
+    }
+    tasks.into_sorted_vec()
+}
+
+fn report_nodes(search: &Search, nodes: u64) {
+    if let Some(nodes_visited) = search.nodes_visited {
+        nodes_visited.fetch_add(nodes, Ordering::Relaxed);
+    }
+}
+
+fn run_task(search: &Search, task: &Task) -> NodeCounts {
+    let path = task.path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>().join(" ");
+    // One span per task, so -v shows where the time goes.
+    info_span!("task", path = %path).in_scope(|| {
+        let counts = search_children(search, task.board, task.depth, &task.moves, &mut Scratch::new(search, &task.path));
+        info!(solutions = counts.solutions, nodes = counts.visited, "task searched");
+        report_nodes(search, counts.visited);
+        counts
+    })
+}
+
+struct Queued {
+    counts: NodeCounts,
+    tasks: usize,
+    max_task_depth: usize,
+    // Per worker: nodes searched, and time until it ran out of tasks.
+    workers: Vec<(u64, Duration)>,
+    task_times: Vec<TaskTime>,
+}
+
+#[cfg(feature = "parallel")]
+fn num_workers() -> usize {
+    rayon::current_num_threads()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn num_workers() -> usize {
+    1
+}
+
+#[cfg(feature = "parallel")]
+fn worker_index() -> usize {
+    rayon::current_thread_index().unwrap_or(0)
+}
+
+#[cfg(not(feature = "parallel"))]
+fn worker_index() -> usize {
+    0
+}
+
+#[cfg(feature = "parallel")]
+fn run_workers(work: impl Fn() -> (u64, Duration) + Sync) -> Vec<(u64, Duration)> {
+    rayon::broadcast(|_| work())
+}
+
+#[cfg(not(feature = "parallel"))]
+fn run_workers(work: impl Fn() -> (u64, Duration) + Sync) -> Vec<(u64, Duration)> {
+    vec![work()]
+}
+
+// Workers pull the tasks largest first, so the small ones fill in the gaps at the end.
+fn search_queued(search: &Search, board: Board, tasks_per_worker: usize) -> Queued {
+    let mut counts = NodeCounts::default();
+    let tasks = split_tasks(search, board, num_workers() * tasks_per_worker.max(1), &mut counts);
+    report_nodes(search, counts.visited);
+    let max_task_depth = tasks.iter().map(|task| task.depth).max().unwrap_or(0);
+
+    let next = AtomicUsize::new(0);
+    let found = Mutex::new(NodeCounts::default());
+    let (epoch, task_times) = (Instant::now(), Mutex::new(Vec::new()));
+    let workers = run_workers(|| {
+        let started = Instant::now();
+        let mut worker_counts = NodeCounts::default();
+        while let Some(index) = tasks.len().checked_sub(next.fetch_add(1, Ordering::Relaxed) + 1) {
+            let task_started = Instant::now();
+            let task_counts = run_task(search, &tasks[index]);
+            if search.record_task_times {
+                task_times.lock().unwrap().push(TaskTime {
+                    moves: tasks[index].path.iter().map(|mov| mov.to_string()).collect::<Vec<_>>().join(" "),
+                    worker: worker_index(),
+                    start: task_started - epoch,
+                    duration: task_started.elapsed(),
+                    nodes: task_counts.visited,
+                    solutions: task_counts.solutions,
+                });
+            }
+            worker_counts = worker_counts + task_counts;
+        }
+        let mut found = found.lock().unwrap();
+        *found = *found + worker_counts;
+        (worker_counts.visited, started.elapsed())
+    });
+
+    Queued {
+        counts: found.into_inner().unwrap() + counts,
+        tasks: tasks.len(),
+        max_task_depth,
+        workers,
+        task_times: task_times.into_inner().unwrap(),
+    }
+}
+
+pub fn solve(board: Board, fen_string: Option<String>, steno_constraints: &[Constraint], options: &SolveOptions) -> SearchStats {
+    if options.print_solutions {
+        let writer = SolutionWriter::stdout();
+        let stats = solve_with_callback(board, steno_constraints, options, &|path| {
+            writer.write(&render_with_boards(board, &fen_string, path, OutputFormat::Url, options.show_boards));
+        });
+        writer.finish().unwrap();
+        stats
+    } else {
+        solve_with_callback(board, steno_constraints, options, &|_| {})
+    }
+}
+
+pub fn solve_with_callback(board: Board, steno_constraints: &[Constraint], options: &SolveOptions, on_solution: &(dyn Fn(&[ChessMove]) + Sync)) -> SearchStats {
+    let _span = info_span!("solve", steno = %steno_string(steno_constraints)).entered();
+    if unsolvable(&board, steno_constraints, options) {
+        return SearchStats { peak_memory: peak_memory_bytes(), ..SearchStats::default() };
+    }
+    with_hook(options, on_solution, |options, on_solution| run_search(board, steno_constraints, 0, options, on_solution))
+}
+
+// Searches the plies of `steno_constraints` after `prefix`, a line played
+// from `board` that satisfies the ones before, just as the whole search
+// would below it: the prefix's moves count toward the line's history and the
+// ply numbers, and solutions are reported whole. No proof is tried, since
+// the steno's were before the prefix was handed out.
+pub fn solve_after_prefix(board: Board, prefix: &[ChessMove], steno_constraints: &[Constraint], options: &SolveOptions, on_solution: &(dyn Fn(&[ChessMove]) + Sync)) -> SearchStats {
+    let _span = info_span!("solve", steno = %steno_string(steno_constraints), prefix = prefix.len()).entered();
+    with_hook(options, on_solution, |options, on_solution| {
+        let mut search = new_search(board, steno_constraints, 0, options, on_solution);
+        search.shard = None;
+        let started = Instant::now();
+        let (mut before, mut position) = (board, board);
+        for &mov in prefix {
+            before = position;
+            position = position.make_move_new(mov);
+        }
+        let last = prefix.last().map(|&mov| (&before, mov));
+        let counts = enumerate_positions(&search, position, prefix.len().min(steno_constraints.len()), last, &mut Scratch::new(&search, prefix));
+        report_nodes(&search, counts.visited);
+        SearchStats {
+            nodes_visited: counts.visited,
+            nodes_pruned: counts.pruned,
+            solutions: counts.solutions,
+            tasks: 1,
+            elapsed: started.elapsed(),
+            peak_memory: peak_memory_bytes(),
+            ..SearchStats::default()
+        }
+    })
+}
+
+// Warns of the reasons the steno can't have solutions, if any are visible
+// without searching. The proofs assume the sides take turns, so a series is
+// always searched, as is any steno under `force`.
+fn unsolvable(board: &Board, steno_constraints: &[Constraint], options: &SolveOptions) -> bool {
+    if options.series || options.force {
+        return false;
+    }
+    let warnings = suspicious_constraints(board, options.halfmove_clock, steno_constraints);
+    for warning in &warnings {
+        warn!("Provably 0 solutions: {}", warning);
+    }
+    !warnings.is_empty()
+}
+
+// Runs `search` with the options' hook put in front of `on_solution`.
+fn with_hook(options: &SolveOptions, on_solution: &(dyn Fn(&[ChessMove]) + Sync), search: impl FnOnce(&SolveOptions, &(dyn Fn(&[ChessMove]) + Sync)) -> SearchStats) -> SearchStats {
+    let Some(hook) = &options.on_solution else {
+        return search(options, on_solution);
+    };
+    let cancel = options.cancel.clone().unwrap_or_default();
+    let options = SolveOptions {
+        cancel: Some(cancel.clone()),
+        on_solution: None,
+        ..options.clone()
+    };
+    let stopped = Mutex::new(false);
+    search(&options, &|path| {
+        let mut stopped = stopped.lock().unwrap();
+        if *stopped {
+            return;
+        }
+        let flow = (hook.0)(&Solution { moves: path.to_vec() });
+        on_solution(path);
+        if flow.is_break() {
+            *stopped = true;
+            cancel.store(true, Ordering::Relaxed);
+        }
+    })
+}
+
+// A search running on a thread of its own.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct SolveHandle {
+    cancel: Arc<AtomicBool>,
+    thread: std::thread::JoinHandle<SearchStats>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SolveHandle {
+    // Every worker gives up at the next node it visits; join still returns
+    // the stats of what was searched.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    pub fn join(self) -> SearchStats {
+        self.thread.join().unwrap()
+    }
+}
+
+// solve_with_callback without waiting for it. The options' cancel flag is
+// used when there is one, so it can be set from elsewhere too.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn solve_in_background(board: Board, steno_constraints: Vec<Constraint>, options: SolveOptions, on_solution: impl Fn(&[ChessMove]) + Send + Sync + 'static) -> SolveHandle {
+    let cancel = options.cancel.clone().unwrap_or_default();
+    let options = SolveOptions {
+        cancel: Some(cancel.clone()),
+        ..options
+    };
+    let thread = std::thread::spawn(move || solve_with_callback(board, &steno_constraints, &options, &on_solution));
+    SolveHandle { cancel, thread }
+}
+
+// Whether the search has to know the line that led to each node, not just
+// the position.
+fn tracks_history(steno_constraints: &[Constraint], options: &SolveOptions) -> bool {
+    options.prune_repetitions || requires_line(steno_constraints)
+}
+
+fn new_search<'a>(board: Board, steno_constraints: &'a [Constraint], plies_after: usize, options: &'a SolveOptions, on_solution: &'a (dyn Fn(&[ChessMove]) + Sync)) -> Search<'a> {
+    Search {
+        steno_constraints,
+        on_solution,
+        cancel: options.cancel.as_deref(),
+        target: options.target.as_ref(),
+        plies_after,
+        move_order: options.move_order,
+        seed: options.seed.unwrap_or_else(rand::random),
+        shard: options.shard,
+        nodes_visited: options.nodes_visited.as_deref(),
+        ply_nanos: options.record_ply_times.then(|| (0..=steno_constraints.len()).map(|_| AtomicU64::new(0)).collect()),
+        ply_pruned: options.record_pruning.then(|| (0..=steno_constraints.len()).map(|_| AtomicU64::new(0)).collect()),
+        ply_tested: options.record_pruning.then(|| (0..=steno_constraints.len()).map(|_| AtomicU64::new(0)).collect()),
+        record_task_times: options.record_task_times,
+        history: tracks_history(steno_constraints, options).then_some((board, options.halfmove_clock)),
+        prune_repetitions: options.prune_repetitions,
+        series: options.series,
+        goal: options.goal,
+    }
+}
+
+fn run_search(board: Board, steno_constraints: &[Constraint], plies_after: usize, options: &SolveOptions, on_solution: &(dyn Fn(&[ChessMove]) + Sync)) -> SearchStats {
+    let search = new_search(board, steno_constraints, plies_after, options, on_solution);
+
+    let started = Instant::now();
+    let queued = search_queued(&search, board, options.tasks_per_worker);
+    let elapsed = started.elapsed();
+
+    SearchStats {
+        nodes_visited: queued.counts.visited,
+        nodes_pruned: queued.counts.pruned,
+        solutions: queued.counts.solutions,
+        tasks: queued.tasks,
+        max_task_depth: queued.max_task_depth,
+        worker_nodes: queued.workers.iter().map(|&(nodes, _)| nodes).collect(),
+        worker_busy: queued.workers.iter().map(|&(_, busy)| busy).collect(),
+        ply_times: search.ply_nanos.map(|ply_nanos| ply_nanos.into_iter().map(|nanos| Duration::from_nanos(nanos.into_inner())).collect()).unwrap_or_default(),
+        ply_pruned: search.ply_pruned.map(|ply_pruned| ply_pruned.into_iter().map(AtomicU64::into_inner).collect()).unwrap_or_default(),
+        ply_tested: search.ply_tested.map(|ply_tested| ply_tested.into_iter().map(AtomicU64::into_inner).collect()).unwrap_or_default(),
+        task_times: queued.task_times,
+        elapsed,
+        peak_memory: peak_memory_bytes(),
+    }
+}
+
+fn is_rare(constraint: &Constraint) -> bool {
+    constraint.requires(&|part| {
+        matches!(part, Constraint::Checkmate | Constraint::Stalemate | Constraint::EscapeCheck | Constraint::Castle { .. } | Constraint::Promotion(_) | Constraint::EnPassant)
+    })
+}
+
+// The first stage keeps every prefix in memory: ~200k games at ply 4 from the
+// initial position, but ~5M at ply 5.
+const MAX_DEFAULT_SPLIT: usize = 4;
+
+// As deep as the prefix table allows, since transpositions pile up with depth,
+// but never past the first rare, highly restrictive constraint, so the second
+// stage starts by filtering hard.
+pub fn default_split_ply(steno_constraints: &[Constraint]) -> usize {
+    let first_rare = steno_constraints.iter()
+        .position(is_rare)
+        .unwrap_or(steno_constraints.len());
+    first_rare.min(MAX_DEFAULT_SPLIT).min(steno_constraints.len().saturating_sub(1))
+}
+
+// Splits the steno at `split_ply`. The first stage enumerates every prefix and
+// groups them by the position they reach; the second searches the remaining
+// constraints once per distinct position and joins each suffix with every
+// prefix that transposed into it. Worth it when the prefix is short but
+// transposition-rich, since the expensive suffix search isn't repeated.
+// Per-ply times, per-ply pruning and per-worker load aren't recorded. A shard
+// is taken of the first stage only, so with a split before SHARD_PLY games
+// are dealt out differently than in a one-stage search. With an opening index
+// that covers the prefix, the first stage is looked up rather than searched.
+pub fn solve_two_stage(board: Board, steno_constraints: &[Constraint], split_ply: usize, options: &SolveOptions, on_solution: &(dyn Fn(&[ChessMove]) + Sync)) -> SearchStats {
+    let _span = info_span!("solve", steno = %steno_string(steno_constraints), split_ply).entered();
+    if unsolvable(&board, steno_constraints, options) {
+        return SearchStats { peak_memory: peak_memory_bytes(), ..SearchStats::default() };
+    }
+    // Prefixes joined to one suffix have lines of their own, and a series'
+    // prefixes end before the other side's pass.
+    if tracks_history(steno_constraints, options) || options.series {
+        warn!("Series, repetition, the fifty-move rule and constraints on earlier plies depend on the whole line, so searching in one stage");
+        return with_hook(options, on_solution, |options, on_solution| run_search(board, steno_constraints, 0, options, on_solution));
+    }
+    with_hook(options, on_solution, |options, on_solution| run_two_stage(board, steno_constraints, split_ply, options, on_solution))
+}
+
+fn run_two_stage(board: Board, steno_constraints: &[Constraint], split_ply: usize, options: &SolveOptions, on_solution: &(dyn Fn(&[ChessMove]) + Sync)) -> SearchStats {
+    let split_ply = split_ply.min(steno_constraints.len());
+    let (prefix_constraints, suffix_constraints) = steno_constraints.split_at(split_ply);
+    let options = SolveOptions {
+        record_ply_times: false,
+        record_pruning: false,
+        ..options.clone()
+    };
+
+    let started = Instant::now();
+    let prefixes: Mutex<HashMap<Board, Vec<Vec<ChessMove>>>> = Mutex::new(HashMap::new());
+    let (reserved, overflowed) = (AtomicU64::new(0), AtomicBool::new(false));
+    let add_prefix = |path: &[ChessMove]| {
+        if let Some(budget) = &options.memory_budget {
+            // A new position's entry counted with every prefix, to be safe.
+            let bytes = (mem::size_of::<Board>() + mem::size_of::<Vec<ChessMove>>() + mem::size_of_val(path)) as u64;
+            if overflowed.load(Ordering::Relaxed) || !budget.reserve(bytes) {
+                overflowed.store(true, Ordering::Relaxed);
+                return;
+            }
+            reserved.fetch_add(bytes, Ordering::Relaxed);
+        }
+        let position = path.iter().fold(board, |board, &mov| board.make_move_new(mov));
+        prefixes.lock().unwrap().entry(position).or_default().push(path.to_vec());
+    };
+    let release = || {
+        if let Some(budget) = &options.memory_budget {
+            budget.release(reserved.load(Ordering::Relaxed));
+        }
+    };
+    // The index starts from the initial position, and a shard's share is
+    // dealt out by the first stage's search, so only then can it stand in.
+    let index = options.opening_index.as_deref().filter(|index| board == Board::default() && options.shard.is_none() && index.covers(prefix_constraints));
+    let first = match index {
+        Some(index) => {
+            let (games, examined) = index.games(prefix_constraints);
+            games.iter().for_each(|path| add_prefix(path));
+            SearchStats { nodes_visited: examined, solutions: games.len() as u64, ..SearchStats::default() }
+        }
+        // The goal is the second stage's to check, at the end of the game.
+        None => run_search(board, prefix_constraints, suffix_constraints.len(), &SolveOptions { goal: None, ..options.clone() }, &add_prefix),
+    };
+    if overflowed.into_inner() {
+        drop(prefixes);
+        release();
+        warn!("--max-memory reached by the two-stage prefix table, searching in one stage");
+        let one_stage = run_search(board, steno_constraints, 0, &options, on_solution);
+        return SearchStats { nodes_visited: first.nodes_visited + one_stage.nodes_visited, elapsed: started.elapsed(), ..one_stage };
+    }
+    let prefixes: Vec<(Board, Vec<Vec<ChessMove>>)> = prefixes.into_inner().unwrap().into_iter().collect();
+    info!(prefixes = first.solutions, positions = prefixes.len(), "first stage done");
+
+    #[cfg(feature = "parallel")]
+    let groups = prefixes.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let groups = prefixes.iter();
+
+    // The groups are the second stage's tasks, each searched on one worker.
+    let second = groups.map(|(position, paths)| {
+        let on_suffix = |suffix: &[ChessMove]| {
+            for prefix in paths {
+                let mut path = prefix.clone();
+                path.extend_from_slice(suffix);
+                on_solution(&path);
+            }
+        };
+        let mut search = new_search(*position, suffix_constraints, 0, &options, &on_suffix);
+        search.shard = None;
+        let counts = enumerate_positions(&search, *position, 0, None, &mut Scratch::new(&search, &[]));
+        // Each group's root is a prefix leaf the first stage already counted,
+        // unless the search was cancelled before visiting it.
+        report_nodes(&search, counts.visited.saturating_sub(1));
+        NodeCounts {
+            visited: counts.visited.saturating_sub(1),
+            pruned: counts.pruned,
+            solutions: counts.solutions * paths.len() as u64,
+        }
+    }).sum::<NodeCounts>();
+    release();
+
+    SearchStats {
+        nodes_visited: first.nodes_visited + second.visited,
+        nodes_pruned: first.nodes_pruned + second.pruned,
+        solutions: second.solutions,
+        tasks: first.tasks + prefixes.len(),
+        max_task_depth: first.max_task_depth,
+        worker_nodes: Vec::new(),
+        worker_busy: Vec::new(),
+        ply_times: Vec::new(),
+        ply_pruned: Vec::new(),
+        ply_tested: Vec::new(),
+        task_times: Vec::new(),
+        elapsed: started.elapsed(),
+        peak_memory: peak_memory_bytes(),
+    }
+}
+
+pub fn perft(board: Board, depth: usize) -> SearchStats {
+    let steno_constraints = vec![Constraint::Any; depth];
+    let options = SolveOptions {
+        print_solutions: false,
+        ..SolveOptions::default()
+    };
+    solve(board, None, &steno_constraints, &options)
+}
+
+// Counts solutions from `board` with `halfmove_clock` on it, giving up as
+// soon as `limit` have been found.
+pub fn count_solutions(board: Board, halfmove_clock: u32, steno_constraints: &[Constraint], limit: Option<u64>) -> u64 {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let found = AtomicU64::new(0);
+    let options = SolveOptions {
+        print_solutions: false,
+        cancel: Some(cancel.clone()),
+        halfmove_clock,
+        ..SolveOptions::default()
+    };
+    solve_with_callback(board, steno_constraints, &options, &|_| {
+        let found = found.fetch_add(1, Ordering::Relaxed) + 1;
+        if limit.is_some_and(|limit| found >= limit) {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    });
+    let found = found.into_inner();
+    limit.map_or(found, |limit| found.min(limit))
+}
@@ -0,0 +1,589 @@
+use chess::Board;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::warn;
+
+use crate::metrics::{respond_metrics, Metrics};
+use crate::render::{render_solution, OutputFormat};
+use crate::search::{solve_with_callback, SolveOptions};
+use crate::steno::{check_steno_length, halfmove_clock, parse_steno_string, Constraint, DEFAULT_MAX_PLIES};
+
+pub struct ServerConfig {
+    pub addr: String,
+    // Number of jobs solved at once; further submissions wait in a queue.
+    pub max_concurrent: usize,
+    // Where jobs are kept, one JSON file each, so a restart doesn't lose
+    // them. Jobs that were queued or running are started over.
+    pub data_dir: Option<PathBuf>,
+    // Solutions a job keeps to serve and save; those found after are only
+    // counted.
+    pub max_solutions: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            addr: "127.0.0.1:8080".to_string(),
+            max_concurrent: 2,
+            data_dir: None,
+            max_solutions: 100_000,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+struct SolveRequest {
+    steno: String,
+    fen: Option<String>,
+    limit: Option<u64>,
+    format: Option<String>,
+    // Search settings such as the move order or a target position; those
+    // the server manages itself, printing and cancelling, are overridden.
+    options: Option<SolveOptions>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Done | JobStatus::Cancelled)
+    }
+}
+
+struct JobState {
+    status: JobStatus,
+    cancel_requested: bool,
+    // The first max_solutions of those found.
+    solutions: Vec<String>,
+    found: u64,
+    nodes_visited: u64,
+    started: Option<Instant>,
+    elapsed: Option<Duration>,
+}
+
+// A job as saved to the data directory.
+#[derive(Serialize, Deserialize)]
+struct JobRecord {
+    id: u64,
+    steno: String,
+    fen: Option<String>,
+    limit: Option<u64>,
+    format: Option<String>,
+    options: Option<SolveOptions>,
+    status: JobStatus,
+    solutions: Vec<String>,
+    // Missing from jobs saved before solutions were capped, which kept them all.
+    #[serde(default)]
+    solutions_found: u64,
+    nodes_visited: u64,
+    elapsed_ms: Option<u64>,
+}
+
+struct Job {
+    id: u64,
+    request: SolveRequest,
+    steno_constraints: Vec<Constraint>,
+    board: Board,
+    format: OutputFormat,
+    max_solutions: usize,
+    cancel: Arc<AtomicBool>,
+    state: Mutex<JobState>,
+    changed: Condvar,
+}
+
+impl Job {
+    fn to_json(&self) -> Value {
+        let state = self.state.lock().unwrap();
+        let elapsed = state.elapsed.or_else(|| state.started.map(|started| started.elapsed()));
+        json!({
+            "id": self.id,
+            "steno": self.request.steno,
+            "fen": self.request.fen,
+            "limit": self.request.limit,
+            "status": state.status.as_str(),
+            "solutions_found": state.found,
+            "solutions_kept": state.solutions.len(),
+            "nodes_visited": state.status.is_finished().then_some(state.nodes_visited),
+            "elapsed_ms": elapsed.map(|elapsed| elapsed.as_millis() as u64),
+        })
+    }
+
+    fn record(&self) -> JobRecord {
+        let state = self.state.lock().unwrap();
+        JobRecord {
+            id: self.id,
+            steno: self.request.steno.clone(),
+            fen: self.request.fen.clone(),
+            limit: self.request.limit,
+            format: self.request.format.clone(),
+            options: self.request.options.clone(),
+            status: state.status,
+            solutions: state.solutions.clone(),
+            solutions_found: state.found,
+            nodes_visited: state.nodes_visited,
+            elapsed_ms: state.elapsed.map(|elapsed| elapsed.as_millis() as u64),
+        }
+    }
+
+    fn run(&self, metrics: &Metrics) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.status == JobStatus::Cancelled {
+                return;
+            }
+            state.status = JobStatus::Running;
+            state.started = Some(Instant::now());
+        }
+        self.changed.notify_all();
+
+        let options = SolveOptions {
+            print_solutions: false,
+            cancel: Some(self.cancel.clone()),
+            nodes_visited: Some(metrics.nodes_visited.clone()),
+            halfmove_clock: halfmove_clock(&self.request.fen),
+            ..self.request.options.clone().unwrap_or_default()
+        };
+        let stats = solve_with_callback(self.board, &self.steno_constraints, &options, &|path| {
+            let solution = render_solution(&self.request.fen, path, self.format);
+            let mut state = self.state.lock().unwrap();
+            if self.request.limit.is_some_and(|limit| state.found >= limit) {
+                return;
+            }
+            state.found += 1;
+            if state.solutions.len() < self.max_solutions {
+                state.solutions.push(solution);
+            }
+            metrics.solutions.fetch_add(1, Ordering::Relaxed);
+            if self.request.limit.is_some_and(|limit| state.found >= limit) {
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+            drop(state);
+            self.changed.notify_all();
+        });
+
+        let mut state = self.state.lock().unwrap();
+        state.nodes_visited = stats.nodes_visited;
+        state.elapsed = Some(stats.elapsed);
+        state.status = if state.cancel_requested { JobStatus::Cancelled } else { JobStatus::Done };
+        drop(state);
+        self.changed.notify_all();
+    }
+
+    fn request_cancel(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.status.is_finished() {
+            return;
+        }
+        state.cancel_requested = true;
+        if state.status == JobStatus::Queued {
+            state.status = JobStatus::Cancelled;
+        }
+        self.cancel.store(true, Ordering::Relaxed);
+        drop(state);
+        self.changed.notify_all();
+    }
+}
+
+// The data directory's job files, when there is one.
+struct JobStore {
+    dir: Option<PathBuf>,
+    // Held while a job file is written, since a job can be saved from both
+    // its worker and a cancel.
+    writing: Mutex<()>,
+}
+
+impl JobStore {
+    fn load(&self) -> Result<Vec<JobRecord>, String> {
+        let Some(dir) = &self.dir else {
+            return Ok(Vec::new());
+        };
+        fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        let mut records = Vec::new();
+        for entry in fs::read_dir(dir).map_err(|err| format!("{}: {}", dir.display(), err))? {
+            let path = entry.map_err(|err| err.to_string())?.path();
+            // Left by a save cut short, whose job file still holds the last
+            // complete one.
+            if path.extension().is_some_and(|extension| extension == "partial") {
+                let _ = fs::remove_file(&path);
+            } else if path.extension().is_some_and(|extension| extension == "json") {
+                let contents = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+                records.push(serde_json::from_str::<JobRecord>(&contents).map_err(|err| format!("{}: {}", path.display(), err))?);
+            }
+        }
+        records.sort_by_key(|record| record.id);
+        Ok(records)
+    }
+
+    fn save(&self, job: &Job) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let _writing = self.writing.lock().unwrap();
+        let path = dir.join(format!("{}.json", job.id));
+        let partial = dir.join(format!("{}.json.partial", job.id));
+        let saved = serde_json::to_string(&job.record()).map_err(io::Error::other).and_then(|contents| replace_file(&path, &partial, &contents));
+        if let Err(err) = saved {
+            warn!(job = job.id, "could not save {}: {}", path.display(), err);
+        }
+    }
+}
+
+// Written next to the old file, synced and renamed over it, so a crash never
+// leaves half a job behind, nor a rename that lands before what it names.
+fn replace_file(path: &Path, partial: &Path, contents: &str) -> io::Result<()> {
+    let mut file = fs::File::create(partial)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(partial, path)?;
+    // The rename itself lasts once the directory is synced.
+    #[cfg(unix)]
+    fs::File::open(path.parent().unwrap_or(Path::new(".")))?.sync_all()?;
+    Ok(())
+}
+
+struct JobManager {
+    jobs: Mutex<HashMap<u64, Arc<Job>>>,
+    next_id: AtomicU64,
+    queue: Sender<Arc<Job>>,
+    metrics: Arc<Metrics>,
+    store: Arc<JobStore>,
+    max_solutions: usize,
+}
+
+impl JobManager {
+    fn new(config: &ServerConfig) -> Result<JobManager, String> {
+        let (queue, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let metrics = Arc::new(Metrics::new());
+        let store = Arc::new(JobStore { dir: config.data_dir.clone(), writing: Mutex::new(()) });
+        let records = store.load()?;
+        for _ in 0..config.max_concurrent.max(1) {
+            let (receiver, metrics, store) = (receiver.clone(), metrics.clone(), store.clone());
+            thread::spawn(move || run_jobs(&receiver, &metrics, &store));
+        }
+
+        let manager = JobManager {
+            jobs: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(records.last().map_or(1, |record| record.id + 1)),
+            queue,
+            metrics,
+            store,
+            max_solutions: config.max_solutions,
+        };
+        for record in records {
+            let request = SolveRequest { steno: record.steno, fen: record.fen, limit: record.limit, format: record.format, options: record.options };
+            let job = manager.new_job(record.id, request)?;
+            if record.status.is_finished() {
+                let mut state = job.state.lock().unwrap();
+                state.status = record.status;
+                state.found = record.solutions_found.max(record.solutions.len() as u64);
+                state.solutions = record.solutions;
+                state.nodes_visited = record.nodes_visited;
+                state.elapsed = record.elapsed_ms.map(Duration::from_millis);
+                drop(state);
+                manager.jobs.lock().unwrap().insert(job.id, Arc::new(job));
+            } else {
+                manager.enqueue(Arc::new(job))?;
+            }
+        }
+        Ok(manager)
+    }
+
+    // Everything known about a job, oldest first.
+    fn list(&self) -> Value {
+        let mut jobs: Vec<Arc<Job>> = self.jobs.lock().unwrap().values().cloned().collect();
+        jobs.sort_by_key(|job| job.id);
+        json!({ "jobs": jobs.iter().map(|job| job.to_json()).collect::<Vec<_>>() })
+    }
+
+    fn metrics(&self) -> String {
+        let jobs = self.jobs.lock().unwrap();
+        let count = |status: JobStatus| jobs.values().filter(|job| job.state.lock().unwrap().status == status).count();
+        let (running, queued) = (count(JobStatus::Running), count(JobStatus::Queued));
+        drop(jobs);
+        self.metrics.render(running, queued)
+    }
+
+    fn submit(&self, request: SolveRequest) -> Result<Arc<Job>, String> {
+        let job = Arc::new(self.new_job(self.next_id.fetch_add(1, Ordering::Relaxed), request)?);
+        self.enqueue(job.clone())?;
+        Ok(job)
+    }
+
+    fn enqueue(&self, job: Arc<Job>) -> Result<(), String> {
+        self.store.save(&job);
+        self.jobs.lock().unwrap().insert(job.id, job.clone());
+        self.queue.send(job).map_err(|err| err.to_string())
+    }
+
+    fn new_job(&self, id: u64, request: SolveRequest) -> Result<Job, String> {
+        let steno_constraints = parse_steno_string(&request.steno)?;
+        check_steno_length(&steno_constraints, DEFAULT_MAX_PLIES)?;
+        let board = match &request.fen {
+            Some(fen) => Board::from_str(fen).map_err(|err| format!("Invalid FEN: {}", err))?,
+            None => Board::default(),
+        };
+        let format = match &request.format {
+            Some(format) => format.parse()?,
+            None => OutputFormat::default(),
+        };
+
+        Ok(Job {
+            id,
+            request,
+            steno_constraints,
+            board,
+            format,
+            max_solutions: self.max_solutions,
+            cancel: Arc::new(AtomicBool::new(false)),
+            state: Mutex::new(JobState {
+                status: JobStatus::Queued,
+                cancel_requested: false,
+                solutions: Vec::new(),
+                found: 0,
+                nodes_visited: 0,
+                started: None,
+                elapsed: None,
+            }),
+            changed: Condvar::new(),
+        })
+    }
+
+    fn get(&self, id: &str) -> Result<Arc<Job>, (u16, String)> {
+        let not_found = || (404, format!("No such job: {}", id));
+        let id: u64 = id.parse().map_err(|_| not_found())?;
+        self.jobs.lock().unwrap().get(&id).cloned().ok_or_else(not_found)
+    }
+}
+
+fn run_jobs(receiver: &Mutex<Receiver<Arc<Job>>>, metrics: &Metrics, store: &JobStore) {
+    loop {
+        let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        job.run(metrics);
+        store.save(&job);
+    }
+}
+
+pub fn serve(config: &ServerConfig) -> io::Result<()> {
+    let server = Server::http(&config.addr).map_err(|err| io::Error::other(err.to_string()))?;
+    let manager = Arc::new(JobManager::new(config).map_err(io::Error::other)?);
+    eprintln!("Listening on http://{}", config.addr);
+
+    for request in server.incoming_requests() {
+        let manager = manager.clone();
+        thread::spawn(move || handle_request(&manager, request));
+    }
+
+    Ok(())
+}
+
+fn handle_request(manager: &JobManager, mut request: Request) {
+    let method = request.method().clone();
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let result = match (&method, segments.as_slice()) {
+        (Method::Post, ["solve"]) => {
+            let mut body = String::new();
+            match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => serde_json::from_str::<SolveRequest>(&body)
+                    .map_err(|err| (400, format!("Invalid request body: {}", err)))
+                    .and_then(|solve_request| manager.submit(solve_request).map_err(|err| (400, err)))
+                    .map(|job| (202, job.to_json())),
+                Err(err) => Err((400, err.to_string())),
+            }
+        }
+        (Method::Get, ["jobs"]) => Ok((200, manager.list())),
+        (Method::Get, ["jobs", id]) => manager.get(id).map(|job| (200, job.to_json())),
+        (Method::Get, ["jobs", id, "solutions"]) => manager.get(id).map(|job| {
+            let state = job.state.lock().unwrap();
+            let body = json!({ "id": job.id, "solutions": state.solutions, "solutions_found": state.found });
+            drop(state);
+            (200, body)
+        }),
+        (Method::Get, ["jobs", id, "stream"]) => match manager.get(id) {
+            Ok(job) => {
+                let mut writer = request.into_writer();
+                let _ = stream_solutions(&job, &mut *writer);
+                return;
+            }
+            Err(err) => Err(err),
+        },
+        (Method::Delete, ["jobs", id]) => manager.get(id).map(|job| {
+            job.request_cancel();
+            manager.store.save(&job);
+            (200, job.to_json())
+        }),
+        (Method::Get, ["metrics"]) => {
+            respond_metrics(request, manager.metrics());
+            return;
+        }
+        _ => Err((404, format!("No route for {} {}", method, path))),
+    };
+
+    respond_json(request, result);
+}
+
+pub(crate) fn respond_json(request: Request, result: Result<(u16, Value), (u16, String)>) {
+    let (status, body) = match result {
+        Ok(ok) => ok,
+        Err((status, message)) => (status, json!({ "error": message })),
+    };
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(body.to_string()).with_status_code(status).with_header(content_type);
+    let _ = request.respond(response);
+}
+
+// One server-sent event. Every line of `data` gets a field of its own, since
+// a line break ends the field and a blank line the event.
+fn sse_event(event: &str, data: &str) -> String {
+    let mut framed = format!("event: {}\n", event);
+    for line in data.split('\n') {
+        framed.push_str(&format!("data: {}\n", line.trim_end_matches('\r')));
+    }
+    framed.push('\n');
+    framed
+}
+
+// Server-sent events, written straight to the socket with our own chunked
+// framing so each solution is flushed as soon as it is found instead of
+// waiting on tiny_http's chunk buffer. Only the solutions the job keeps are
+// sent; the done event counts them all.
+fn stream_solutions(job: &Job, writer: &mut dyn Write) -> io::Result<()> {
+    writer.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nTransfer-Encoding: chunked\r\n\r\n")?;
+    writer.flush()?;
+
+    let mut next = 0;
+    loop {
+        let (batch, finished) = {
+            let mut state = job.state.lock().unwrap();
+            while state.solutions.len() == next && !state.status.is_finished() {
+                state = job.changed.wait(state).unwrap();
+            }
+            (state.solutions[next..].to_vec(), state.status.is_finished())
+        };
+        next += batch.len();
+
+        let mut events = String::new();
+        for solution in &batch {
+            events.push_str(&sse_event("solution", solution));
+        }
+        if finished {
+            events.push_str(&sse_event("done", &job.to_json().to_string()));
+        }
+        write!(writer, "{:x}\r\n{}\r\n", events.len(), events)?;
+        if finished {
+            writer.write_all(b"0\r\n\r\n")?;
+            writer.flush()?;
+            return Ok(());
+        }
+        writer.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Saved jobs come back after a restart, whatever a crash left half written.
+    #[test]
+    fn jobs_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("steno-solver-jobs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = ServerConfig { data_dir: Some(dir.clone()), ..ServerConfig::default() };
+        let manager = JobManager::new(&config).unwrap();
+        let request = SolveRequest { steno: "~~x".to_string(), fen: None, limit: None, format: None, options: None };
+        let job = manager.submit(request).unwrap();
+        let mut state = job.state.lock().unwrap();
+        while !state.status.is_finished() {
+            state = job.changed.wait(state).unwrap();
+        }
+        drop(state);
+        // Its worker saves it too, but only after marking it done.
+        manager.store.save(&job);
+        // A job whose first save was cut short.
+        fs::write(dir.join("99.json.partial"), "{\"id\": 99").unwrap();
+
+        let restarted = JobManager::new(&config).unwrap();
+        let reloaded = restarted.get(&job.id.to_string()).unwrap();
+        assert_eq!(reloaded.to_json()["status"], "done");
+        assert_eq!(reloaded.to_json()["solutions_found"], 34);
+        assert_eq!(reloaded.state.lock().unwrap().solutions, job.state.lock().unwrap().solutions);
+        assert!(!dir.join("99.json.partial").exists());
+        assert_eq!(restarted.next_id.load(Ordering::Relaxed), job.id + 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn multi_line_events() {
+        let event = sse_event("solution", "[Event \"?\"]\r\n\n1. e4 e5 *");
+        assert_eq!(event, "event: solution\ndata: [Event \"?\"]\ndata: \ndata: 1. e4 e5 *\n\n");
+        assert_eq!(sse_event("done", "{}"), "event: done\ndata: {}\n\n");
+    }
+
+    #[test]
+    fn solutions_past_the_cap_are_counted() {
+        let manager = JobManager::new(&ServerConfig { max_solutions: 3, ..ServerConfig::default() }).unwrap();
+        let request = SolveRequest { steno: "~~".to_string(), fen: None, limit: None, format: Some("pgn".to_string()), options: None };
+        let job = manager.submit(request).unwrap();
+        let mut state = job.state.lock().unwrap();
+        while !state.status.is_finished() {
+            state = job.changed.wait(state).unwrap();
+        }
+        assert_eq!((state.found, state.solutions.len()), (400, 3));
+        drop(state);
+        assert_eq!(job.to_json()["solutions_found"], 400);
+
+        let mut stream = Vec::new();
+        stream_solutions(&job, &mut stream).unwrap();
+        let stream = String::from_utf8(stream).unwrap();
+        assert_eq!(stream.matches("event: solution\n").count(), 3);
+        assert_eq!(stream.matches("event: done\n").count(), 1);
+    }
+
+    // The FEN's halfmove clock carries over, so the fifty-move rule can
+    // apply at once.
+    #[test]
+    fn jobs_start_from_the_fens_clock() {
+        let manager = JobManager::new(&ServerConfig::default()).unwrap();
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 98 1".to_string();
+        let request = SolveRequest { steno: "~/".to_string(), fen: Some(fen), limit: None, format: Some("uci".to_string()), options: None };
+        let job = manager.submit(request).unwrap();
+        let mut state = job.state.lock().unwrap();
+        while !state.status.is_finished() {
+            state = job.changed.wait(state).unwrap();
+        }
+        assert_eq!(state.found, 16);
+    }
+}
@@ -0,0 +1,88 @@
+use chess::{Board, ChessMove, Piece};
+#[cfg(feature = "engine")]
+use chess::{BoardStatus, Color};
+use std::str::FromStr;
+
+#[cfg(feature = "engine")]
+use crate::engine::Engine;
+use crate::render::{pgn_movetext, san_moves};
+
+// Orders to print a collected solution set in, nicest first by each measure.
+// Ties, and everything under Alphabetical, go by the moves' SAN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolutionSort {
+    // Fewest characters of movetext.
+    ShortestPgn,
+    MostCaptures,
+    // Best final position for the side that made the last move, by a UCI
+    // engine's evaluation; mates first. Needs the engine feature.
+    EngineEval,
+    Alphabetical,
+}
+
+impl FromStr for SolutionSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shortest-pgn" => Ok(SolutionSort::ShortestPgn),
+            "most-captures" => Ok(SolutionSort::MostCaptures),
+            #[cfg(feature = "engine")]
+            "engine-eval" => Ok(SolutionSort::EngineEval),
+            #[cfg(not(feature = "engine"))]
+            "engine-eval" => Err("Sort engine-eval needs the engine feature".to_string()),
+            "alphabetical" => Ok(SolutionSort::Alphabetical),
+            _ => Err(format!("Unknown sort: {} (expected shortest-pgn, most-captures, engine-eval or alphabetical)", s)),
+        }
+    }
+}
+
+fn captures(board: Board, path: &[ChessMove]) -> i64 {
+    let mut board = board;
+    let mut captures = 0;
+    for &mov in path {
+        // A pawn moving diagonally to an empty square takes en passant.
+        let en_passant = board.piece_on(mov.get_source()) == Some(Piece::Pawn) && mov.get_source().get_file() != mov.get_dest().get_file();
+        captures += (board.piece_on(mov.get_dest()).is_some() || en_passant) as i64;
+        board = board.make_move_new(mov);
+    }
+    captures
+}
+
+// The final position's worth to whoever made the last move, in centipawns.
+#[cfg(feature = "engine")]
+fn final_eval(engine: &mut Engine, board: Board, fen_string: &Option<String>, path: &[ChessMove], depth: u32) -> Result<i64, String> {
+    let position = path.iter().fold(board, |board, &mov| board.make_move_new(mov));
+    let white_moved_last = position.side_to_move() == Color::Black;
+    let white_score = match position.status() {
+        BoardStatus::Checkmate if white_moved_last => 100_000,
+        BoardStatus::Checkmate => -100_000,
+        BoardStatus::Stalemate => 0,
+        BoardStatus::Ongoing => engine.evaluate(fen_string, path, depth)?.centipawns(),
+    } as i64;
+    Ok(if white_moved_last { white_score } else { -white_score })
+}
+
+// Scores every solution under `sort`, lowest first, and orders them by it.
+// EngineEval needs the engine, and evaluates each final position once.
+pub fn sort_solutions(solutions: &mut Vec<Vec<ChessMove>>, board: Board, fen_string: &Option<String>, sort: SolutionSort, #[cfg(feature = "engine")] mut engine: Option<(&mut Engine, u32)>) -> Result<(), String> {
+    let mut keyed = Vec::with_capacity(solutions.len());
+    for path in solutions.drain(..) {
+        let score = match sort {
+            SolutionSort::ShortestPgn => pgn_movetext(fen_string, &path).len() as i64,
+            SolutionSort::MostCaptures => -captures(board, &path),
+            #[cfg(feature = "engine")]
+            SolutionSort::EngineEval => {
+                let (engine, depth) = engine.as_mut().ok_or("--sort engine-eval needs a UCI engine")?;
+                -final_eval(engine, board, fen_string, &path, *depth)?
+            }
+            #[cfg(not(feature = "engine"))]
+            SolutionSort::EngineEval => return Err("Sort engine-eval needs the engine feature".to_string()),
+            SolutionSort::Alphabetical => 0,
+        };
+        keyed.push(((score, san_moves(fen_string, &path).join(" ")), path));
+    }
+    keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+    solutions.extend(keyed.into_iter().map(|(_, path)| path));
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use chess::{File, Piece, Rank, Square};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::steno::{parse_steno_with, Constraint};
+
+// One ply of a spec, every field of which the move has to satisfy; a ply
+// with none of them set is any move. Pieces are K Q R B N P or their names,
+// and `flags` takes anything else as plies of the constraint language, such
+// as "%", "[!P]" or a character from --define.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlySpec {
+    pub piece: Option<String>,
+    pub dest: Option<String>,
+    pub dest_file: Option<String>,
+    pub dest_rank: Option<u8>,
+    pub capture: Option<bool>,
+    // Check, mate included.
+    pub check: Option<bool>,
+    pub mate: Option<bool>,
+    // The mover was in check before the move.
+    pub escapes_check: Option<bool>,
+    pub promotion: Option<String>,
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+// A steno as JSON, for programs that would rather write structure than the
+// string: {"fen": ..., "plies": [{"piece": "N"}, {}, {"capture": true, "mate": true}]}.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PuzzleSpec {
+    pub fen: Option<String>,
+    pub plies: Vec<PlySpec>,
+}
+
+pub fn load_spec(path: &Path) -> Result<PuzzleSpec, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    serde_json::from_str(&contents).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn spec_piece(piece: &str) -> Option<Piece> {
+    Some(match piece.to_ascii_lowercase().as_str() {
+        "k" | "king" => Piece::King,
+        "q" | "queen" => Piece::Queen,
+        "r" | "rook" => Piece::Rook,
+        "b" | "l" | "bishop" => Piece::Bishop,
+        "n" | "knight" => Piece::Knight,
+        "p" | "pawn" => Piece::Pawn,
+        _ => return None,
+    })
+}
+
+fn ply_constraint(ply: &PlySpec, definitions: &HashMap<char, Constraint>) -> Result<Constraint, String> {
+    let mut parts = Vec::new();
+    if let Some(piece) = &ply.piece {
+        parts.push(Constraint::Mover(spec_piece(piece).ok_or_else(|| format!("Unknown piece: {} (expected K, Q, R, B, N or P)", piece))?));
+    }
+    if let Some(dest) = &ply.dest {
+        parts.push(Constraint::Square(Square::from_str(dest).map_err(|_| format!("Invalid square: {}", dest))?));
+    }
+    if let Some(file) = &ply.dest_file {
+        match file.as_bytes() {
+            &[file @ b'a'..=b'h'] => parts.push(Constraint::File(File::from_index((file - b'a') as usize))),
+            _ => return Err(format!("Invalid file: {} (expected a to h)", file)),
+        }
+    }
+    if let Some(rank) = ply.dest_rank {
+        if !(1..=8).contains(&rank) {
+            return Err(format!("Invalid rank: {} (expected 1 to 8)", rank));
+        }
+        parts.push(Constraint::Rank(Rank::from_index(rank as usize - 1)));
+    }
+    // A steno can only ask for these, not for their absence.
+    for (name, set, constraint) in [("capture", ply.capture, Constraint::Capture), ("check", ply.check, Constraint::Check), ("mate", ply.mate, Constraint::Checkmate), ("escapes_check", ply.escapes_check, Constraint::EscapeCheck)] {
+        match set {
+            Some(true) => parts.push(constraint),
+            Some(false) => return Err(format!("{} can only be true; leave it out to allow either", name)),
+            None => {}
+        }
+    }
+    if let Some(promotion) = &ply.promotion {
+        match spec_piece(promotion) {
+            Some(piece @ (Piece::Queen | Piece::Rook | Piece::Bishop | Piece::Knight)) => parts.push(Constraint::Promotion(piece)),
+            _ => return Err(format!("Invalid promotion: {} (expected Q, R, B or N)", promotion)),
+        }
+    }
+    for flag in &ply.flags {
+        let mut constraints = parse_steno_with(flag, definitions)?;
+        if constraints.len() != 1 {
+            return Err(format!("Expected one ply of a steno as a flag, got {:?}", flag));
+        }
+        match constraints.pop().unwrap() {
+            Constraint::All(all) => parts.extend(all),
+            constraint => parts.push(constraint),
+        }
+    }
+    Ok(match parts.len() {
+        0 => Constraint::Any,
+        1 => parts.pop().unwrap(),
+        _ => Constraint::All(parts),
+    })
+}
+
+// The spec's plies as a steno, with `definitions` for the characters in
+// their flags.
+pub fn spec_constraints(spec: &PuzzleSpec, definitions: &HashMap<char, Constraint>) -> Result<Vec<Constraint>, String> {
+    spec.plies.iter().enumerate()
+        .map(|(index, ply)| ply_constraint(ply, definitions).map_err(|err| format!("Ply {} of the spec: {}", index + 1, err)))
+        .collect()
+}
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::iter::Sum;
+use std::ops::Add;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct NodeCounts {
+    pub visited: u64,
+    pub pruned: u64,
+    pub solutions: u64,
+}
+
+impl Add for NodeCounts {
+    type Output = NodeCounts;
+
+    fn add(self, other: NodeCounts) -> NodeCounts {
+        NodeCounts {
+            visited: self.visited + other.visited,
+            pruned: self.pruned + other.pruned,
+            solutions: self.solutions + other.solutions,
+        }
+    }
+}
+
+impl Sum for NodeCounts {
+    fn sum<I: Iterator<Item = NodeCounts>>(iter: I) -> NodeCounts {
+        iter.fold(NodeCounts::default(), |a, b| a + b)
+    }
+}
+
+// One work-queue task: the moves leading to its subtree, in UCI, the worker
+// that searched it, and when, counted from the start of the search.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TaskTime {
+    pub moves: String,
+    pub worker: usize,
+    pub start: Duration,
+    pub duration: Duration,
+    pub nodes: u64,
+    pub solutions: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SearchStats {
+    pub nodes_visited: u64,
+    pub nodes_pruned: u64,
+    pub solutions: u64,
+    // Subtrees the search was split into for the work queue, and the deepest
+    // ply one of them starts at.
+    pub tasks: usize,
+    pub max_task_depth: usize,
+    // Per worker thread: nodes searched, and time until it ran out of tasks.
+    pub worker_nodes: Vec<u64>,
+    pub worker_busy: Vec<Duration>,
+    // Time spent inside nodes at each depth, index 0 being the starting position.
+    // Only recorded when requested, since timing every node has a cost.
+    pub ply_times: Vec<Duration>,
+    // Moves rejected by the constraint at each depth, indexed like ply_times.
+    // Only recorded when requested.
+    pub ply_pruned: Vec<u64>,
+    // Moves the constraint at each depth was tested on, recorded with ply_pruned.
+    pub ply_tested: Vec<u64>,
+    // Every task in the order they finished. Only recorded when requested.
+    pub task_times: Vec<TaskTime>,
+    pub elapsed: Duration,
+    pub peak_memory: Option<u64>,
+}
+
+impl SearchStats {
+    pub fn solutions_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.solutions as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    // Mean worker busy time over the busiest worker's; 1.0 when no worker
+    // waited on the others at the end.
+    pub fn load_balance(&self) -> Option<f64> {
+        let busiest = self.worker_busy.iter().max()?.as_secs_f64();
+        let mean = self.worker_busy.iter().map(Duration::as_secs_f64).sum::<f64>() / self.worker_busy.len() as f64;
+        Some(if busiest > 0.0 { mean / busiest } else { 1.0 })
+    }
+
+    pub fn nodes_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.nodes_visited as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Nodes visited: {}", self.nodes_visited)?;
+        writeln!(f, "Nodes pruned: {}", self.nodes_pruned)?;
+        writeln!(f, "Tasks: {} (deepest starting at ply {})", self.tasks, self.max_task_depth)?;
+        if let Some(balance) = self.load_balance() {
+            writeln!(f, "Load balance: {:.1}%", balance * 100.0)?;
+            for (worker, (nodes, busy)) in self.worker_nodes.iter().zip(&self.worker_busy).enumerate() {
+                writeln!(f, "  {:>3}: {} nodes in {:?}", worker, nodes, busy)?;
+            }
+        }
+        if !self.ply_times.is_empty() {
+            writeln!(f, "Time per ply:")?;
+            for (ply, time) in self.ply_times.iter().enumerate() {
+                writeln!(f, "  {:>3}: {:?}", ply, time)?;
+            }
+        }
+        writeln!(f, "Elapsed: {:?}", self.elapsed)?;
+        writeln!(f, "Solutions/sec: {:.1}", self.solutions_per_sec())?;
+        match self.peak_memory {
+            Some(bytes) => write!(f, "Peak memory: {:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+            None => write!(f, "Peak memory: unavailable"),
+        }
+    }
+}
+
+pub(crate) fn peak_memory_bytes() -> Option<u64> {
+    status_bytes("VmHWM:")
+}
+
+// Resident memory right now.
+#[cfg(feature = "server")]
+pub(crate) fn memory_bytes() -> Option<u64> {
+    status_bytes("VmRSS:")
+}
+
+fn status_bytes(field: &str) -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with(field))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
@@ -0,0 +1,1064 @@
+use chess::{BitBoard, Board, BoardStatus, ChessMove, Color, File, Piece, Rank, Square, ALL_SQUARES};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "script")]
+use std::sync::Arc;
+
+use crate::parity::parity_conflicts;
+#[cfg(feature = "script")]
+use crate::script::Script;
+#[cfg(feature = "syzygy")]
+use crate::syzygy::{probe, TablebaseOutcome};
+
+// One character per ply; shown by `--help`.
+pub const CONSTRAINT_LANGUAGE: &str = "\
+Constraint language (one character per ply, or a bracketed square):
+  ~            any move
+  a-h          the move lands on that file
+  1-8          the move lands on that rank
+  [e4]         the move lands on that square
+  [!P]         the move leaves the opponent without pawns (or any other piece letter)
+  [N@b1]       the move is made by the piece that started on b1, here a knight
+  {ke}         after the move the black king is on the e-file (K for White's, or a rank or square)
+  {K!1}        after the move White's king is off the first rank
+  [Nx!P]       every one of the bracketed constraints, `&` between them optional
+  K Q R L N P  the move is made by a king, queen, rook, bishop (L), knight or pawn
+  x            the move captures (en passant included)
+  %            the move is an en passant capture
+  +            the move gives the opponent check (mate included)
+  ^            the move gives the opponent check but not mate
+  #            the move mates the opponent
+  ?            the mover was in check before the move, which gets it out
+  =            the move gives stalemate
+  @            the move repeats a position for the third time (or more)
+  /            the move brings the halfmove clock to 100, reaching the fifty-move rule
+  *            the move is made by the piece that made the side's previous move
+  X            the move recaptures on the square the other side just captured on
+  o            kingside castling
+  0            queenside castling
+  O            castling on either side
+  W B          castling by White or by Black, on either side
+  q r l n      promotion to a queen, rook, bishop or knight
+  $ _          the tablebases call the position after the move won or drawn for the mover (syzygy feature)
+
+Example: `steno_solver \"PPN~Qx#\"` finds every seven-ply game whose last move
+is a queen capture giving mate.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CastleSide {
+    Kingside,
+    Queenside,
+}
+
+// Part of the board a king can be asked to stand in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Zone {
+    File(File),
+    Rank(Rank),
+    Square(Square),
+}
+
+impl Zone {
+    pub(crate) fn contains(self, square: Square) -> bool {
+        match self {
+            Zone::File(file) => square.get_file() == file,
+            Zone::Rank(rank) => square.get_rank() == rank,
+            Zone::Square(zone) => square == zone,
+        }
+    }
+
+    fn mirrored(self) -> Zone {
+        match self {
+            Zone::File(file) => Zone::File(file),
+            Zone::Rank(rank) => Zone::Rank(Rank::from_index(7 - rank.to_index())),
+            Zone::Square(square) => Zone::Square(Square::make_square(Rank::from_index(7 - square.get_rank().to_index()), square.get_file())),
+        }
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Zone::File(file) => write!(f, "{}", (b'a' + file.to_index() as u8) as char),
+            Zone::Rank(rank) => write!(f, "{}", rank.to_index() + 1),
+            Zone::Square(square) => write!(f, "{}", square),
+        }
+    }
+}
+
+// What one ply of a steno asks of the move played there.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Constraint {
+    Any,
+    File(File),
+    Rank(Rank),
+    // The destination square; for castling, the king's.
+    Square(Square),
+    // After the move the opponent has none of this piece left.
+    Eliminated(Piece),
+    Mover(Piece),
+    // En passant included.
+    Capture,
+    EnPassant,
+    Check,
+    CheckNotMate,
+    Checkmate,
+    Stalemate,
+    // The mover was in check before the move.
+    EscapeCheck,
+    Repetition,
+    FiftyMoves,
+    // The piece that made this side's last move moves again.
+    SamePiece,
+    // Takes on the square the other side's move just before captured on.
+    Recapture,
+    // Made by the piece that stood on this square in the start position.
+    Origin(Square),
+    // After the move `color`'s king stands in the zone, or when not
+    // `inside`, anywhere else.
+    KingZone { color: Color, zone: Zone, inside: bool },
+    // Either side or either color when None.
+    Castle { side: Option<CastleSide>, color: Option<Color> },
+    Promotion(Piece),
+    // The tables from --syzygy-path give the mover this result after the
+    // move. Only a ply's own position is probed: best play says nothing of
+    // which games a steno allows, so nothing is pruned by it.
+    #[cfg(feature = "syzygy")]
+    Tablebase(TablebaseOutcome),
+    // Every one of these, from stenos overlaid with --and.
+    All(Vec<Constraint>),
+    // A predicate from --define, written as its character in the steno.
+    #[cfg(feature = "script")]
+    Custom(char, Arc<Script>),
+}
+
+// Everything a constraint can look at: the move and the position it led to.
+#[derive(Clone, Copy, Debug)]
+pub struct MoveContext<'a> {
+    pub mov: ChessMove,
+    pub mover: Piece,
+    pub color: Color,
+    // The piece taken and the square it stood on, which for en passant isn't
+    // the destination.
+    pub captured: Option<Piece>,
+    pub captured_square: Option<Square>,
+    pub en_passant: bool,
+    pub castling: Option<CastleSide>,
+    pub board: &'a Board,
+    // The pieces giving the opponent check after the move, and whether the
+    // mover was in check before it.
+    pub checkers: BitBoard,
+    pub in_check: bool,
+    // Only worked out when the steno asks about repetition, the fifty-move
+    // rule or the plies before, since it takes the whole line.
+    pub line: Option<LineState>,
+}
+
+// What the game so far says about the position a move led to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineState {
+    // Times the position has occurred, this time included.
+    pub repetitions: usize,
+    pub halfmove_clock: u32,
+    // Where the side that moved last went with its move before, and where
+    // the other side's move just before that captured. None before the
+    // start position, whose earlier moves aren't known.
+    pub own_last_square: Option<Square>,
+    pub capture_square: Option<Square>,
+    // Where the piece that moved last stood in the start position; a
+    // promoted piece is the pawn's.
+    pub mover_origin: Option<Square>,
+}
+
+// The FEN's fifth field, which Board doesn't keep; 0 without a FEN or when
+// the field is missing.
+pub fn halfmove_clock(fen: &Option<String>) -> u32 {
+    fen.as_deref().and_then(|fen| fen.split_whitespace().nth(4)?.parse().ok()).unwrap_or(0)
+}
+
+// The line state after `path`, played from `root` whose halfmove clock was
+// `halfmove_clock`. Captures and pawn moves reset the clock and can't be
+// undone, so positions before the last of them are never repeated.
+pub(crate) fn line_state(root: &Board, halfmove_clock: u32, path: &[ChessMove]) -> LineState {
+    let mut board = *root;
+    let mut clock = halfmove_clock;
+    let mut hashes = vec![root.get_hash()];
+    // Each move's side, destination and whether it captured.
+    let mut plies = Vec::with_capacity(path.len());
+    // The start square of the piece on each square.
+    let mut origins = ALL_SQUARES.map(|square| root.piece_on(square).map(|_| square));
+    let mut mover_origin = None;
+    for &mov in path {
+        // A series' path leaves out the other side's passes.
+        if board.color_on(mov.get_source()) != Some(board.side_to_move()) {
+            board = board.null_move().unwrap_or(board);
+        }
+        let (source, dest) = (mov.get_source(), mov.get_dest());
+        let pawn = board.piece_on(source) == Some(Piece::Pawn);
+        let en_passant = pawn && source.get_file() != dest.get_file() && board.piece_on(dest).is_none();
+        let irreversible = pawn || board.piece_on(dest).is_some();
+        plies.push((board.color_on(source), dest, board.piece_on(dest).is_some() || en_passant));
+
+        mover_origin = origins[source.to_index()];
+        origins[dest.to_index()] = origins[source.to_index()].take();
+        if en_passant {
+            origins[Square::make_square(source.get_rank(), dest.get_file()).to_index()] = None;
+        }
+        // Castling is the king's two-square step; the rook jumps over it.
+        if board.piece_on(source) == Some(Piece::King) && source.get_file().to_index().abs_diff(dest.get_file().to_index()) == 2 {
+            let (rook_from, rook_to) = if dest.get_file() > source.get_file() { (File::H, File::F) } else { (File::A, File::D) };
+            let square = |file| Square::make_square(source.get_rank(), file);
+            origins[square(rook_to).to_index()] = origins[square(rook_from).to_index()].take();
+        }
+        board = board.make_move_new(mov);
+        if irreversible {
+            clock = 0;
+            hashes.clear();
+        } else {
+            clock += 1;
+        }
+        hashes.push(board.get_hash());
+    }
+    let hash = board.get_hash();
+    // In a series the side's move before is the one just before.
+    let (own_last_square, capture_square) = match plies.split_last() {
+        Some((&(color, _, _), before)) => (
+            before.iter().rev().find(|(other, _, _)| *other == color).map(|&(_, dest, _)| dest),
+            before.last().filter(|(other, _, captured)| *other != color && *captured).map(|&(_, dest, _)| dest),
+        ),
+        None => (None, None),
+    };
+    LineState { repetitions: hashes.iter().filter(|&&other| other == hash).count(), halfmove_clock: clock, own_last_square, capture_square, mover_origin }
+}
+
+impl<'a> MoveContext<'a> {
+    // `mov` is a legal move in `before`, and `board` the position it leads to.
+    pub fn new(before: &Board, mov: ChessMove, board: &'a Board) -> MoveContext<'a> {
+        let (source, dest) = (mov.get_source(), mov.get_dest());
+        let mover = before.piece_on(source).expect("the move's source square holds the piece moved");
+        // The crate's en passant square is the pawn that just made a double
+        // step; taking it lands behind it, on its file.
+        let en_passant_pawn = before.en_passant()
+            .filter(|pawn| mover == Piece::Pawn && pawn.get_file() == dest.get_file() && pawn.get_rank() == source.get_rank());
+        let (captured, captured_square) = match (en_passant_pawn, before.piece_on(dest)) {
+            (Some(pawn), _) => (Some(Piece::Pawn), Some(pawn)),
+            (None, Some(piece)) => (Some(piece), Some(dest)),
+            (None, None) => (None, None),
+        };
+        // The crate encodes castling as the king's two-square step.
+        let castling = (mover == Piece::King && source.get_file().to_index().abs_diff(dest.get_file().to_index()) == 2)
+            .then(|| if dest.get_file() > source.get_file() { CastleSide::Kingside } else { CastleSide::Queenside });
+        MoveContext {
+            mov,
+            mover,
+            color: before.side_to_move(),
+            captured,
+            captured_square,
+            en_passant: en_passant_pawn.is_some(),
+            castling,
+            board,
+            checkers: *board.checkers(),
+            in_check: before.checkers().popcnt() > 0,
+            line: None,
+        }
+    }
+}
+
+impl Constraint {
+    pub fn from_char(ch: char) -> Option<Constraint> {
+        Some(match ch {
+            '~' => Constraint::Any,
+            'a'..='h' => Constraint::File(File::from_index(ch as usize - 'a' as usize)),
+            '1'..='8' => Constraint::Rank(Rank::from_index(ch as usize - '1' as usize)),
+            'K' => Constraint::Mover(Piece::King),
+            'Q' => Constraint::Mover(Piece::Queen),
+            'R' => Constraint::Mover(Piece::Rook),
+            'L' => Constraint::Mover(Piece::Bishop),
+            'N' => Constraint::Mover(Piece::Knight),
+            'P' => Constraint::Mover(Piece::Pawn),
+            'x' => Constraint::Capture,
+            '%' => Constraint::EnPassant,
+            '+' => Constraint::Check,
+            '^' => Constraint::CheckNotMate,
+            '#' => Constraint::Checkmate,
+            '=' => Constraint::Stalemate,
+            '?' => Constraint::EscapeCheck,
+            '@' => Constraint::Repetition,
+            '/' => Constraint::FiftyMoves,
+            '*' => Constraint::SamePiece,
+            'X' => Constraint::Recapture,
+            'o' => Constraint::Castle { side: Some(CastleSide::Kingside), color: None },
+            '0' => Constraint::Castle { side: Some(CastleSide::Queenside), color: None },
+            'O' => Constraint::Castle { side: None, color: None },
+            'W' => Constraint::Castle { side: None, color: Some(Color::White) },
+            'B' => Constraint::Castle { side: None, color: Some(Color::Black) },
+            'q' => Constraint::Promotion(Piece::Queen),
+            'r' => Constraint::Promotion(Piece::Rook),
+            'l' => Constraint::Promotion(Piece::Bishop),
+            'n' => Constraint::Promotion(Piece::Knight),
+            #[cfg(feature = "syzygy")]
+            '$' => Constraint::Tablebase(TablebaseOutcome::Win),
+            #[cfg(feature = "syzygy")]
+            '_' => Constraint::Tablebase(TablebaseOutcome::Draw),
+            _ => return None,
+        })
+    }
+
+    // None for a conjunction, a square or a material predicate, which are
+    // written in brackets.
+    pub fn to_char(&self) -> Option<char> {
+        let piece_char = |piece| match piece {
+            Piece::King => 'K',
+            Piece::Queen => 'Q',
+            Piece::Rook => 'R',
+            Piece::Bishop => 'L',
+            Piece::Knight => 'N',
+            Piece::Pawn => 'P',
+        };
+        Some(match *self {
+            Constraint::Any => '~',
+            Constraint::File(file) => (b'a' + file.to_index() as u8) as char,
+            Constraint::Rank(rank) => (b'1' + rank.to_index() as u8) as char,
+            Constraint::Square(_) | Constraint::Eliminated(_) | Constraint::Origin(_) | Constraint::KingZone { .. } => return None,
+            Constraint::Mover(piece) => piece_char(piece),
+            Constraint::Capture => 'x',
+            Constraint::EnPassant => '%',
+            Constraint::Check => '+',
+            Constraint::CheckNotMate => '^',
+            Constraint::Checkmate => '#',
+            Constraint::Stalemate => '=',
+            Constraint::EscapeCheck => '?',
+            Constraint::Repetition => '@',
+            Constraint::FiftyMoves => '/',
+            Constraint::SamePiece => '*',
+            Constraint::Recapture => 'X',
+            Constraint::Castle { side: Some(CastleSide::Kingside), .. } => 'o',
+            Constraint::Castle { side: Some(CastleSide::Queenside), .. } => '0',
+            Constraint::Castle { side: None, color: None } => 'O',
+            Constraint::Castle { side: None, color: Some(Color::White) } => 'W',
+            Constraint::Castle { side: None, color: Some(Color::Black) } => 'B',
+            Constraint::Promotion(piece) => piece_char(piece).to_ascii_lowercase(),
+            #[cfg(feature = "syzygy")]
+            Constraint::Tablebase(TablebaseOutcome::Win) => '$',
+            #[cfg(feature = "syzygy")]
+            Constraint::Tablebase(TablebaseOutcome::Draw) => '_',
+            #[cfg(feature = "syzygy")]
+            Constraint::Tablebase(TablebaseOutcome::Loss) => return None,
+            Constraint::All(_) => return None,
+            #[cfg(feature = "script")]
+            Constraint::Custom(ch, _) => ch,
+        })
+    }
+
+    // As written between brackets.
+    fn bracketed(&self) -> String {
+        match self {
+            Constraint::All(parts) => parts.iter().map(Constraint::bracketed).collect::<Vec<_>>().join("&"),
+            Constraint::Square(square) => square.to_string(),
+            Constraint::Eliminated(piece) => format!("!{}", Constraint::Mover(*piece)),
+            Constraint::Origin(square) => format!("@{}", square),
+            Constraint::KingZone { color, zone, inside } => format!("{{{}{}{}}}", if *color == Color::White { 'K' } else { 'k' }, if *inside { "" } else { "!" }, zone),
+            _ => self.to_string(),
+        }
+    }
+
+    // The same constraint with the colors swapped and the board turned over,
+    // or None for a predicate from --define, which can't be mirrored.
+    pub(crate) fn mirrored(&self) -> Option<Constraint> {
+        Some(match self {
+            Constraint::Rank(rank) => Constraint::Rank(Rank::from_index(7 - rank.to_index())),
+            Constraint::Square(square) => Constraint::Square(Square::make_square(Rank::from_index(7 - square.get_rank().to_index()), square.get_file())),
+            Constraint::Origin(square) => Constraint::Origin(Square::make_square(Rank::from_index(7 - square.get_rank().to_index()), square.get_file())),
+            Constraint::KingZone { color, zone, inside } => Constraint::KingZone { color: !*color, zone: zone.mirrored(), inside: *inside },
+            Constraint::Castle { side, color } => Constraint::Castle { side: *side, color: color.map(|color| !color) },
+            Constraint::All(parts) => Constraint::All(parts.iter().map(Constraint::mirrored).collect::<Option<_>>()?),
+            #[cfg(feature = "script")]
+            Constraint::Custom(..) => return None,
+            other => other.clone(),
+        })
+    }
+
+    // Whether this constraint or, for a conjunction, one of its parts passes `test`.
+    pub(crate) fn requires(&self, test: &impl Fn(&Constraint) -> bool) -> bool {
+        match self {
+            Constraint::All(parts) => parts.iter().any(|part| part.requires(test)),
+            _ => test(self),
+        }
+    }
+
+    pub fn matches(&self, context: &MoveContext) -> bool {
+        let dest = context.mov.get_dest();
+        match *self {
+            Constraint::Any => true,
+            Constraint::File(file) => dest.get_file() == file,
+            Constraint::Rank(rank) => dest.get_rank() == rank,
+            Constraint::Square(square) => dest == square,
+            Constraint::Eliminated(piece) => (context.board.pieces(piece) & context.board.color_combined(!context.color)).popcnt() == 0,
+            Constraint::Mover(piece) => context.mover == piece,
+            Constraint::Capture => context.captured.is_some(),
+            Constraint::EnPassant => context.en_passant,
+            Constraint::Check => context.checkers.popcnt() > 0,
+            Constraint::CheckNotMate => context.checkers.popcnt() > 0 && context.board.status() != BoardStatus::Checkmate,
+            Constraint::Checkmate => matches!(context.board.status(), BoardStatus::Checkmate),
+            Constraint::Stalemate => matches!(context.board.status(), BoardStatus::Stalemate),
+            Constraint::EscapeCheck => context.in_check,
+            Constraint::Repetition => context.line.is_some_and(|line| line.repetitions >= 3),
+            Constraint::FiftyMoves => context.line.is_some_and(|line| line.halfmove_clock >= 100),
+            Constraint::SamePiece => context.line.is_some_and(|line| line.own_last_square == Some(context.mov.get_source())),
+            Constraint::Recapture => context.line.is_some_and(|line| line.capture_square.is_some() && line.capture_square == context.captured_square),
+            Constraint::Origin(square) => context.line.is_some_and(|line| line.mover_origin == Some(square)),
+            Constraint::KingZone { color, zone, inside } => zone.contains(context.board.king_square(color)) == inside,
+            Constraint::Castle { side, color } => {
+                context.castling.is_some_and(|castling| side.is_none_or(|side| side == castling))
+                    && color.is_none_or(|color| color == context.color)
+            }
+            Constraint::Promotion(piece) => context.mov.get_promotion() == Some(piece),
+            #[cfg(feature = "syzygy")]
+            Constraint::Tablebase(outcome) => probe(context.board) == Some(outcome),
+            Constraint::All(ref parts) => parts.iter().all(|part| part.matches(context)),
+            #[cfg(feature = "script")]
+            Constraint::Custom(_, ref script) => script.matches(context),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self, self.to_char()) {
+            (_, Some(ch)) => write!(f, "{}", ch),
+            (Constraint::KingZone { .. }, None) => write!(f, "{}", self.bracketed()),
+            (_, None) => write!(f, "[{}]", self.bracketed()),
+        }
+    }
+}
+
+// In JSON a constraint is its ply of a steno, "x" or "[e4&+]". A predicate
+// from --define is written as its character, which reads back only where
+// the same definition is given, so not through Deserialize.
+impl Serialize for Constraint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Constraint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Constraint, D::Error> {
+        let ply = String::deserialize(deserializer)?;
+        let mut constraints = parse_steno_string(&ply).map_err(D::Error::custom)?;
+        match constraints.len() {
+            1 => Ok(constraints.pop().unwrap()),
+            _ => Err(D::Error::custom(format!("Expected one ply of a steno, got {:?}", ply))),
+        }
+    }
+}
+
+// A braced ply, `{ke}` or `{K!1}`: where the kings stand after the move,
+// White's written K and Black's k as in a FEN, `!` for anywhere but there.
+fn parse_position(token: &str) -> Result<Vec<Constraint>, String> {
+    let invalid = || format!("Invalid position predicate in steno string: {{{}}}", token);
+    let mut predicates = Vec::new();
+    let mut chars = token.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let color = match ch {
+            '&' => continue,
+            'K' => Color::White,
+            'k' => Color::Black,
+            _ => return Err(invalid()),
+        };
+        let inside = chars.next_if_eq(&'!').is_none();
+        let zone = match (chars.next(), chars.peek().copied()) {
+            (Some(file @ 'a'..='h'), Some(rank @ '1'..='8')) => {
+                chars.next();
+                Zone::Square(Square::from_str(&format!("{}{}", file, rank)).unwrap())
+            }
+            (Some(file @ 'a'..='h'), _) => Zone::File(File::from_index(file as usize - 'a' as usize)),
+            (Some(rank @ '1'..='8'), _) => Zone::Rank(Rank::from_index(rank as usize - '1' as usize)),
+            _ => return Err(invalid()),
+        };
+        predicates.push(Constraint::KingZone { color, zone, inside });
+    }
+    if predicates.is_empty() {
+        return Err(invalid());
+    }
+    Ok(predicates)
+}
+
+fn braced(rest: &str) -> Result<(&str, &str), String> {
+    rest.split_once('}').ok_or_else(|| format!("Unclosed {{ in steno string: {{{}", rest))
+}
+
+// A bracketed ply: squares, material and position predicates and constraint
+// characters, all of which the move has to satisfy.
+fn parse_bracketed(token: &str, definitions: &HashMap<char, Constraint>) -> Result<Constraint, String> {
+    let invalid = || format!("Invalid constraint in steno string: [{}]", token);
+    // Braced position predicates go after the rest.
+    let (mut plain, mut positions, mut rest) = (String::new(), Vec::new(), token);
+    while let Some((before, after)) = rest.split_once('{') {
+        plain.push_str(before);
+        let (predicates, after) = braced(after)?;
+        positions.extend(parse_position(predicates)?);
+        rest = after;
+    }
+    plain.push_str(rest);
+
+    let mut parts = Vec::new();
+    let mut chars = plain.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let part = match (ch, chars.peek().copied()) {
+            ('&', _) => continue,
+            ('!', Some(piece)) => match Constraint::from_char(piece) {
+                Some(Constraint::Mover(piece)) => Constraint::Eliminated(piece),
+                _ => return Err(invalid()),
+            },
+            ('a'..='h', Some(rank @ '1'..='8')) => Constraint::Square(Square::from_str(&format!("{}{}", ch, rank)).unwrap()),
+            ('@', Some(file @ 'a'..='h')) if matches!(chars.clone().nth(1), Some('1'..='8')) => {
+                chars.next();
+                Constraint::Origin(Square::from_str(&format!("{}{}", file, chars.peek().unwrap())).unwrap())
+            }
+            _ => {
+                parts.push(Constraint::from_char(ch).or_else(|| definitions.get(&ch).cloned()).ok_or_else(invalid)?);
+                continue;
+            }
+        };
+        chars.next();
+        parts.push(part);
+    }
+    parts.extend(positions);
+    match parts.len() {
+        0 => Err(invalid()),
+        1 => Ok(parts.pop().unwrap()),
+        _ => Ok(Constraint::All(parts)),
+    }
+}
+
+pub fn parse_steno_string(steno: &str) -> Result<Vec<Constraint>, String> {
+    parse_steno_with(steno, &HashMap::new())
+}
+
+// Like parse_steno_string, with extra characters standing for the given constraints.
+pub fn parse_steno_with(steno: &str, definitions: &HashMap<char, Constraint>) -> Result<Vec<Constraint>, String> {
+    let mut steno_constraints = Vec::new();
+    let mut rest = steno;
+    while let Some(ch) = rest.chars().next() {
+        rest = &rest[ch.len_utf8()..];
+        if ch == '[' {
+            let (token, after) = rest.split_once(']').ok_or_else(|| format!("Unclosed [ in steno string: [{}", rest))?;
+            steno_constraints.push(parse_bracketed(token, definitions)?);
+            rest = after;
+            continue;
+        }
+        if ch == '{' {
+            let (token, after) = braced(rest)?;
+            let mut predicates = parse_position(token)?;
+            steno_constraints.push(if predicates.len() == 1 { predicates.pop().unwrap() } else { Constraint::All(predicates) });
+            rest = after;
+            continue;
+        }
+        steno_constraints.push(Constraint::from_char(ch)
+            .or_else(|| definitions.get(&ch).cloned())
+            .ok_or_else(|| match ch {
+                '$' | '_' if cfg!(not(feature = "syzygy")) => format!("'{}' probes tablebases, which need a build with the syzygy feature", ch),
+                _ => format!("Invalid character in steno string: {}", ch),
+            })?);
+    }
+    Ok(steno_constraints)
+}
+
+// Past this many plies a steno is more likely a mistake than a search anyone
+// means to run; solve takes --max-plies to go further.
+pub const DEFAULT_MAX_PLIES: usize = 400;
+
+pub fn check_steno_length(steno_constraints: &[Constraint], max_plies: usize) -> Result<(), String> {
+    if steno_constraints.len() > max_plies {
+        return Err(format!("The steno has {} plies, more than the limit of {}", steno_constraints.len(), max_plies));
+    }
+    Ok(())
+}
+
+// The steno string for parsed constraints.
+pub fn steno_string(steno_constraints: &[Constraint]) -> String {
+    steno_constraints.iter().map(|constraint| constraint.to_string()).collect()
+}
+
+// Overlays stenos of the same length into one whose plies ask for everything
+// the stenos ask for at that ply.
+pub fn intersect_stenos(stenos: &[Vec<Constraint>]) -> Result<Vec<Constraint>, String> {
+    let Some(first) = stenos.first() else {
+        return Ok(Vec::new());
+    };
+    if let Some(other) = stenos.iter().find(|steno| steno.len() != first.len()) {
+        return Err(format!("Stenos of different lengths can't be combined: {} and {} plies", first.len(), other.len()));
+    }
+
+    Ok((0..first.len()).map(|ply| {
+        let mut parts: Vec<Constraint> = Vec::new();
+        for constraint in stenos.iter().map(|steno| &steno[ply]) {
+            if *constraint != Constraint::Any && !parts.contains(constraint) {
+                parts.push(constraint.clone());
+            }
+        }
+        match parts.len() {
+            0 => Constraint::Any,
+            1 => parts.pop().unwrap(),
+            _ => Constraint::All(parts),
+        }
+    }).collect())
+}
+
+// Earliest ply each constraint can be met at when starting from the initial
+// position (Fool's mate, 1. e4 d5 2. exd5, Loyd's ten-move stalemate...),
+// with `halfmove_clock` on it.
+fn earliest_ply(constraint: &Constraint, halfmove_clock: u32) -> usize {
+    match constraint {
+        Constraint::All(parts) => parts.iter().map(|part| earliest_ply(part, halfmove_clock)).max().unwrap_or(1),
+        Constraint::Capture | Constraint::Check | Constraint::CheckNotMate | Constraint::Mover(Piece::King | Piece::Queen | Piece::Rook | Piece::Bishop) => 3,
+        // 1. e4 f6 2. Qh5+ g6.
+        Constraint::Checkmate | Constraint::EscapeCheck => 4,
+        Constraint::EnPassant => 5,
+        Constraint::Castle { side: Some(CastleSide::Queenside), .. } | Constraint::Promotion(_) => 9,
+        Constraint::Castle { color: Some(Color::Black), .. } => 8,
+        Constraint::Castle { .. } => 7,
+        Constraint::Stalemate => 19,
+        // Two knights out and back twice.
+        Constraint::Repetition => 8,
+        // The pieces can go out and back to the initial position, with the
+        // clock run up on the way.
+        Constraint::FiftyMoves => 100usize.saturating_sub(halfmove_clock as usize).max(1),
+        // 1. Nf3 e5 2. Ng5, and 1. e4 d5 2. exd5 Qxd5.
+        Constraint::SamePiece => 3,
+        Constraint::Recapture => 4,
+        // Seven men left after 25 captures, the first of them at ply 3.
+        #[cfg(feature = "syzygy")]
+        Constraint::Tablebase(_) => 27,
+        _ => 1,
+    }
+}
+
+// Earliest ply a promotion by `color` can be played at from `board`: its
+// most advanced pawn walking straight up, a double step included. None
+// when it has no pawns.
+fn earliest_promotion(board: &Board, color: Color) -> Option<usize> {
+    let pawns = board.pieces(Piece::Pawn) & board.color_combined(color);
+    let moves = pawns.map(|square| {
+        let rank = square.get_rank().to_index();
+        let (distance, start) = match color {
+            Color::White => (7 - rank, 1),
+            Color::Black => (rank, 6),
+        };
+        if rank == start { distance - 1 } else { distance }
+    }).min()?;
+    let first_ply = if color == board.side_to_move() { 1 } else { 2 };
+    Some(first_ply + 2 * (moves - 1))
+}
+
+// Earliest ply `constraint` can be met at when played by `color` from a
+// position other than the initial one. Only promotions, en passant and
+// getting out of check, which the position says something about, and the
+// constraints on the plies before get a bound above the first ply.
+fn earliest_ply_from(board: &Board, halfmove_clock: u32, constraint: &Constraint, color: Color) -> Option<usize> {
+    match constraint {
+        Constraint::All(parts) => parts.iter().map(|part| earliest_ply_from(board, halfmove_clock, part, color)).try_fold(1, |latest, earliest| Some(latest.max(earliest?))),
+        Constraint::Promotion(_) => earliest_promotion(board, color),
+        // The FEN's en passant square only allows it on the first ply.
+        Constraint::EnPassant if board.en_passant().is_none() => Some(2),
+        Constraint::EscapeCheck if board.checkers().popcnt() == 0 => Some(2),
+        // The moves before the start position aren't known.
+        Constraint::Recapture => Some(2),
+        Constraint::SamePiece => Some(3),
+        Constraint::FiftyMoves => Some(100usize.saturating_sub(halfmove_clock as usize).max(1)),
+        // A capture a ply at most, down to the seven men tables go up to.
+        #[cfg(feature = "syzygy")]
+        Constraint::Tablebase(_) => Some((board.combined().popcnt() as usize).saturating_sub(7).max(1)),
+        _ => Some(1),
+    }
+}
+
+// Whether no move can satisfy both `a` and `b`.
+pub(crate) fn conflict(a: &Constraint, b: &Constraint) -> bool {
+    let castle_file = |side: Option<CastleSide>, file: File| match side {
+        Some(CastleSide::Kingside) => file != File::G,
+        Some(CastleSide::Queenside) => file != File::C,
+        None => file != File::G && file != File::C,
+    };
+    let castle_rank = |color: Option<Color>, rank: Rank| match color {
+        Some(Color::White) => rank != Rank::First,
+        Some(Color::Black) => rank != Rank::Eighth,
+        None => rank != Rank::First && rank != Rank::Eighth,
+    };
+    let one_way = |a: &Constraint, b: &Constraint| match (a, b) {
+        (Constraint::File(x), Constraint::File(y)) => x != y,
+        (Constraint::Rank(x), Constraint::Rank(y)) => x != y,
+        (Constraint::Mover(x), Constraint::Mover(y)) => x != y,
+        (Constraint::Promotion(x), Constraint::Promotion(y)) => x != y,
+        (Constraint::Mover(piece), Constraint::EnPassant | Constraint::Promotion(_)) => *piece != Piece::Pawn,
+        (Constraint::Mover(piece), Constraint::Castle { .. }) => *piece != Piece::King,
+        (Constraint::Castle { .. }, Constraint::Capture | Constraint::EnPassant | Constraint::Promotion(_) | Constraint::EscapeCheck) => true,
+        (Constraint::Castle { side: Some(x), .. }, Constraint::Castle { side: Some(y), .. }) if x != y => true,
+        (Constraint::Castle { color: Some(x), .. }, Constraint::Castle { color: Some(y), .. }) => x != y,
+        (Constraint::Castle { side, .. }, Constraint::File(file)) => castle_file(*side, *file),
+        (Constraint::Castle { color, .. }, Constraint::Rank(rank)) => castle_rank(*color, *rank),
+        (Constraint::Promotion(_), Constraint::EnPassant) => true,
+        (Constraint::Promotion(_), Constraint::Rank(rank)) => *rank != Rank::First && *rank != Rank::Eighth,
+        (Constraint::EnPassant, Constraint::Rank(rank)) => *rank != Rank::Third && *rank != Rank::Sixth,
+        (Constraint::Checkmate, Constraint::Stalemate | Constraint::CheckNotMate) => true,
+        (Constraint::Stalemate, Constraint::Check | Constraint::CheckNotMate) => true,
+        _ => false,
+    };
+    match (a, b) {
+        (Constraint::Square(square), other) | (other, Constraint::Square(square)) => {
+            conflict(&Constraint::File(square.get_file()), other) || conflict(&Constraint::Rank(square.get_rank()), other)
+        }
+        _ => one_way(a, b) || one_way(b, a),
+    }
+}
+
+// The parts of a ply's constraint, flattened from nested conjunctions.
+pub(crate) fn parts(constraint: &Constraint) -> Vec<&Constraint> {
+    match constraint {
+        Constraint::All(all) => all.iter().flat_map(parts).collect(),
+        _ => vec![constraint],
+    }
+}
+
+// Reasons the steno can't have solutions that are visible without searching.
+// Each one is a proof, so the solver doesn't search when there are any.
+pub(crate) fn suspicious_constraints(board: &Board, halfmove_clock: u32, steno_constraints: &[Constraint]) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for (ply, constraint) in steno_constraints.iter().enumerate() {
+        let parts = parts(constraint);
+        for (index, a) in parts.iter().enumerate() {
+            if let Some(b) = parts[index + 1..].iter().find(|b| conflict(a, b)) {
+                warnings.push(format!("Ply {} asks for both '{}' and '{}', which no move can be", ply + 1, a, b));
+            }
+        }
+        if parts.contains(&&Constraint::Eliminated(Piece::King)) {
+            warnings.push(format!("'{}' at ply {} is impossible: kings are never captured", Constraint::Eliminated(Piece::King), ply + 1));
+        }
+
+        // Castling rights are only ever lost, so those missing at the start
+        // stay missing.
+        let color = if ply % 2 == 0 { board.side_to_move() } else { !board.side_to_move() };
+        for part in &parts {
+            let Constraint::Castle { side, color: castling_color } = part else {
+                continue;
+            };
+            if castling_color.is_some_and(|castling_color| castling_color != color) {
+                warnings.push(format!("'{}' at ply {} is a {:?} move, so it can never be castling by {:?}", part, ply + 1, color, castling_color.unwrap()));
+                continue;
+            }
+            let rights = board.castle_rights(color);
+            let possible = match side {
+                Some(CastleSide::Kingside) => rights.has_kingside(),
+                Some(CastleSide::Queenside) => rights.has_queenside(),
+                None => rights.has_kingside() || rights.has_queenside(),
+            };
+            if !possible {
+                warnings.push(format!("'{}' at ply {} is impossible: {:?} can no longer castle that way", part, ply + 1, color));
+            }
+        }
+    }
+
+    if let Some(end) = steno_constraints.iter().position(|constraint| constraint.requires(&|part| matches!(part, Constraint::Checkmate | Constraint::Stalemate))) {
+        let remaining = steno_constraints.len() - end - 1;
+        if remaining > 0 {
+            warnings.push(format!("Ply {} ends the game with '{}', so the {} plies after it can never be played", end + 1, steno_constraints[end], remaining));
+        }
+    }
+
+    for (ply, constraint) in steno_constraints.iter().enumerate() {
+        if *board == Board::default() {
+            let earliest = earliest_ply(constraint, halfmove_clock);
+            if ply + 1 < earliest {
+                warnings.push(format!("'{}' at ply {} is impossible from the initial position (earliest is ply {})", constraint, ply + 1, earliest));
+            }
+            continue;
+        }
+        let color = if ply % 2 == 0 { board.side_to_move() } else { !board.side_to_move() };
+        match earliest_ply_from(board, halfmove_clock, constraint, color) {
+            Some(earliest) if ply + 1 < earliest => {
+                warnings.push(format!("'{}' at ply {} is impossible from this position (earliest is ply {})", constraint, ply + 1, earliest));
+            }
+            Some(_) => {}
+            None => warnings.push(format!("'{}' at ply {} is impossible: {:?} has no pawns to promote", constraint, ply + 1, color)),
+        }
+    }
+
+    warnings.extend(parity_conflicts(board, steno_constraints));
+    warnings
+}
+
+// What `lint` reports: the reasons the steno has no solutions, and notes on
+// plies that mean less than they may seem to.
+pub struct Lint {
+    pub warnings: Vec<String>,
+    pub notes: Vec<String>,
+}
+
+pub fn lint_steno(board: &Board, halfmove_clock: u32, steno_constraints: &[Constraint]) -> Lint {
+    let mut notes = Vec::new();
+    for (ply, constraint) in steno_constraints.iter().enumerate() {
+        let color = if ply % 2 == 0 { board.side_to_move() } else { !board.side_to_move() };
+        if parts(constraint).iter().any(|part| matches!(part, Constraint::Castle { color: None, .. })) {
+            notes.push(format!("'{}' at ply {} is a {:?} move, so it only matches {:?} castling", constraint, ply + 1, color, color));
+        }
+    }
+    Lint { warnings: suspicious_constraints(board, halfmove_clock, steno_constraints), notes }
+}
+
+fn piece_name(piece: Piece) -> &'static str {
+    match piece {
+        Piece::Pawn => "pawn",
+        Piece::Knight => "knight",
+        Piece::Bishop => "bishop",
+        Piece::Rook => "rook",
+        Piece::Queen => "queen",
+        Piece::King => "king",
+    }
+}
+
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+fn join_and(words: &[String]) -> String {
+    match words {
+        [] => String::new(),
+        [word] => word.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+// A ply's constraint in words, e.g. "a rook move that gives check": the
+// mover, whether it captures, where it lands, then everything else it does.
+fn describe(constraint: &Constraint) -> String {
+    let mut movers = Vec::new();
+    let mut noun = "move";
+    let mut destinations = Vec::new();
+    let mut clauses = Vec::new();
+    for part in parts(constraint) {
+        match part {
+            Constraint::Any | Constraint::All(_) => {}
+            Constraint::Mover(piece) => movers.push(piece_name(*piece).to_string()),
+            Constraint::Capture if noun != "en passant capture" => noun = "capture",
+            Constraint::Capture => {}
+            Constraint::EnPassant => noun = "en passant capture",
+            Constraint::File(file) => destinations.push(format!("to the {}-file", (b'a' + file.to_index() as u8) as char)),
+            Constraint::Rank(rank) => destinations.push(format!("to the {} rank", ordinal(rank.to_index() as u32 + 1))),
+            Constraint::Square(square) => destinations.push(format!("to {}", square)),
+            Constraint::Eliminated(piece) => clauses.push(format!("leaves the opponent without {}s", piece_name(*piece))),
+            Constraint::Check => clauses.push("gives check".to_string()),
+            Constraint::CheckNotMate => clauses.push("gives check but not mate".to_string()),
+            Constraint::Checkmate => clauses.push("gives checkmate".to_string()),
+            Constraint::Stalemate => clauses.push("gives stalemate".to_string()),
+            Constraint::EscapeCheck => clauses.push("gets out of check".to_string()),
+            Constraint::Repetition => clauses.push("repeats a position for the third time".to_string()),
+            Constraint::FiftyMoves => clauses.push("brings the halfmove clock to 100".to_string()),
+            Constraint::SamePiece => clauses.push("moves the same piece again".to_string()),
+            Constraint::Recapture => clauses.push("takes back on the square just captured on".to_string()),
+            Constraint::Origin(square) => clauses.push(format!("is made by the piece that started on {}", square)),
+            Constraint::KingZone { color, zone, inside } => {
+                let zone = match zone {
+                    Zone::File(_) => format!("the {}-file", zone),
+                    Zone::Rank(rank) => format!("the {} rank", ordinal(rank.to_index() as u32 + 1)),
+                    Zone::Square(square) => square.to_string(),
+                };
+                let king = if *color == Color::White { "white" } else { "black" };
+                clauses.push(format!("leaves the {} king {} {}", king, if *inside { "on" } else { "off" }, zone));
+            }
+            Constraint::Castle { side, color } => {
+                let side = match side {
+                    Some(CastleSide::Kingside) => " kingside",
+                    Some(CastleSide::Queenside) => " queenside",
+                    None => "",
+                };
+                let color = color.map_or(String::new(), |color| format!(" by {:?}", color));
+                clauses.push(format!("castles{}{}", side, color));
+            }
+            Constraint::Promotion(piece) => clauses.push(format!("promotes to a {}", piece_name(*piece))),
+            #[cfg(feature = "syzygy")]
+            Constraint::Tablebase(outcome) => clauses.push(format!("leaves a tablebase {:?} for the mover", outcome).to_lowercase()),
+            #[cfg(feature = "script")]
+            Constraint::Custom(ch, _) => clauses.push(format!("matches --define {}", ch)),
+        }
+    }
+    if movers.is_empty() && destinations.is_empty() && clauses.is_empty() && noun == "move" {
+        return "any move".to_string();
+    }
+
+    let mut phrase = join_and(&movers);
+    if !phrase.is_empty() {
+        phrase.push(' ');
+    }
+    phrase.push_str(noun);
+    for destination in &destinations {
+        phrase.push(' ');
+        phrase.push_str(destination);
+    }
+    if !clauses.is_empty() {
+        phrase.push_str(" that ");
+        phrase.push_str(&join_and(&clauses));
+    }
+    let article = if phrase.starts_with(['a', 'e', 'i', 'o', 'u']) { "an" } else { "a" };
+    format!("{} {}", article, phrase)
+}
+
+// One line per ply: whose move it is, counting moves from `fullmove` as a
+// FEN does, and what the steno asks of it.
+pub fn explain_steno(steno_constraints: &[Constraint], side_to_move: Color, fullmove: u32) -> Vec<String> {
+    // Plies already played in the move `fullmove`.
+    let offset = (side_to_move == Color::Black) as usize;
+    steno_constraints.iter().enumerate().map(|(ply, constraint)| {
+        let color = if ply.is_multiple_of(2) { side_to_move } else { !side_to_move };
+        let number = fullmove + ((ply + offset) / 2) as u32;
+        format!("{:?}'s {} move (ply {}): {}", color, ordinal(number), ply + 1, describe(constraint))
+    }).collect()
+}
+
+// `last` is the position before `board` and the move that led from it, None
+// at the root.
+pub(crate) fn check_steno_constraints(board: &Board, last: Option<(&Board, ChessMove)>, depth: usize, steno_constraints: &[Constraint], line: Option<LineState>) -> bool {
+    let Some((before, last_move)) = last else {
+        return true;
+    };
+    let context = MoveContext { line, ..MoveContext::new(before, last_move, board) };
+    steno_constraints[depth - 1].matches(&context)
+}
+
+// Whether a ply of the steno asks about repetition, the fifty-move rule, the
+// plies before it or where a piece started.
+pub(crate) fn requires_line(steno_constraints: &[Constraint]) -> bool {
+    steno_constraints.iter().any(|constraint| constraint.requires(&|part| matches!(part, Constraint::Repetition | Constraint::FiftyMoves | Constraint::SamePiece | Constraint::Recapture | Constraint::Origin(_))))
+}
+
+// Checks that `moves`, played from `board` with `halfmove_clock` on it,
+// satisfy the steno ply by ply.
+pub fn verify_game(board: Board, halfmove_clock: u32, steno_constraints: &[Constraint], moves: &[ChessMove]) -> Result<(), String> {
+    if moves.len() != steno_constraints.len() {
+        return Err(format!("The game has {} plies but the steno has {}", moves.len(), steno_constraints.len()));
+    }
+
+    let (start, mut board) = (board, board);
+    for (ply, &mov) in moves.iter().enumerate() {
+        if !board.legal(mov) {
+            return Err(format!("Illegal move at ply {}: {}", ply + 1, mov));
+        }
+        let before = board;
+        board = board.make_move_new(mov);
+        let line = requires_line(steno_constraints).then(|| line_state(&start, halfmove_clock, &moves[..=ply]));
+        if !check_steno_constraints(&board, Some((&before, mov)), ply + 1, steno_constraints, line) {
+            return Err(format!("Ply {} ({}) does not satisfy '{}'", ply + 1, mov, steno_constraints[ply]));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The move `uci` from `fen`, as the constraints see it.
+    fn with_context<T>(fen: &str, uci: &str, test: impl FnOnce(&MoveContext) -> T) -> T {
+        let before = Board::from_str(fen).unwrap();
+        let mov = ChessMove::from_str(uci).unwrap();
+        assert!(before.legal(mov), "{} isn't legal in {}", uci, fen);
+        let after = before.make_move_new(mov);
+        test(&MoveContext::new(&before, mov, &after))
+    }
+
+    fn matches(fen: &str, uci: &str, ply: &str) -> bool {
+        let constraint = parse_steno_string(ply).unwrap().pop().unwrap();
+        with_context(fen, uci, |context| constraint.matches(context))
+    }
+
+    #[test]
+    fn en_passant_from_the_en_passant_square() {
+        let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 4";
+        // Only the pawn that just stepped two squares can be taken in passing.
+        assert!(matches(fen, "e5f6", "%"));
+        assert!(matches(fen, "e5f6", "x"));
+        assert!(!Board::from_str(fen).unwrap().legal(ChessMove::from_str("e5d6").unwrap()));
+        with_context(fen, "e5f6", |context| {
+            assert_eq!(context.captured, Some(Piece::Pawn));
+            assert_eq!(context.captured_square, Some(Square::F5));
+        });
+        // An ordinary pawn capture isn't en passant.
+        let fen = "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2";
+        assert!(matches(fen, "e4d5", "x"));
+        assert!(!matches(fen, "e4d5", "%"));
+        with_context(fen, "e4d5", |context| assert_eq!(context.captured_square, Some(Square::D5)));
+    }
+
+    #[test]
+    fn check_that_isnt_mate() {
+        // 1. e4 f6 2. Qh5+, which g6 blocks.
+        let fen = "rnbqkbnr/ppppp1pp/5p2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        assert!(matches(fen, "d1h5", "+"));
+        assert!(matches(fen, "d1h5", "^"));
+        assert!(!matches(fen, "d1h5", "#"));
+        // Fool's mate.
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2";
+        assert!(matches(fen, "d8h4", "+"));
+        assert!(matches(fen, "d8h4", "#"));
+        assert!(!matches(fen, "d8h4", "^"));
+        // Blocking it gets out of check.
+        let fen = "rnbqkbnr/ppppp1pp/5p2/7Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 2";
+        assert!(matches(fen, "g7g6", "?"));
+        assert!(!matches("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1", "e7e5", "?"));
+    }
+
+    #[test]
+    fn castling_by_either_color_on_either_side() {
+        let white = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
+        let black = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R b KQkq - 0 1";
+        for (fen, uci, side, color) in [(white, "e1g1", 'o', 'W'), (white, "e1c1", '0', 'W'), (black, "e8g8", 'o', 'B'), (black, "e8c8", '0', 'B')] {
+            let other_side = if side == 'o' { '0' } else { 'o' };
+            let other_color = if color == 'W' { 'B' } else { 'W' };
+            for (ply, matched) in [(side, true), (other_side, false), ('O', true), (color, true), (other_color, false), ('K', true)] {
+                assert_eq!(matches(fen, uci, &ply.to_string()), matched, "{} for {} in {}", ply, uci, fen);
+            }
+        }
+        // A king's one-square step isn't castling.
+        assert!(!matches(white, "e1f1", "O"));
+        // The king's destination, as in the steno.
+        assert!(matches(white, "e1g1", "[g1]"));
+        assert!(matches(black, "e8c8", "[c8]"));
+    }
+
+    #[test]
+    fn promotions() {
+        let fen = "1n6/P7/8/8/8/8/k6K/8 w - - 0 1";
+        assert!(matches(fen, "a7a8q", "q"));
+        assert!(!matches(fen, "a7a8q", "n"));
+        assert!(matches(fen, "a7a8n", "n"));
+        assert!(!matches(fen, "a7a8n", "x"));
+        assert!(matches(fen, "a7b8r", "[xr]"));
+        assert!(matches(fen, "a7b8b", "[Pxl&b8]"));
+        with_context(fen, "a7b8q", |context| {
+            assert_eq!(context.mover, Piece::Pawn);
+            assert_eq!(context.captured, Some(Piece::Knight));
+        });
+    }
+
+    #[test]
+    fn stenos_round_trip() {
+        for steno in ["~~N+", "abcdefgh12345678", "KQRLNPx%", "+^#?=@/*X", "o0OWB", "qrln", "[e4][!P][N&@b1]", "{ke}{K!1}{kh8}", "[N&x&!P][e4&+]"] {
+            let steno_constraints = parse_steno_string(steno).unwrap();
+            assert_eq!(steno_string(&steno_constraints), steno);
+            assert_eq!(parse_steno_string(&steno_string(&steno_constraints)).unwrap(), steno_constraints);
+        }
+        // `&` is optional in brackets, and written out after.
+        assert_eq!(steno_string(&parse_steno_string("[Nx!P]").unwrap()), "[N&x&!P]");
+        assert!(parse_steno_string("[e4").is_err());
+        assert!(parse_steno_string("~j").is_err());
+    }
+}
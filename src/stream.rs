@@ -0,0 +1,145 @@
+use chess::Board;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+use crate::search::{solve_with_callback, Solution, SolveOptions};
+use crate::stats::SearchStats;
+use crate::steno::Constraint;
+
+// Solutions found but not yet taken from the stream; past this the search
+// waits for the consumer.
+const STREAM_BUFFER: usize = 1024;
+
+// Stops a search once cancelled. Clones share the flag, so one can be kept
+// while another is handed to the stream.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// A steno to solve, with the options to solve it with.
+#[derive(Clone, Debug)]
+pub struct Solver {
+    pub board: Board,
+    pub steno_constraints: Vec<Constraint>,
+    pub options: SolveOptions,
+}
+
+impl Solver {
+    pub fn new(board: Board, steno_constraints: Vec<Constraint>) -> Solver {
+        Solver {
+            board,
+            steno_constraints,
+            options: SolveOptions {
+                print_solutions: false,
+                ..SolveOptions::default()
+            },
+        }
+    }
+
+    // Solves on a thread of its own, the workers still searching on rayon's
+    // pool, so no executor thread is ever blocked and any runtime can poll
+    // the stream. Cancelling the token or dropping the stream stops the
+    // search within a node per worker.
+    pub fn solve_stream(&self, token: CancellationToken) -> SolutionStream {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(StreamState { solutions: VecDeque::new(), stats: None, waker: None }),
+            taken: Condvar::new(),
+        });
+        let solver = self.clone();
+        let (thread_shared, thread_token) = (shared.clone(), token.clone());
+        thread::spawn(move || {
+            let options = SolveOptions {
+                print_solutions: false,
+                cancel: Some(thread_token.0.clone()),
+                ..solver.options
+            };
+            let stats = solve_with_callback(solver.board, &solver.steno_constraints, &options, &|path| {
+                let mut state = thread_shared.state.lock().unwrap();
+                while state.solutions.len() >= STREAM_BUFFER && !thread_token.is_cancelled() {
+                    // Timed, since a cancelled token doesn't notify.
+                    state = thread_shared.taken.wait_timeout(state, Duration::from_millis(50)).unwrap().0;
+                }
+                if thread_token.is_cancelled() {
+                    return;
+                }
+                state.solutions.push_back(Solution { moves: path.to_vec() });
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+            let mut state = thread_shared.state.lock().unwrap();
+            state.stats = Some(stats);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        SolutionStream { shared, token }
+    }
+}
+
+struct StreamState {
+    solutions: VecDeque<Solution>,
+    // Set once the search is over.
+    stats: Option<SearchStats>,
+    waker: Option<Waker>,
+}
+
+struct Shared {
+    state: Mutex<StreamState>,
+    taken: Condvar,
+}
+
+pub struct SolutionStream {
+    shared: Arc<Shared>,
+    token: CancellationToken,
+}
+
+impl SolutionStream {
+    // The search's statistics, once the stream has ended.
+    pub fn stats(&self) -> Option<SearchStats> {
+        self.shared.state.lock().unwrap().stats.clone()
+    }
+}
+
+impl Stream for SolutionStream {
+    type Item = Solution;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Solution>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(solution) = state.solutions.pop_front() {
+            self.shared.taken.notify_one();
+            return Poll::Ready(Some(solution));
+        }
+        if state.stats.is_some() {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for SolutionStream {
+    fn drop(&mut self) {
+        self.token.cancel();
+        self.shared.taken.notify_one();
+    }
+}
@@ -0,0 +1,161 @@
+use chess::{Board, ChessMove};
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::report::BranchingReport;
+use crate::search::{count_solutions, solve_with_callback, SolveOptions};
+use crate::steno::{Constraint, MoveContext};
+
+// Every built-in character but `~`, tried at each ply.
+const CANDIDATES: &str = "abcdefgh12345678KQRLNPx%+^#=o0OWBqrln";
+
+// Solutions kept in memory to test the candidates against.
+pub const MAX_SUGGEST_SOLUTIONS: usize = 100_000;
+
+// Plies to change, each with the constraint to put there, and the steno
+// that results.
+#[derive(Clone, Debug)]
+pub struct Suggestion {
+    pub changes: Vec<(usize, Constraint)>,
+    pub steno_constraints: Vec<Constraint>,
+}
+
+// One candidate change and, as a bitset, which of the known solutions still
+// match with it.
+struct Change {
+    ply: usize,
+    constraint: Constraint,
+    kept: Vec<u64>,
+}
+
+fn popcount(bits: &[u64]) -> u32 {
+    bits.iter().map(|word| word.count_ones()).sum()
+}
+
+fn solutions(board: Board, halfmove_clock: u32, steno_constraints: &[Constraint]) -> Result<Vec<Vec<ChessMove>>, String> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let solutions = Mutex::new(Vec::new());
+    let options = SolveOptions {
+        print_solutions: false,
+        cancel: Some(cancel.clone()),
+        halfmove_clock,
+        ..SolveOptions::default()
+    };
+    solve_with_callback(board, steno_constraints, &options, &|path| {
+        let mut solutions = solutions.lock().unwrap();
+        if solutions.len() > MAX_SUGGEST_SOLUTIONS {
+            cancel.store(true, Ordering::Relaxed);
+            return;
+        }
+        solutions.push(path.to_vec());
+    });
+    let solutions = solutions.into_inner().unwrap();
+    if solutions.len() > MAX_SUGGEST_SOLUTIONS {
+        return Err(format!("The steno has more than {} solutions; tighten it by hand first", MAX_SUGGEST_SOLUTIONS));
+    }
+    Ok(solutions)
+}
+
+// Proposes the fewest ply changes, at most `max_changes`, that make the
+// steno unique. Each change puts a built-in character at one ply. Candidates
+// are first tested against the known solutions: a variant can only be a
+// tightening when exactly one of them still matches it. Those variants are
+// then solved again, since a character that doesn't imply the one it replaces
+// may let in new games. Plies where the solutions branch the most are tried
+// first. Returns no suggestions when the steno is already unique.
+pub fn suggest_unique(board: Board, halfmove_clock: u32, steno_constraints: &[Constraint], max_changes: usize, max_suggestions: usize) -> Result<Vec<Suggestion>, String> {
+    let solutions = solutions(board, halfmove_clock, steno_constraints)?;
+    match solutions.len() {
+        0 => return Err("The steno has no solutions".to_string()),
+        1 => return Ok(Vec::new()),
+        _ => {}
+    }
+
+    let report = BranchingReport::new(steno_constraints.len());
+    for path in &solutions {
+        report.record(path);
+    }
+    let distinct = report.distinct_moves();
+    let mut plies: Vec<usize> = (0..steno_constraints.len()).filter(|&ply| distinct[ply] > 1).collect();
+    plies.sort_by_key(|&ply| (Reverse(distinct[ply]), ply));
+
+    let words = solutions.len().div_ceil(64);
+    let mut changes: Vec<Change> = plies.iter().flat_map(|&ply| {
+        CANDIDATES.chars().filter_map(Constraint::from_char).filter(move |constraint| *constraint != steno_constraints[ply]).map(move |constraint| Change {
+            ply,
+            constraint,
+            kept: vec![0; words],
+        })
+    }).collect();
+    for (index, path) in solutions.iter().enumerate() {
+        let mut positions = vec![board];
+        for &mov in path {
+            positions.push(positions.last().unwrap().make_move_new(mov));
+        }
+        for change in &mut changes {
+            let context = MoveContext::new(&positions[change.ply], path[change.ply], &positions[change.ply + 1]);
+            if change.constraint.matches(&context) {
+                change.kept[index / 64] |= 1 << (index % 64);
+            }
+        }
+    }
+    changes.retain(|change| (1..solutions.len() as u32).contains(&popcount(&change.kept)));
+
+    for size in 1..=max_changes {
+        let mut combinations = Combinations { board, halfmove_clock, steno_constraints, changes: &changes, size, chosen: Vec::new(), suggestions: Vec::new(), max_suggestions };
+        combinations.find(0, &vec![u64::MAX; words]);
+        if !combinations.suggestions.is_empty() {
+            return Ok(combinations.suggestions);
+        }
+    }
+    Err(format!("No way to make the steno unique with at most {} changes", max_changes))
+}
+
+struct Combinations<'a> {
+    board: Board,
+    halfmove_clock: u32,
+    steno_constraints: &'a [Constraint],
+    changes: &'a [Change],
+    size: usize,
+    chosen: Vec<usize>,
+    suggestions: Vec<Suggestion>,
+    max_suggestions: usize,
+}
+
+impl Combinations<'_> {
+    // Depth-first over combinations of `size` changes at distinct plies,
+    // adding changes from `start` on to the chosen ones.
+    fn find(&mut self, start: usize, kept: &[u64]) {
+        let changes = self.changes;
+        for (index, change) in changes.iter().enumerate().skip(start) {
+            if self.suggestions.len() >= self.max_suggestions {
+                return;
+            }
+            if self.chosen.iter().any(|&other| changes[other].ply == change.ply) {
+                continue;
+            }
+            let kept: Vec<u64> = kept.iter().zip(&change.kept).map(|(a, b)| a & b).collect();
+            let count = popcount(&kept);
+            if count == 0 || (count == 1) != (self.chosen.len() + 1 == self.size) {
+                continue;
+            }
+
+            self.chosen.push(index);
+            if self.chosen.len() == self.size {
+                let mut variant = self.steno_constraints.to_vec();
+                for &chosen in &self.chosen {
+                    variant[changes[chosen].ply] = changes[chosen].constraint.clone();
+                }
+                if count_solutions(self.board, self.halfmove_clock, &variant, Some(2)) == 1 {
+                    let mut changes: Vec<(usize, Constraint)> = self.chosen.iter().map(|&chosen| (changes[chosen].ply, changes[chosen].constraint.clone())).collect();
+                    changes.sort_by_key(|&(ply, _)| ply);
+                    self.suggestions.push(Suggestion { changes, steno_constraints: variant });
+                }
+            } else {
+                self.find(index + 1, &kept);
+            }
+            self.chosen.pop();
+        }
+    }
+}
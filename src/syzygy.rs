@@ -0,0 +1,84 @@
+use chess::Board;
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess};
+use shakmaty_syzygy::{Tablebase, Wdl};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::steno::Constraint;
+
+// The tables every search probes, loaded once for the whole process.
+static TABLEBASE: OnceLock<Tablebase<Chess>> = OnceLock::new();
+
+// What the tables say best play from a position gets the side that just
+// moved into it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TablebaseOutcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+// Loads the Syzygy tables in `paths` for `$` and `_` to probe, returning how
+// many were found. Tables can only be loaded once.
+pub fn load_syzygy(paths: &[PathBuf]) -> Result<usize, String> {
+    let mut tablebase = Tablebase::new();
+    let mut tables = 0;
+    for path in paths {
+        tables += tablebase.add_directory(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+    TABLEBASE.set(tablebase).map_err(|_| "Syzygy tables are already loaded".to_string())?;
+    Ok(tables)
+}
+
+pub fn syzygy_loaded() -> bool {
+    TABLEBASE.get().is_some()
+}
+
+// Whether a ply of the steno asks the tables.
+pub fn probes_tablebase(steno_constraints: &[Constraint]) -> bool {
+    steno_constraints.iter().any(|constraint| constraint.requires(&|part| matches!(part, Constraint::Tablebase(_))))
+}
+
+// The tables' verdict with the fifty-move counter reset, which a cursed win
+// or a blessed loss still counts as a draw under. None when no tables are
+// loaded, or when they don't cover the position: more pieces than they
+// have, or castling rights left.
+pub(crate) fn probe_position(position: &Chess) -> Option<TablebaseOutcome> {
+    // The side to move's result, so the other way round for the mover.
+    Some(match TABLEBASE.get()?.probe_wdl_after_zeroing(position).ok()? {
+        Wdl::Loss => TablebaseOutcome::Win,
+        Wdl::BlessedLoss | Wdl::Draw | Wdl::CursedWin => TablebaseOutcome::Draw,
+        Wdl::Win => TablebaseOutcome::Loss,
+    })
+}
+
+pub(crate) fn probe(board: &Board) -> Option<TablebaseOutcome> {
+    let fen: Fen = board.to_string().parse().ok()?;
+    probe_position(&fen.into_position(CastlingMode::Standard).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steno::{parse_steno_string, steno_string};
+    use std::str::FromStr;
+
+    #[test]
+    fn characters_round_trip() {
+        let steno_constraints = parse_steno_string("~$[Qx$]_").unwrap();
+        assert_eq!(steno_constraints[1], Constraint::Tablebase(TablebaseOutcome::Win));
+        assert_eq!(steno_constraints[3], Constraint::Tablebase(TablebaseOutcome::Draw));
+        assert_eq!(steno_string(&steno_constraints), "~$[Q&x&$]_");
+        assert!(probes_tablebase(&steno_constraints));
+        assert!(!probes_tablebase(&parse_steno_string("~~x#").unwrap()));
+    }
+
+    // Nothing is loaded in the tests, so no position is known to the tables.
+    #[test]
+    fn unloaded_tables_answer_nothing() {
+        assert!(!syzygy_loaded());
+        let board = Board::from_str("8/8/8/8/8/2k5/8/KQ6 w - - 0 1").unwrap();
+        assert_eq!(probe(&board), None);
+    }
+}
@@ -0,0 +1,100 @@
+use chess::{Board, Color, Piece, ALL_COLORS};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetMatch {
+    // Same pieces on the same squares, side to move, castling and en passant rights.
+    #[default]
+    Exact,
+    // Same pieces on the same squares; flags are ignored.
+    Pieces,
+}
+
+impl FromStr for TargetMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(TargetMatch::Exact),
+            "pieces" => Ok(TargetMatch::Pieces),
+            _ => Err(format!("Unknown final position match: {} (expected exact or pieces)", s)),
+        }
+    }
+}
+
+// The position a solution has to end in, for proof games with steno constraints.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TargetPosition {
+    #[serde(rename = "fen", with = "fen")]
+    pub board: Board,
+    pub mode: TargetMatch,
+}
+
+// A board as its FEN.
+mod fen {
+    use chess::Board;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    pub fn serialize<S: Serializer>(board: &Board, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(board)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Board, D::Error> {
+        let fen = String::deserialize(deserializer)?;
+        Board::from_str(&fen).map_err(|err| D::Error::custom(format!("Invalid FEN: {}", err)))
+    }
+}
+
+const PROMOTION_PIECES: [Piece; 4] = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+fn count(board: &Board, color: Color, piece: Piece) -> usize {
+    (board.pieces(piece) & board.color_combined(color)).popcnt() as usize
+}
+
+impl TargetPosition {
+    pub fn new(board: Board, mode: TargetMatch) -> TargetPosition {
+        TargetPosition { board, mode }
+    }
+
+    pub(crate) fn matches(&self, board: &Board) -> bool {
+        match self.mode {
+            TargetMatch::Exact => *board == self.board,
+            TargetMatch::Pieces => {
+                board.color_combined(Color::White) == self.board.color_combined(Color::White)
+                    && board.color_combined(Color::Black) == self.board.color_combined(Color::Black)
+                    && chess::ALL_PIECES.iter().all(|&piece| board.pieces(piece) == self.board.pieces(piece))
+            }
+        }
+    }
+
+    // A cheap material bound: false when the target can't be reached from
+    // `board` in `plies_left` plies. Pieces are never created, pawns only turn
+    // into other pieces, and a side's pieces can only be captured on the
+    // other side's moves.
+    pub(crate) fn reachable(&self, board: &Board, plies_left: usize) -> bool {
+        let to_move = board.side_to_move();
+        let moves_left = |color: Color| if color == to_move { plies_left.div_ceil(2) } else { plies_left / 2 };
+
+        ALL_COLORS.iter().all(|&color| {
+            let pawns = count(board, color, Piece::Pawn);
+            let target_pawns = count(&self.board, color, Piece::Pawn);
+            if pawns < target_pawns {
+                return false;
+            }
+
+            let promotions = PROMOTION_PIECES.iter()
+                .map(|&piece| count(&self.board, color, piece).saturating_sub(count(board, color, piece)))
+                .sum::<usize>();
+            if promotions > pawns - target_pawns || promotions > moves_left(color) {
+                return false;
+            }
+
+            let pieces = board.color_combined(color).popcnt() as usize;
+            let target_pieces = self.board.color_combined(color).popcnt() as usize;
+            pieces >= target_pieces && pieces - target_pieces <= moves_left(!color)
+        })
+    }
+}
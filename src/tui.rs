@@ -0,0 +1,158 @@
+use chess::{Board, ChessMove, Color as Side, File, Piece, Rank, Square};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+use std::io;
+
+use crate::render::san_moves;
+
+struct Explorer {
+    start: Board,
+    solutions: Vec<Vec<ChessMove>>,
+    san: Vec<Vec<String>>,
+    list_state: ListState,
+    ply: usize,
+}
+
+impl Explorer {
+    fn selected(&self) -> usize {
+        self.list_state.selected().unwrap_or(0)
+    }
+
+    fn select(&mut self, index: usize) {
+        if !self.solutions.is_empty() {
+            self.list_state.select(Some(index.min(self.solutions.len() - 1)));
+            self.ply = self.ply.min(self.solutions[self.selected()].len());
+        }
+    }
+
+    fn board_at_ply(&self) -> (Board, Option<ChessMove>) {
+        let mut board = self.start;
+        let mut last_move = None;
+        if let Some(path) = self.solutions.get(self.selected()) {
+            for &mov in &path[..self.ply] {
+                board = board.make_move_new(mov);
+                last_move = Some(mov);
+            }
+        }
+        (board, last_move)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(frame.area());
+
+        let items: Vec<ListItem> = self.san.iter().enumerate()
+            .map(|(index, moves)| ListItem::new(format!("{:>5}. {}", index + 1, moves.join(" "))))
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(format!("Solutions ({})", self.solutions.len())))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, columns[0], &mut self.list_state);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(11), Constraint::Min(3), Constraint::Length(3)])
+            .split(columns[1]);
+
+        let (board, last_move) = self.board_at_ply();
+        let title = format!("Ply {}/{}", self.ply, self.solutions.get(self.selected()).map_or(0, Vec::len));
+        frame.render_widget(Paragraph::new(board_lines(&board, last_move)).block(Block::default().borders(Borders::ALL).title(title)), rows[0]);
+
+        let moves: Vec<Span> = self.san.get(self.selected()).map_or(Vec::new(), |moves| {
+            moves.iter().enumerate().map(|(index, san)| {
+                let style = if index + 1 == self.ply { Style::default().add_modifier(Modifier::REVERSED) } else { Style::default() };
+                Span::styled(format!("{} ", san), style)
+            }).collect()
+        });
+        frame.render_widget(Paragraph::new(Line::from(moves)).wrap(Wrap { trim: true }).block(Block::default().borders(Borders::ALL).title("Moves")), rows[1]);
+
+        let help = "↑/↓ solution  ←/→ ply  Home/End first/last ply  q quit";
+        frame.render_widget(Paragraph::new(help).block(Block::default().borders(Borders::ALL)), rows[2]);
+    }
+
+    // Returns false once the user asks to quit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        let length = self.solutions.get(self.selected()).map_or(0, Vec::len);
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Down | KeyCode::Char('j') => self.select(self.selected() + 1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(self.selected().saturating_sub(1)),
+            KeyCode::PageDown => self.select(self.selected() + 20),
+            KeyCode::PageUp => self.select(self.selected().saturating_sub(20)),
+            KeyCode::Right | KeyCode::Char('l') => self.ply = (self.ply + 1).min(length),
+            KeyCode::Left | KeyCode::Char('h') => self.ply = self.ply.saturating_sub(1),
+            KeyCode::Home => self.ply = 0,
+            KeyCode::End => self.ply = length,
+            _ => {}
+        }
+        true
+    }
+}
+
+fn piece_glyph(piece: Piece) -> char {
+    match piece {
+        Piece::King => '♚',
+        Piece::Queen => '♛',
+        Piece::Rook => '♜',
+        Piece::Bishop => '♝',
+        Piece::Knight => '♞',
+        Piece::Pawn => '♟',
+    }
+}
+
+fn board_lines(board: &Board, last_move: Option<ChessMove>) -> Vec<Line<'static>> {
+    let highlighted = |square: Square| last_move.is_some_and(|mov| mov.get_source() == square || mov.get_dest() == square);
+
+    let mut lines = Vec::new();
+    for rank in (0..8).rev() {
+        let mut spans = vec![Span::raw(format!("{} ", rank + 1))];
+        for file in 0..8 {
+            let square = Square::make_square(Rank::from_index(rank), File::from_index(file));
+            let background = match ((rank + file) % 2 == 1, highlighted(square)) {
+                (true, false) => Color::Rgb(240, 217, 181),
+                (false, false) => Color::Rgb(181, 136, 99),
+                (true, true) => Color::Rgb(205, 210, 106),
+                (false, true) => Color::Rgb(170, 162, 58),
+            };
+            let (glyph, foreground) = match (board.piece_on(square), board.color_on(square)) {
+                (Some(piece), Some(Side::White)) => (piece_glyph(piece), Color::White),
+                (Some(piece), Some(Side::Black)) => (piece_glyph(piece), Color::Black),
+                _ => (' ', Color::Reset),
+            };
+            spans.push(Span::styled(format!(" {} ", glyph), Style::default().fg(foreground).bg(background)));
+        }
+        lines.push(Line::from(spans));
+    }
+    lines.push(Line::from("   a  b  c  d  e  f  g  h"));
+    lines
+}
+
+pub fn explore(board: Board, fen_string: &Option<String>, solutions: Vec<Vec<ChessMove>>) -> io::Result<()> {
+    let san = solutions.iter().map(|path| san_moves(fen_string, path)).collect();
+    let mut explorer = Explorer {
+        start: board,
+        solutions,
+        san,
+        list_state: ListState::default().with_selected(Some(0)),
+        ply: 0,
+    };
+    explorer.ply = explorer.solutions.first().map_or(0, Vec::len);
+
+    let mut terminal = ratatui::init();
+    let result = (|| loop {
+        terminal.draw(|frame| explorer.draw(frame))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !explorer.handle_key(key.code) {
+                return Ok(());
+            }
+        }
+    })();
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,110 @@
+use chess::Board;
+use js_sys::Function;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+use crate::render::{render_solution, OutputFormat};
+use crate::search::{solve_with_callback, SolveOptions};
+use crate::steno::{halfmove_clock, parse_steno_string};
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct JsSolveOptions {
+    fen: Option<String>,
+    format: Option<String>,
+    limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct JsSolveSummary {
+    solutions: u64,
+    nodes_visited: u64,
+    elapsed_ms: f64,
+}
+
+// The JS callback, and the first exception it threw.
+struct SolutionCallback {
+    function: Function,
+    error: RefCell<Option<JsValue>>,
+}
+
+// SAFETY: the search needs a `Sync` callback because it may run on rayon
+// workers, but the wasm build has no `parallel` feature and
+// wasm32-unknown-unknown runs on a single thread, so the JS function is only
+// ever called from the thread that passed it in, and `error` only borrowed
+// there.
+unsafe impl Sync for SolutionCallback {}
+
+impl SolutionCallback {
+    // Whether to keep going; not after it threw.
+    fn call(&self, solution: &JsValue) -> bool {
+        match self.function.call1(&JsValue::NULL, solution) {
+            Ok(result) => result != JsValue::FALSE,
+            Err(err) => {
+                *self.error.borrow_mut() = Some(err);
+                false
+            }
+        }
+    }
+}
+
+// Solves `steno` and calls `on_solution(solution)` for every solution found,
+// rendered in `options.format` ("url", "san" or "uci"; only "uci", the
+// default, without the san feature). The search stops early once
+// `options.limit` solutions were reported or the callback returns `false`.
+// Returns a summary `{ solutions, nodes_visited, elapsed_ms }`, or what the
+// callback threw, if it did.
+#[wasm_bindgen]
+pub fn solve(steno: &str, options: JsValue, on_solution: Function) -> Result<JsValue, JsValue> {
+    let options: JsSolveOptions = if options.is_undefined() || options.is_null() {
+        JsSolveOptions::default()
+    } else {
+        serde_wasm_bindgen::from_value(options)?
+    };
+
+    let steno_constraints = parse_steno_string(steno).map_err(|err| JsValue::from_str(&err))?;
+    let board = match &options.fen {
+        Some(fen) => Board::from_str(fen).map_err(|err| JsValue::from_str(&format!("Invalid FEN: {}", err)))?,
+        None => Board::default(),
+    };
+    let format = match &options.format {
+        Some(format) => format.parse().map_err(|err: String| JsValue::from_str(&err))?,
+        None => OutputFormat::default(),
+    };
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let reported = AtomicU64::new(0);
+    let callback = SolutionCallback { function: on_solution, error: RefCell::new(None) };
+    let solve_options = SolveOptions {
+        print_solutions: false,
+        cancel: Some(cancel.clone()),
+        halfmove_clock: halfmove_clock(&options.fen),
+        ..SolveOptions::default()
+    };
+
+    let stats = solve_with_callback(board, &steno_constraints, &solve_options, &|path| {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let solution = JsValue::from_str(&render_solution(&options.fen, path, format));
+        let keep_going = callback.call(&solution);
+        let reported = reported.fetch_add(1, Ordering::Relaxed) + 1;
+        if !keep_going || options.limit.is_some_and(|limit| reported >= limit) {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    });
+
+    if let Some(err) = callback.error.into_inner() {
+        return Err(err);
+    }
+    let summary = JsSolveSummary {
+        solutions: reported.load(Ordering::Relaxed),
+        nodes_visited: stats.nodes_visited,
+        elapsed_ms: stats.elapsed.as_secs_f64() * 1000.0,
+    };
+    Ok(serde_wasm_bindgen::to_value(&summary)?)
+}
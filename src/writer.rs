@@ -0,0 +1,151 @@
+use std::io::{self, Write};
+use std::iter;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const BATCH_SIZE: usize = 1 << 16;
+const CHANNEL_CAPACITY: usize = 16;
+// Whatever is pending gets written out when no full batch arrives for this
+// long, so slow searches still show solutions as they're found.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Every sink gets every byte, in order. A sink that fails is dropped and the
+// rest keep going, so a closed stdout doesn't cut the --tee files short; the
+// write only fails once every sink has. The first error is kept for
+// take_error().
+pub struct Tee {
+    sinks: Vec<Box<dyn Write + Send>>,
+    error: Option<io::Error>,
+}
+
+impl Tee {
+    pub fn new(sinks: Vec<Box<dyn Write + Send>>) -> Tee {
+        Tee { sinks, error: None }
+    }
+
+    // The first error a sink failed with, if any did.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    fn each(&mut self, mut apply: impl FnMut(&mut Box<dyn Write + Send>) -> io::Result<()>) -> io::Result<()> {
+        let mut failed = None;
+        self.sinks.retain_mut(|sink| match apply(sink) {
+            Ok(()) => true,
+            Err(err) => {
+                failed.get_or_insert(err);
+                false
+            }
+        });
+        if self.error.is_none() {
+            self.error = failed;
+        }
+        if self.sinks.is_empty() {
+            return self.error.take().map_or(Ok(()), Err);
+        }
+        Ok(())
+    }
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.each(|sink| sink.write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.each(|sink| sink.flush())
+    }
+}
+
+// Owns the output stream on a dedicated thread. Workers only append to a
+// shared batch; full batches go over a bounded channel, so nobody holds a
+// stdout lock or makes a syscall per leaf.
+pub struct SolutionWriter {
+    pending: Arc<Mutex<String>>,
+    sender: SyncSender<String>,
+    handle: JoinHandle<io::Result<()>>,
+    failed: Arc<AtomicBool>,
+}
+
+impl SolutionWriter {
+    pub fn new<W: Write + Send + 'static>(output: W) -> SolutionWriter {
+        SolutionWriter::spawn(output, |_| Ok(()))
+    }
+
+    // `done` runs once everything has been written and flushed, for an error
+    // the output held back until then.
+    fn spawn<W: Write + Send + 'static>(mut output: W, done: impl FnOnce(W) -> io::Result<()> + Send + 'static) -> SolutionWriter {
+        let pending = Arc::new(Mutex::new(String::with_capacity(BATCH_SIZE)));
+        let (sender, receiver) = mpsc::sync_channel::<String>(CHANNEL_CAPACITY);
+
+        let (thread_pending, failed) = (pending.clone(), Arc::new(AtomicBool::new(false)));
+        let thread_failed = failed.clone();
+        let handle = thread::spawn(move || {
+            let written = (|| {
+                loop {
+                    match receiver.recv_timeout(FLUSH_INTERVAL) {
+                        Ok(batch) => output.write_all(batch.as_bytes())?,
+                        Err(RecvTimeoutError::Timeout) => {
+                            let batch = mem::take(&mut *thread_pending.lock().unwrap());
+                            output.write_all(batch.as_bytes())?;
+                            output.flush()?;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                let batch = mem::take(&mut *thread_pending.lock().unwrap());
+                output.write_all(batch.as_bytes())?;
+                output.flush()
+            })();
+            let written = written.and_then(|()| done(output));
+            thread_failed.store(written.is_err(), Ordering::Relaxed);
+            written
+        });
+
+        SolutionWriter { pending, sender, handle, failed }
+    }
+
+    pub fn stdout() -> SolutionWriter {
+        SolutionWriter::new(io::stdout())
+    }
+
+    // Standard output and each of `sinks`, all fed from the one thread. The
+    // files get everything even if stdout closes early; finish() still
+    // reports that stdout did.
+    pub fn stdout_and(sinks: Vec<Box<dyn Write + Send>>) -> SolutionWriter {
+        if sinks.is_empty() {
+            return SolutionWriter::stdout();
+        }
+        let stdout: Box<dyn Write + Send> = Box::new(io::stdout());
+        SolutionWriter::spawn(Tee::new(iter::once(stdout).chain(sinks).collect()), |mut tee| tee.take_error().map_or(Ok(()), Err))
+    }
+
+    // Blocks while the channel is full. Text written after an output error is
+    // dropped; the error itself comes back from finish().
+    pub fn write(&self, text: &str) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.push_str(text);
+        if pending.len() >= BATCH_SIZE {
+            let batch = mem::replace(&mut *pending, String::with_capacity(BATCH_SIZE + text.len()));
+            drop(pending);
+            let _ = self.sender.send(batch);
+        }
+    }
+
+    // Whether the output has failed, say because the reader of a pipe went
+    // away, so a search can stop rather than render what nobody will read.
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    // Waits for everything written so far to reach the output and be flushed.
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.sender);
+        self.handle.join().unwrap_or_else(|_| Err(io::Error::other("writer thread panicked")))
+    }
+}
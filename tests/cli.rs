@@ -0,0 +1,54 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+// White in check, so it can't be Black's move.
+const WHITE_IN_CHECK: &str = "4k3/8/8/8/8/8/8/4K2r w - - 0 1";
+
+fn run(args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_steno_solver")).args(args).output().unwrap();
+    (output.status.code().unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+// A --start-ply that puts the wrong side to move is a bad argument, as an
+// invalid FEN is.
+#[test]
+fn start_ply_with_the_wrong_side_to_move() {
+    for command in [&[][..], &["lint"], &["explain"]] {
+        let args: Vec<&str> = command.iter().copied().chain(["--fen", WHITE_IN_CHECK, "--start-ply", "2", "~"]).collect();
+        let (code, stderr) = run(&args);
+        assert_eq!(code, 2, "{:?}: {}", args, stderr);
+        assert!(stderr.starts_with("--start-ply 2 puts Black to move"), "{:?}: {}", args, stderr);
+    }
+    assert_eq!(run(&["-q", "--fen", WHITE_IN_CHECK, "--start-ply", "3", "K"]).0, 0);
+}
+
+// The --tee file gets every solution even when the reader of stdout goes
+// away after the first, as `| head -1` does.
+#[test]
+fn tee_outlives_a_closed_stdout() {
+    let tee = std::env::temp_dir().join(format!("steno_solver_tee_{}.txt", std::process::id()));
+    let mut child = Command::new(env!("CARGO_BIN_EXE_steno_solver"))
+        .args(["--format", "uci", "--tee", tee.to_str().unwrap(), "~~~~"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut first = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut first).unwrap();
+    assert!(!first.is_empty());
+    child.wait().unwrap();
+    let written = fs::read_to_string(&tee).unwrap();
+    fs::remove_file(&tee).unwrap();
+    assert_eq!(written.lines().filter(|line| line.split(' ').count() == 4).count(), 197281);
+}
+
+// A game from a FEN starts from its halfmove clock, so its fifty-move plies
+// check as the search found them.
+#[test]
+fn verify_from_the_fens_clock() {
+    let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 98 1";
+    let output = Command::new(env!("CARGO_BIN_EXE_steno_solver")).args(["verify", "~/", "--fen", fen, "--moves", "Nf3 Nf6"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "The game matches the steno\nThe steno has other solutions too\n");
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "server")]
+
+use chess::Board;
+use std::net::TcpListener;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+use steno_solver::{coordinate, parse_steno_string, solve_with_callback, work, CoordinatorConfig, SolveOptions};
+
+fn direct_count(steno: &str, fen: Option<&str>) -> u64 {
+    let board = fen.map_or(Board::default(), |fen| Board::from_str(fen).unwrap());
+    let found = AtomicU64::new(0);
+    let options = SolveOptions {
+        print_solutions: false,
+        halfmove_clock: fen.and_then(|fen| fen.split_whitespace().nth(4)?.parse().ok()).unwrap_or(0),
+        ..SolveOptions::default()
+    };
+    solve_with_callback(board, &parse_steno_string(steno).unwrap(), &options, &|_| {
+        found.fetch_add(1, Ordering::Relaxed);
+    });
+    found.into_inner()
+}
+
+// One coordinator and one worker on a free local port.
+fn cluster_count(steno: &str, fen: Option<&str>, task_ply: usize) -> u64 {
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let config = CoordinatorConfig {
+        addr: addr.clone(),
+        steno: steno.to_string(),
+        fen: fen.map(str::to_string),
+        task_ply,
+        lease: Duration::from_secs(10),
+    };
+    let coordinator = thread::spawn(move || coordinate(&config));
+    // Until the coordinator listens, the worker can't connect.
+    let mut worked = Err(String::new());
+    for _ in 0..100 {
+        worked = work(&addr, "test");
+        if worked.is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    worked.unwrap();
+    coordinator.join().unwrap().unwrap().solutions.len() as u64
+}
+
+#[test]
+fn recapture_after_the_task_prefix() {
+    assert_eq!(direct_count("~~xX", None), 23);
+    assert_eq!(cluster_count("~~xX", None, 3), 23);
+}
+
+#[test]
+fn constraints_on_earlier_plies_and_the_halfmove_clock() {
+    assert_eq!(cluster_count("N~*~*", None, 2), direct_count("N~*~*", None));
+    let fen = "4k3/8/8/8/8/8/8/4K2R w K - 98 60";
+    assert_eq!(cluster_count("~/", Some(fen), 1), direct_count("~/", Some(fen)));
+}
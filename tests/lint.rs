@@ -0,0 +1,50 @@
+use chess::Board;
+use steno_solver::{count_solutions, lint_steno, parse_steno_string, solve_with_callback, verify_game, SolveOptions};
+
+fn warnings(halfmove_clock: u32, steno: &str) -> Vec<String> {
+    lint_steno(&Board::default(), halfmove_clock, &parse_steno_string(steno).unwrap()).warnings
+}
+
+fn count(steno: &str) -> u64 {
+    count_solutions(Board::default(), 0, &parse_steno_string(steno).unwrap(), None)
+}
+
+// Whose move a ply is decides where it can castle and take en passant.
+#[test]
+fn parity_impossible_stenos() {
+    // 1. e4 a6 2. e5 d5 3. exd6 takes on the sixth rank, and Black can only
+    // take on the third, as in 1. a3 e5 2. a4 e4 3. d4 exd3.
+    assert!(warnings(0, "[e4]~[e5][d5][%6]").is_empty());
+    assert!(count("[e4]~[e5][d5][%6]") > 0);
+    assert!(warnings(0, "~[e5]~[e4][d4][%3]").is_empty());
+    assert!(count("~[e5]~[e4][d4][%3]") > 0);
+    let reasons = warnings(0, "~[e5]~[e4][d4][%6]");
+    assert_eq!(reasons.len(), 1, "{:?}", reasons);
+    assert!(reasons[0].contains("Black move"), "{}", reasons[0]);
+    assert_eq!(count("~[e5]~[e4][d4][%6]"), 0);
+
+    // White castles on odd plies only.
+    assert!(warnings(0, "~~~~~~W").is_empty());
+    assert!(!warnings(0, "~~~~~~~W").is_empty());
+    assert!(warnings(0, "~~~~~~~B").is_empty());
+    assert!(!warnings(0, "~~~~~~B").is_empty());
+}
+
+// The initial position with moves behind it, the knights out and back, is
+// no longer the start of the game: its clock can reach fifty moves at once.
+#[test]
+fn initial_position_reached_again() {
+    assert!(!warnings(0, "N/").is_empty());
+    assert!(warnings(98, "N/").is_empty());
+    assert!(!warnings(90, "N/").is_empty());
+    let options = SolveOptions { print_solutions: false, halfmove_clock: 98, ..SolveOptions::default() };
+    // Four knight moves, each answered by one of Black's four.
+    assert_eq!(solve_with_callback(Board::default(), &parse_steno_string("N/").unwrap(), &options, &|_| {}).solutions, 16);
+    assert_eq!(count_solutions(Board::default(), 98, &parse_steno_string("N/").unwrap(), None), 16);
+    let knights = ["g1f3", "g8f6"].map(|uci| uci.parse().unwrap());
+    assert!(verify_game(Board::default(), 98, &parse_steno_string("N/").unwrap(), &knights).is_ok());
+    assert!(verify_game(Board::default(), 0, &parse_steno_string("N/").unwrap(), &knights).is_err());
+    // The bounds that only depend on the position still hold.
+    assert!(!warnings(4, "x").is_empty());
+    assert!(!warnings(4, "~~~~o").is_empty());
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "parallel")]
+
+use chess::Board;
+use rayon::ThreadPoolBuilder;
+use std::process::Command;
+use steno_solver::{default_split_ply, parse_steno_string, solve_two_stage, solve_with_callback, CountBy, DistinctCounter, SolutionDigest, SolveOptions};
+
+// The solutions, their digest and the distinct final positions, found on
+// `threads` workers.
+fn solve_on(threads: usize, steno: &str, two_stage: bool) -> (u64, u64, u64) {
+    let board = Board::default();
+    let steno_constraints = parse_steno_string(steno).unwrap();
+    let pool = ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    pool.install(|| {
+        let digest = SolutionDigest::new();
+        let positions = DistinctCounter::new(CountBy::Positions);
+        let options = SolveOptions {
+            print_solutions: false,
+            ..SolveOptions::default()
+        };
+        let on_solution = |path: &[_]| {
+            digest.record(path);
+            positions.record(board, path).unwrap();
+        };
+        if two_stage {
+            solve_two_stage(board, &steno_constraints, default_split_ply(&steno_constraints), &options, &on_solution);
+        } else {
+            solve_with_callback(board, &steno_constraints, &options, &on_solution);
+        }
+        (digest.solutions(), digest.digest(), positions.count())
+    })
+}
+
+#[test]
+fn same_solutions_on_any_number_of_threads() {
+    for steno in ["~~~x", "NN~~x+", "PPN~Qx#"] {
+        let one = solve_on(1, steno, false);
+        assert!(one.0 > 0, "{} has no solutions", steno);
+        for threads in [2, 4, 7] {
+            assert_eq!(solve_on(threads, steno, false), one, "{} on {} threads", steno, threads);
+        }
+    }
+}
+
+#[test]
+fn two_stage_finds_the_same_solutions() {
+    for steno in ["~~~x", "PPN~Qx#"] {
+        let one = solve_on(1, steno, false);
+        assert_eq!(solve_on(1, steno, true), one, "{} in two stages", steno);
+        assert_eq!(solve_on(4, steno, true), one, "{} in two stages on 4 threads", steno);
+    }
+}
+
+#[test]
+fn repro_check_passes() {
+    let output = Command::new(env!("CARGO_BIN_EXE_steno_solver")).args(["--repro-check", "--threads", "4", "~~~x"]).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Repro check passed"), "{}", stdout);
+}
@@ -0,0 +1,69 @@
+use chess::Board;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::str::FromStr;
+use steno_solver::{count_solutions, parse_steno_string, solve_two_stage, solve_with_callback, SolutionHook, SolveOptions};
+
+// With --limit the hook cancels the search, and the second stage's groups
+// left after that are never visited.
+#[test]
+fn two_stage_stopped_by_a_limit() {
+    let found = AtomicU64::new(0);
+    let options = SolveOptions {
+        print_solutions: false,
+        on_solution: Some(SolutionHook::new(|_| ControlFlow::Break(()))),
+        ..SolveOptions::default()
+    };
+    let stats = solve_two_stage(Board::default(), &parse_steno_string("~~~x").unwrap(), 2, &options, &|_| {
+        found.fetch_add(1, Ordering::Relaxed);
+    });
+    assert_eq!(found.into_inner(), 1);
+    // Far fewer than the 206604 nodes of a full four-ply search.
+    assert!(stats.nodes_visited < 10_000, "{} nodes", stats.nodes_visited);
+}
+
+fn count(fen: &str, steno: &str) -> u64 {
+    count_solutions(Board::from_str(fen).unwrap(), 0, &parse_steno_string(steno).unwrap(), None)
+}
+
+const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+// The perft test position with castling, en passant and pins for both sides.
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+// The published perft breakdowns: captures count en passant, which only `%`
+// picks out.
+#[test]
+fn counts_match_perft() {
+    assert_eq!(count(START, "~~~~"), 197_281);
+    assert_eq!(count(START, "~~~x"), 1_576);
+    assert_eq!(count(START, "~~~+"), 469);
+    assert_eq!(count(START, "~~~#"), 8);
+    assert_eq!(count(START, "~~~~%"), 258);
+    assert_eq!(count(KIWIPETE, "x"), 8);
+    assert_eq!(count(KIWIPETE, "O"), 2);
+    assert_eq!(count(KIWIPETE, "~~"), 2_039);
+    assert_eq!(count(KIWIPETE, "~x"), 351);
+    assert_eq!(count(KIWIPETE, "~%"), 1);
+    assert_eq!(count(KIWIPETE, "~O"), 91);
+    assert_eq!(count(KIWIPETE, "~+"), 3);
+    assert_eq!(count(KIWIPETE, "~~x"), 17_102);
+    assert_eq!(count(KIWIPETE, "~~%"), 45);
+    assert_eq!(count(KIWIPETE, "~~O"), 3_162);
+    assert_eq!(count(KIWIPETE, "~~+"), 993);
+    assert_eq!(count(KIWIPETE, "~~#"), 1);
+}
+
+// Solutions come out whole, and the search visits each node of the tree
+// once, the leaves included.
+#[test]
+fn solutions_and_nodes() {
+    let options = SolveOptions { print_solutions: false, ..SolveOptions::default() };
+    let solutions = std::sync::Mutex::new(Vec::new());
+    let stats = solve_with_callback(Board::default(), &parse_steno_string("~~xX").unwrap(), &options, &|line| solutions.lock().unwrap().push(line.to_vec()));
+    let solutions = solutions.into_inner().unwrap();
+    assert_eq!(solutions.len(), 23);
+    assert!(solutions.iter().all(|line| line.len() == 4));
+    assert_eq!(stats.solutions, 23);
+    let stats = solve_with_callback(Board::default(), &parse_steno_string("~~~x").unwrap(), &options, &|_| {});
+    assert_eq!(stats.nodes_visited, 1 + 20 + 400 + 8_902 + 197_281);
+}